@@ -1,29 +1,184 @@
-mod codegen;
-mod evaluator;
+mod analysis;
+mod class;
+pub(crate) mod codegen;
+mod dfa;
+mod error;
+pub(crate) mod evaluator;
 mod parser;
+mod regex;
+mod rust_codegen;
+#[cfg(feature = "serde")]
+mod ser_ast;
+mod set;
+#[cfg(feature = "unicode")]
+mod unicode;
+#[cfg(feature = "wasm")]
+mod wasm;
 
-use crate::helper::DynError;
 use std::fmt::{self, Display};
 
-#[derive(Debug)]
+pub use class::{CharClass, CharMatcher, NamedMatcher};
+pub use codegen::{estimate_code_size, get_code, CodeGenError};
+pub use dfa::ByteDfa;
+pub use error::RegexError;
+pub use evaluator::{validate_program, Captures, EvalError, Match};
+pub use parser::{escape, parse, parse_all, ParseError, AST};
+pub use regex::{
+    Greediness, MatchResult, Matches, Regex, RegexBuilder, Stats, Syntax, DEFAULT_SIZE_LIMIT,
+};
+pub use rust_codegen::codegen_rust;
+#[cfg(feature = "serde")]
+pub use ser_ast::{SerAst, SerAstError, SerNode};
+pub use set::RegexSet;
+#[cfg(feature = "wasm")]
+pub use wasm::match_json;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Instruction {
     Char(char),
+    CharCI(char),       // 大文字と小文字を区別しない文字
+    Class(CharClass),   // 文字クラス
+    CharNot(char),      // [^x]: 指定した文字以外の1文字
+    Assert(AssertKind), // 文字を消費しない位置に関する条件
     Match,
+    MatchN(usize), // RegexSetで，指定した番号の正規表現のマッチ
     Jump(usize),
     Split(usize, usize),
+    SplitN(Vec<usize>), // 先頭から順に優先されるN方向の分岐
+    Save(usize),
+}
+
+/// `Instruction::Assert` が表す，文字を消費しない位置に関する条件の種類
+///
+/// 新しいアンカーは，この列挙型に種類を追加し `holds` で判定を定義すれば，
+/// 命令列を扱う各所では `Instruction::Assert` としてまとめて扱える。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssertKind {
+    Start,        // ^: 文字列の先頭
+    End,          // $ (strict_end): 文字列の末尾
+    EndNewline,   // $: 文字列の末尾，または末尾の改行の直前
+    LineStart,    // (?m)^: 行頭
+    LineEnd,      // (?m)$: 行末
+    PrevEnd,      // \G: 直前のマッチの終了位置
+    WordBoundary, // \b: 単語の境界
+}
+
+impl AssertKind {
+    /// 現在の位置で条件が成り立つかを返す
+    ///
+    /// prevは現在の位置の直前の文字，nextは直後の文字，beyondはnextのさらに次の文字で，
+    /// 文字列の先頭や末尾ではNoneとなる。
+    /// \G は直前のマッチがないものとして，^ と同様に文字列の先頭で成り立つ。
+    /// \b は前後の一方のみが単語構成文字 ([[:word:]]) の場合に成り立つ。
+    pub fn holds(&self, prev: Option<char>, next: Option<char>, beyond: Option<char>) -> bool {
+        match self {
+            AssertKind::Start | AssertKind::PrevEnd => prev.is_none(),
+            AssertKind::End => next.is_none(),
+            AssertKind::EndNewline => next.is_none() || (next == Some('\n') && beyond.is_none()),
+            AssertKind::LineStart => prev.is_none_or(|c| c == '\n'),
+            AssertKind::LineEnd => next.is_none_or(|c| c == '\n'),
+            AssertKind::WordBoundary => is_word_char(prev) != is_word_char(next),
+        }
+    }
+}
+
+impl Display for AssertKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssertKind::Start => write!(f, "start"),
+            AssertKind::End => write!(f, "end"),
+            AssertKind::EndNewline => write!(f, "end_newline"),
+            AssertKind::LineStart => write!(f, "line_start"),
+            AssertKind::LineEnd => write!(f, "line_end"),
+            AssertKind::PrevEnd => write!(f, "prev_end"),
+            AssertKind::WordBoundary => write!(f, "word_boundary"),
+        }
+    }
+}
+
+impl Instruction {
+    /// アドレスpcにあるこの命令の実行後に遷移しうる命令のアドレスを返す
+    ///
+    /// Splitの場合は優先される分岐が先に並ぶ。Matchは遷移先を持たない。
+    pub fn successors(&self, pc: usize) -> Vec<usize> {
+        match self {
+            Instruction::Char(_)
+            | Instruction::CharCI(_)
+            | Instruction::Class(_)
+            | Instruction::CharNot(_)
+            | Instruction::Assert(_)
+            | Instruction::Save(_) => vec![pc + 1],
+            Instruction::Match | Instruction::MatchN(_) => Vec::new(),
+            Instruction::Jump(addr) => vec![*addr],
+            Instruction::Split(addr1, addr2) => vec![*addr1, *addr2],
+            Instruction::SplitN(addrs) => addrs.clone(),
+        }
+    }
+
+    /// 文字を消費する命令が，文字cにマッチするかを返す
+    ///
+    /// 文字を消費しない命令の場合は常にfalseを返す
+    pub fn matches_char(&self, c: char) -> bool {
+        match self {
+            Instruction::Char(x) => *x == c,
+            Instruction::CharCI(x) => eq_ignore_case(*x, c),
+            Instruction::Class(class) => class.matches(c),
+            Instruction::CharNot(x) => *x != c,
+            _ => false,
+        }
+    }
+
+    /// 位置に関する条件を表す命令が，現在の位置で成り立つかを返す
+    ///
+    /// 判定は `AssertKind::holds` による。
+    /// 位置に関する条件を表す命令でない場合は常にfalseを返す
+    pub fn matches_position(
+        &self,
+        prev: Option<char>,
+        next: Option<char>,
+        beyond: Option<char>,
+    ) -> bool {
+        match self {
+            Instruction::Assert(kind) => kind.holds(prev, next, beyond),
+            _ => false,
+        }
+    }
 }
 
 impl Display for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Instruction::Char(c) => write!(f, "char {}", c),
+            Instruction::CharCI(c) => write!(f, "char_ci {}", c),
+            Instruction::Class(class) => write!(f, "class {}", class),
+            Instruction::CharNot(c) => write!(f, "char_not {}", c),
+            Instruction::Assert(kind) => write!(f, "assert_{}", kind),
             Instruction::Match => write!(f, "match"),
+            Instruction::MatchN(id) => write!(f, "match_n {}", id),
             Instruction::Jump(addr) => write!(f, "jump {:>04}", addr),
             Instruction::Split(addr1, addr2) => write!(f, "split {:>04}, {:>04}", addr1, addr2),
+            Instruction::SplitN(addrs) => {
+                let addrs = addrs
+                    .iter()
+                    .map(|a| format!("{:>04}", a))
+                    .collect::<Vec<_>>();
+                write!(f, "split_n {}", addrs.join(", "))
+            }
+            Instruction::Save(slot) => write!(f, "save {}", slot),
         }
     }
 }
 
+/// 大文字と小文字を区別せずに2つの文字を比較する
+fn eq_ignore_case(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+/// 文字が単語構成文字 ([[:word:]]，\w) かを返す。文字列の先頭や末尾 (None) はfalse
+fn is_word_char(c: Option<char>) -> bool {
+    c.is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 /// 正規表現をパースしてコード生成し、
 /// ASTと命令列を標準出力に表示。
 ///
@@ -37,7 +192,7 @@ impl Display for Instruction {
 /// # 返り値
 ///
 /// 入力された正規表現にエラーがあったり、内部的な実装エラーがある場合はErrを返す。
-pub fn print(expr: &str) -> Result<(), DynError> {
+pub fn print(expr: &str) -> Result<(), RegexError> {
     println!("expr: {expr}");
     let ast = parser::parse(expr)?;
     println!("AST: {:?}", ast);
@@ -45,13 +200,70 @@ pub fn print(expr: &str) -> Result<(), DynError> {
     println!();
     println!("code:");
     let code = codegen::get_code(&ast)?;
-    for (n, c) in code.iter().enumerate() {
-        println!("{:>04}: {c}", n);
-    }
+    print!("{}", disassemble(&code));
 
     Ok(())
 }
 
+/// 正規表現をパースし，ASTを1行1ノードで字下げした木の形式の文字列に変換する
+///
+/// 子ノードは親ノードより2文字深く字下げされる。
+/// `{:?}` による1行の表示より，大きなパターンの構造を読み取りやすい。
+///
+/// # 利用例
+///
+/// ```
+/// let dump = regex_engine::dump_ast("ab+").unwrap();
+/// assert_eq!(dump, "Seq\n  Char 'a'\n  Plus\n    Char 'b'\n");
+/// ```
+pub fn dump_ast(expr: &str) -> Result<String, ParseError> {
+    let ast = parser::parse(expr)?;
+    let mut out = String::new();
+    write_ast(&ast, 0, &mut out);
+    Ok(out)
+}
+
+/// ASTをdepth段の字下げでoutに書き込む
+fn write_ast(ast: &AST, depth: usize, out: &mut String) {
+    let (label, children): (String, Vec<&AST>) = match ast {
+        AST::Char(c) => (format!("Char {c:?}"), vec![]),
+        AST::CharCI(c) => (format!("CharCI {c:?}"), vec![]),
+        AST::Class(class) => (format!("Class {class}"), vec![]),
+        AST::Start => ("Start".into(), vec![]),
+        AST::End => ("End".into(), vec![]),
+        AST::LineStart => ("LineStart".into(), vec![]),
+        AST::LineEnd => ("LineEnd".into(), vec![]),
+        AST::AnchorPrevEnd => ("AnchorPrevEnd".into(), vec![]),
+        AST::WordBoundary => ("WordBoundary".into(), vec![]),
+        AST::Plus(e) => ("Plus".into(), vec![e]),
+        AST::Star(e) => ("Star".into(), vec![e]),
+        AST::Question(e) => ("Question".into(), vec![e]),
+        AST::Or(e1, e2) => ("Or".into(), vec![e1, e2]),
+        AST::Seq(v) => ("Seq".into(), v.iter().collect()),
+        AST::Capture(n, e) => (format!("Capture {n}"), vec![e]),
+        AST::Lazy(e) => ("Lazy".into(), vec![e]),
+        AST::Repeat(e, min, max) => {
+            let max = max.map_or(String::new(), |m| m.to_string());
+            (format!("Repeat {{{min},{max}}}"), vec![e])
+        }
+    };
+
+    out.push_str(&"  ".repeat(depth));
+    out.push_str(&label);
+    out.push('\n');
+    for e in children {
+        write_ast(e, depth + 1, out);
+    }
+}
+
+/// 命令列を "0000: char a" のような1行1命令のテキストに変換する
+fn disassemble(code: &[Instruction]) -> String {
+    code.iter()
+        .enumerate()
+        .map(|(n, c)| format!("{:>04}: {c}\n", n))
+        .collect()
+}
+
 /// 正規表現と文字列をマッチング
 ///
 /// # 利用例
@@ -63,7 +275,7 @@ pub fn print(expr: &str) -> Result<(), DynError> {
 ///
 /// # Arguments
 ///
-/// expr: 正規表現の文字列, line: マッチング対象の文字列
+/// expr: 正規表現の文字列, line: マッチング対象の文字列 (`&str` の他に `String` なども渡せる)
 /// is_depth: 深さ優先でマッチングするかどうか, falseなら幅優先でマッチング
 ///
 ///
@@ -73,9 +285,13 @@ pub fn print(expr: &str) -> Result<(), DynError> {
 /// マッチングに失敗した場合は `Ok(false)`を返す
 ///
 /// 入力された正規表現にエラーがあったり，内部的な実装エラーがある場合は，Errを返す
-pub fn do_matching(expr: &str, line: &str, is_depth: bool) -> Result<bool, DynError> {
-    let ast = parser::parse(expr)?;
+pub fn do_matching(
+    expr: impl AsRef<str>,
+    line: impl AsRef<str>,
+    is_depth: bool,
+) -> Result<bool, RegexError> {
+    let ast = parser::parse(expr.as_ref())?;
     let code = codegen::get_code(&ast)?;
-    let line = line.chars().collect::<Vec<char>>();
+    let line = line.as_ref().chars().collect::<Vec<char>>();
     Ok(evaluator::eval(&code, &line, is_depth)?)
 }
@@ -0,0 +1,394 @@
+//! analysis.rs analyzes AST to extract properties used for optimization.
+
+use super::{class::CharClass, parser::AST};
+
+/// 必須リテラル解析の結果
+#[derive(Debug, Default)]
+struct Literals {
+    exact: Option<String>, // 式全体が固定文字列の場合，その文字列
+    prefix: String,        // マッチが必ずこの文字列で始まる
+    suffix: String,        // マッチが必ずこの文字列で終わる
+    longest: String,       // マッチに必ず含まれる最長の連続した固定文字列
+}
+
+impl Literals {
+    fn exact(s: String) -> Self {
+        Literals {
+            exact: Some(s.clone()),
+            prefix: s.clone(),
+            suffix: s.clone(),
+            longest: s,
+        }
+    }
+}
+
+/// マッチした文字列に必ず含まれる連続した固定文字列のうち最長のものを返す
+///
+/// たとえば，ab+c の場合は "ab" を返す (a の直後には必ず b が続くため)。
+/// 必須の固定文字列がない場合はNoneを返す。
+pub fn required_literal(ast: &AST) -> Option<String> {
+    let longest = literals(ast).longest;
+    if longest.is_empty() {
+        None
+    } else {
+        Some(longest)
+    }
+}
+
+/// 正規表現が任意の文字列にマッチするかを返す
+///
+/// 空文字列にマッチできる式は，文字列の先頭で必ずマッチする。
+/// ただし，^ や $ を経由する空マッチは位置によって失敗しうるため，ここでは考慮しない。
+/// たとえば，a* はtrueとなるが，^a*$ はfalseとなる。
+pub fn always_matches(ast: &AST) -> bool {
+    match ast {
+        AST::Star(_) | AST::Question(_) => true,
+        AST::Plus(e) | AST::Capture(_, e) | AST::Lazy(e) => always_matches(e),
+        AST::Repeat(e, min, _) => *min == 0 || always_matches(e),
+        AST::Or(e1, e2) => always_matches(e1) || always_matches(e2),
+        AST::Seq(v) => v.iter().all(always_matches),
+        AST::Char(_)
+        | AST::CharCI(_)
+        | AST::Class(_)
+        | AST::Start
+        | AST::End
+        | AST::LineStart
+        | AST::LineEnd
+        | AST::WordBoundary
+        | AST::AnchorPrevEnd => false,
+    }
+}
+
+/// 正規表現がどの文字列にもマッチしないことが明らかかを返す
+///
+/// どの文字にもマッチしない文字クラスを，必ず通過しなければならない場合にtrueとなる。
+/// たとえば，a[^\0-\u{10FFFF}] はtrueとなるが，a[^\0-\u{10FFFF}]* はfalseとなる。
+pub fn never_matches(ast: &AST) -> bool {
+    match ast {
+        AST::Class(c) => c.is_empty(),
+        AST::Plus(e) | AST::Capture(_, e) | AST::Lazy(e) => never_matches(e),
+        AST::Repeat(e, min, _) => *min > 0 && never_matches(e),
+        AST::Or(e1, e2) => never_matches(e1) && never_matches(e2),
+        AST::Seq(v) => v.iter().any(never_matches),
+        AST::Star(_)
+        | AST::Question(_)
+        | AST::Char(_)
+        | AST::CharCI(_)
+        | AST::Start
+        | AST::End
+        | AST::LineStart
+        | AST::LineEnd
+        | AST::WordBoundary
+        | AST::AnchorPrevEnd => false,
+    }
+}
+
+/// どのようにマッチしても，マッチの先頭が必ず同じ1文字になる場合に，その文字を返す
+///
+/// たとえば，abc や a+b|ac は Some('a') となるが，a?b や (?i)abc はNoneとなる。
+/// 判定できない場合もNoneを返す。
+pub fn first_char(ast: &AST) -> Option<char> {
+    match ast {
+        AST::Char(c) => Some(*c),
+        AST::Plus(e) | AST::Capture(_, e) | AST::Lazy(e) => first_char(e),
+        AST::Repeat(e, min, _) if *min > 0 => first_char(e),
+        AST::Or(e1, e2) => first_char(e1).filter(|c| first_char(e2) == Some(*c)),
+        AST::Seq(v) => v.first().and_then(first_char),
+        AST::Repeat(..)
+        | AST::Star(_)
+        | AST::Question(_)
+        | AST::CharCI(_)
+        | AST::Class(_)
+        | AST::Start
+        | AST::End
+        | AST::LineStart
+        | AST::LineEnd
+        | AST::WordBoundary
+        | AST::AnchorPrevEnd => None,
+    }
+}
+
+/// どのようにマッチしても，必ず ^ から始まるかを判定する
+///
+/// たとえば，^abc や ^a|^b はtrueとなるが，^a|b や (^a)? はfalseとなる。
+pub fn anchored_start(ast: &AST) -> bool {
+    match ast {
+        AST::Start => true,
+        AST::Plus(e) | AST::Capture(_, e) | AST::Lazy(e) => anchored_start(e),
+        AST::Repeat(e, min, _) => *min > 0 && anchored_start(e),
+        AST::Or(e1, e2) => anchored_start(e1) && anchored_start(e2),
+        AST::Seq(v) => v.first().is_some_and(anchored_start),
+        AST::Star(_)
+        | AST::Question(_)
+        | AST::Char(_)
+        | AST::CharCI(_)
+        | AST::Class(_)
+        | AST::End
+        | AST::LineStart
+        | AST::LineEnd
+        | AST::WordBoundary
+        | AST::AnchorPrevEnd => false,
+    }
+}
+
+/// どのようにマッチしても，必ず $ で終わるかを判定する
+///
+/// たとえば，abc$ や a$|b$ はtrueとなるが，a$|b はfalseとなる。
+pub fn anchored_end(ast: &AST) -> bool {
+    match ast {
+        AST::End => true,
+        AST::Plus(e) | AST::Capture(_, e) | AST::Lazy(e) => anchored_end(e),
+        AST::Repeat(e, min, _) => *min > 0 && anchored_end(e),
+        AST::Or(e1, e2) => anchored_end(e1) && anchored_end(e2),
+        AST::Seq(v) => v.last().is_some_and(anchored_end),
+        AST::Star(_)
+        | AST::Question(_)
+        | AST::Char(_)
+        | AST::CharCI(_)
+        | AST::Class(_)
+        | AST::Start
+        | AST::LineStart
+        | AST::LineEnd
+        | AST::WordBoundary
+        | AST::AnchorPrevEnd => false,
+    }
+}
+
+/// マッチする文字列の長さ (文字数) の範囲を (最小, 最大) で返す
+///
+/// 上限のない繰り返し (* や +) を含む場合，最大はNoneとなる。
+/// たとえば，ab? は (1, Some(2))，a* は (0, None) となる。
+pub fn len_range(ast: &AST) -> (usize, Option<usize>) {
+    // 上限なく繰り返しても，長さ0の式の最大は0のまま
+    let unbounded = |max: Option<usize>| if max == Some(0) { Some(0) } else { None };
+
+    match ast {
+        AST::Char(_) | AST::CharCI(_) | AST::Class(_) => (1, Some(1)),
+        AST::Start
+        | AST::End
+        | AST::LineStart
+        | AST::LineEnd
+        | AST::WordBoundary
+        | AST::AnchorPrevEnd => (0, Some(0)),
+        AST::Plus(e) => {
+            let (min, max) = len_range(e);
+            (min, unbounded(max))
+        }
+        AST::Star(e) => (0, unbounded(len_range(e).1)),
+        AST::Question(e) => (0, len_range(e).1),
+        AST::Or(e1, e2) => {
+            let (min1, max1) = len_range(e1);
+            let (min2, max2) = len_range(e2);
+            (min1.min(min2), max1.zip(max2).map(|(m1, m2)| m1.max(m2)))
+        }
+        AST::Seq(v) => v
+            .iter()
+            .map(len_range)
+            .fold((0, Some(0)), |(min, max), (n, m)| {
+                (
+                    min.saturating_add(n),
+                    max.zip(m).and_then(|(a, b)| a.checked_add(b)),
+                )
+            }),
+        AST::Capture(_, e) | AST::Lazy(e) => len_range(e),
+        AST::Repeat(e, min, max) => {
+            let (n, m) = len_range(e);
+            let max = match max {
+                Some(max) => m.and_then(|m| m.checked_mul(*max)),
+                None => unbounded(m),
+            };
+            (n.saturating_mul(*min), max)
+        }
+    }
+}
+
+/// 正規表現の問題点を警告メッセージとして返す
+///
+/// 現在は，選択肢のうち手前の選択肢と同一の選択肢 (a|a) や，
+/// 手前の選択肢に包含される1文字の選択肢 ([a-z]|b) を検出する。
+/// これらの選択肢は，マッチングの結果に影響しない。
+/// また，どの文字列にもマッチしない正規表現も検出する。
+pub fn lint(ast: &AST) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if never_matches(ast) {
+        warnings.push("pattern never matches: it requires a class matching no character".into());
+    }
+    lint_ast(ast, &mut warnings);
+    warnings
+}
+
+fn lint_ast(ast: &AST, warnings: &mut Vec<String>) {
+    match ast {
+        AST::Or(_, _) => {
+            // 右結合で入れ子になったOrを選択肢の列に展開する
+            let mut branches = Vec::new();
+            let mut e = ast;
+            while let AST::Or(e1, e2) = e {
+                branches.push(&**e1);
+                e = e2;
+            }
+            branches.push(e);
+
+            for (j, b) in branches.iter().enumerate() {
+                let b = single(b);
+                for (i, a) in branches[..j].iter().enumerate() {
+                    let a = single(a);
+                    if a == b {
+                        warnings.push(format!(
+                            "duplicate alternative: alternative {} is the same as alternative {}",
+                            j + 1,
+                            i + 1
+                        ));
+                        break;
+                    }
+                    if subsumes(a, b) {
+                        warnings.push(format!(
+                            "unreachable alternative: alternative {} is subsumed by alternative {}",
+                            j + 1,
+                            i + 1
+                        ));
+                        break;
+                    }
+                }
+                lint_ast(b, warnings);
+            }
+        }
+        AST::Plus(e) | AST::Star(e) | AST::Question(e) | AST::Capture(_, e) | AST::Lazy(e) => {
+            lint_ast(e, warnings)
+        }
+        AST::Repeat(e, _, _) => lint_ast(e, warnings),
+        AST::Seq(v) => v.iter().for_each(|e| lint_ast(e, warnings)),
+        AST::Char(_)
+        | AST::CharCI(_)
+        | AST::Class(_)
+        | AST::Start
+        | AST::End
+        | AST::LineStart
+        | AST::LineEnd
+        | AST::WordBoundary
+        | AST::AnchorPrevEnd => (),
+    }
+}
+
+/// 要素が1つだけのSeqを，その要素とみなす
+fn single(ast: &AST) -> &AST {
+    match ast {
+        AST::Seq(v) if v.len() == 1 => &v[0],
+        _ => ast,
+    }
+}
+
+/// 1文字にマッチする式aが，式bのマッチする全ての文字にマッチするかを返す
+///
+/// 判定できない場合はfalseを返す
+fn subsumes(a: &AST, b: &AST) -> bool {
+    match (a, b) {
+        (AST::CharCI(x), AST::Char(y)) => x.to_lowercase().eq(y.to_lowercase()),
+        (AST::Class(class), AST::Char(y)) => class.matches(*y),
+        (AST::Class(ca), AST::Class(cb)) => {
+            // 否定や大文字と小文字の区別，利用者定義の判定がない場合のみ範囲の包含で判定する
+            let plain = |c: &CharClass| !c.negated && !c.case_insensitive && c.matchers.is_empty();
+            plain(ca)
+                && plain(cb)
+                && cb
+                    .ranges
+                    .iter()
+                    .all(|&(lo, hi)| ca.ranges.iter().any(|&(alo, ahi)| alo <= lo && hi <= ahi))
+        }
+        _ => false,
+    }
+}
+
+fn literals(ast: &AST) -> Literals {
+    match ast {
+        AST::Char(c) => Literals::exact(c.to_string()),
+        AST::Plus(e) => {
+            // 少なくとも1回はeにマッチするが，全体は固定文字列ではない
+            let Literals {
+                prefix,
+                suffix,
+                longest,
+                ..
+            } = literals(e);
+            Literals {
+                exact: None,
+                prefix,
+                suffix,
+                longest,
+            }
+        }
+        AST::Capture(_, e) | AST::Lazy(e) => literals(e),
+        // ^ や $ は文字を消費しないため，空の固定文字列とみなせる
+        AST::Start
+        | AST::End
+        | AST::LineStart
+        | AST::LineEnd
+        | AST::WordBoundary
+        | AST::AnchorPrevEnd => Literals::exact(String::new()),
+        AST::Repeat(e, min, max) => {
+            let lit = literals(e);
+            match (&lit.exact, max) {
+                // e{n} でeが固定文字列なら，全体もその繰り返しの固定文字列
+                (Some(s), Some(max)) if min == max => Literals::exact(s.repeat(*min)),
+                // 少なくとも1回はeにマッチする
+                _ if *min > 0 => Literals { exact: None, ..lit },
+                _ => Literals::default(),
+            }
+        }
+        AST::Seq(v) => literals_seq(v),
+        // 0回の繰り返しや選択肢があるため，必須の文字列はない
+        // 大文字と小文字を区別しない文字や文字クラスは，入力中の表記が定まらないため固定文字列とみなさない
+        AST::CharCI(_) | AST::Class(_) | AST::Star(_) | AST::Question(_) | AST::Or(_, _) => {
+            Literals::default()
+        }
+    }
+}
+
+/// Seqの各要素の解析結果を連結する
+///
+/// 隣接する要素の末尾と先頭の固定文字列は連続しているため，
+/// それらを繋げたものも必須の固定文字列となる
+fn literals_seq(v: &[AST]) -> Literals {
+    let mut exact = Some(String::new()); // ここまでの要素が全て固定文字列の場合の連結結果
+    let mut prefix = String::new();
+    let mut run = String::new(); // 現在連続している固定文字列
+    let mut longest = String::new();
+
+    for e in v {
+        let lit = literals(e);
+
+        let mut joined = run.clone();
+        joined.push_str(&lit.prefix);
+        for s in [joined, lit.longest] {
+            if s.chars().count() > longest.chars().count() {
+                longest = s;
+            }
+        }
+
+        if let Some(s) = &lit.exact {
+            run.push_str(s);
+            if let Some(ex) = &mut exact {
+                ex.push_str(s);
+            }
+        } else {
+            if let Some(ex) = exact.take() {
+                prefix = ex + &lit.prefix;
+            }
+            run = lit.suffix;
+        }
+    }
+
+    if run.chars().count() > longest.chars().count() {
+        longest = run.clone();
+    }
+
+    match exact {
+        Some(s) => Literals::exact(s),
+        None => Literals {
+            exact: None,
+            prefix,
+            suffix: run,
+            longest,
+        },
+    }
+}
@@ -0,0 +1,165 @@
+//! class.rs provides `CharClass`, a set of characters written as a bracket expression.
+
+use std::{
+    fmt::{self, Debug, Display},
+    sync::Arc,
+};
+
+/// 文字クラス中で使える，利用者が定義する文字の判定
+///
+/// `RegexBuilder::char_matcher` で名前を付けて登録すると，
+/// パターン中の [[:name:]] のようにPOSIX文字クラスと同じ記法で参照できる。
+/// `Fn(char) -> bool` を満たすクロージャは全て `CharMatcher` を実装する。
+pub trait CharMatcher: Send + Sync {
+    /// 文字cにマッチするかを返す
+    fn matches(&self, c: char) -> bool;
+}
+
+impl<F> CharMatcher for F
+where
+    F: Fn(char) -> bool + Send + Sync,
+{
+    fn matches(&self, c: char) -> bool {
+        self(c)
+    }
+}
+
+/// 名前付きの `CharMatcher`
+///
+/// 同じ名前で同じ判定を共有している場合に等しいとみなす
+#[derive(Clone)]
+pub struct NamedMatcher {
+    pub name: String,
+    pub matcher: Arc<dyn CharMatcher>,
+}
+
+impl Debug for NamedMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NamedMatcher")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for NamedMatcher {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && Arc::ptr_eq(&self.matcher, &other.matcher)
+    }
+}
+
+impl Eq for NamedMatcher {}
+
+/// [a-z] のようなブラケット表現で指定される文字クラス
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CharClass {
+    pub ranges: Vec<(char, char)>, // クラスに含まれる文字の範囲 (両端を含む)
+    pub negated: bool,             // [^...] のように否定されているか
+    pub case_insensitive: bool,    // 大文字と小文字を区別しないか
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub matchers: Vec<NamedMatcher>, // 利用者が定義した文字の判定 (シリアライズされない)
+}
+
+impl CharClass {
+    /// 文字cがこのクラスにマッチするかを返す
+    pub fn matches(&self, c: char) -> bool {
+        let contains = |c: char| {
+            self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi)
+                || self.matchers.iter().any(|m| m.matcher.matches(c))
+        };
+        let found = contains(c)
+            || (self.case_insensitive && c.to_lowercase().chain(c.to_uppercase()).any(contains));
+        found != self.negated
+    }
+
+    /// どの文字にもマッチしないことが明らかな場合にtrueを返す
+    ///
+    /// [^\0-\u{10FFFF}] のように，否定されたクラスの範囲が全ての文字を覆う場合などが該当する。
+    /// 利用者が定義した判定を含む場合は判定できないためfalseを返す。
+    pub fn is_empty(&self) -> bool {
+        if !self.matchers.is_empty() {
+            return false;
+        }
+        if !self.negated {
+            return self.ranges.is_empty();
+        }
+
+        // 範囲を始点順に並べ，'\0' から char::MAX まで隙間なく覆われているかを調べる
+        let mut ranges = self.ranges.clone();
+        ranges.sort_unstable();
+        let mut next = Some('\0'); // まだ覆われていない最小の文字
+        for (lo, hi) in ranges {
+            match next {
+                Some(c) if lo > c => return false,
+                Some(c) if hi >= c => next = char_after(hi),
+                _ => (),
+            }
+        }
+        next.is_none()
+    }
+}
+
+/// cの次の文字を返す。サロゲートの範囲は飛ばし，char::MAXの次はNoneとなる
+fn char_after(c: char) -> Option<char> {
+    match c {
+        '\u{d7ff}' => Some('\u{e000}'),
+        _ => char::from_u32(c as u32 + 1),
+    }
+}
+
+impl Display for CharClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // ブラケット表現中で特別な意味を持つ文字はエスケープして表示する
+        let write_char = |f: &mut fmt::Formatter<'_>, c: char| match c {
+            '\\' | ']' | '[' | '^' | '-' => write!(f, "\\{}", c),
+            _ if c.is_control() => write!(f, "{}", c.escape_default()),
+            _ => write!(f, "{}", c),
+        };
+
+        if self.case_insensitive {
+            write!(f, "(?i)")?;
+        }
+        write!(f, "[")?;
+        if self.negated {
+            write!(f, "^")?;
+        }
+        for &(lo, hi) in &self.ranges {
+            write_char(f, lo)?;
+            if lo != hi {
+                write!(f, "-")?;
+                write_char(f, hi)?;
+            }
+        }
+        for m in &self.matchers {
+            if m.name.starts_with('\\') {
+                write!(f, "{}", m.name)?; // \p{Greek} のようなUnicodeプロパティ
+            } else {
+                write!(f, "[:{}:]", m.name)?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+/// POSIX文字クラス名に対応する文字の範囲を返す
+///
+/// 対象はASCII文字のみ。未知の名前の場合はNoneを返す。
+pub(crate) fn posix_class(name: &str) -> Option<&'static [(char, char)]> {
+    let ranges: &'static [(char, char)] = match name {
+        "alpha" => &[('A', 'Z'), ('a', 'z')],
+        "digit" => &[('0', '9')],
+        "alnum" => &[('0', '9'), ('A', 'Z'), ('a', 'z')],
+        "upper" => &[('A', 'Z')],
+        "lower" => &[('a', 'z')],
+        "space" => &[('\t', '\r'), (' ', ' ')],
+        "blank" => &[('\t', '\t'), (' ', ' ')],
+        "punct" => &[('!', '/'), (':', '@'), ('[', '`'), ('{', '~')],
+        "xdigit" => &[('0', '9'), ('A', 'F'), ('a', 'f')],
+        "cntrl" => &[('\0', '\x1f'), ('\x7f', '\x7f')],
+        "print" => &[(' ', '~')],
+        "graph" => &[('!', '~')],
+        "word" => &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')],
+        _ => return None,
+    };
+    Some(ranges)
+}
@@ -1,4 +1,4 @@
-use super::{parser::AST, Instruction};
+use super::{class::CharClass, parser::AST, AssertKind, Instruction};
 use crate::helper::safe_add;
 use std::{
     error::Error,
@@ -11,6 +11,10 @@ pub enum CodeGenError {
     FailStar,
     FailOr,
     FailQuestion,
+    FailRepeat,
+    RepeatTooLarge,              // 命令数の見積もりが上限を超えた
+    InvalidFunctionName(String), // 生成するRustの関数名が識別子として不正
+    UnsupportedMatcher(String),  // Rustのソースコードとして生成できない文字の判定
 }
 
 impl Display for CodeGenError {
@@ -38,11 +42,29 @@ impl Generator {
     fn gen_expr(&mut self, ast: &AST) -> Result<(), CodeGenError> {
         match ast {
             AST::Char(c) => self.gen_char(*c)?,
+            AST::CharCI(c) => self.gen_char_ci(*c)?,
+            AST::Class(class) => self.gen_class(class)?,
+            AST::Start => self.gen_assert(AssertKind::Start)?,
+            AST::End => self.gen_assert(AssertKind::EndNewline)?,
+            AST::LineStart => self.gen_assert(AssertKind::LineStart)?,
+            AST::LineEnd => self.gen_assert(AssertKind::LineEnd)?,
+            AST::WordBoundary => self.gen_assert(AssertKind::WordBoundary)?,
+            AST::AnchorPrevEnd => self.gen_assert(AssertKind::PrevEnd)?,
             AST::Or(e1, e2) => self.gen_or(e1, e2)?,
-            AST::Plus(e) => self.gen_plus(e)?,
-            AST::Star(e) => self.gen_star(e)?,
-            AST::Question(e) => self.gen_question(e)?,
+            AST::Plus(e) => self.gen_plus(e, false)?,
+            AST::Star(e) => self.gen_star(e, false)?,
+            AST::Question(e) => self.gen_question(e, false)?,
             AST::Seq(v) => self.gen_seq(v)?,
+            AST::Capture(n, e) => self.gen_capture(*n, e)?,
+            AST::Repeat(e, min, max) => self.gen_repeat(e, *min, *max, false)?,
+            AST::Lazy(e) => match e.as_ref() {
+                AST::Plus(e) => self.gen_plus(e, true)?,
+                AST::Star(e) => self.gen_star(e, true)?,
+                AST::Question(e) => self.gen_question(e, true)?,
+                AST::Repeat(e, min, max) => self.gen_repeat(e, *min, *max, true)?,
+                // 繰り返し以外は優先順位を持たないので，そのまま生成する
+                e => self.gen_expr(e)?,
+            },
         }
 
         Ok(())
@@ -52,6 +74,16 @@ impl Generator {
         safe_add(&mut self.pc, &1, || CodeGenError::PCOverflow)
     }
 
+    /// 最短一致の繰り返しのために，addrにあるsplit命令の分岐の優先順位を入れ替える
+    fn swap_split(&mut self, addr: usize, err: CodeGenError) -> Result<(), CodeGenError> {
+        if let Some(Instruction::Split(l1, l2)) = self.insts.get_mut(addr) {
+            std::mem::swap(l1, l2);
+            Ok(())
+        } else {
+            Err(err)
+        }
+    }
+
     fn gen_seq(&mut self, exprs: &[AST]) -> Result<(), CodeGenError> {
         for e in exprs {
             self.gen_expr(e)?;
@@ -60,6 +92,82 @@ impl Generator {
         Ok(())
     }
 
+    /// キャプチャグループのコード生成器
+    ///
+    /// グループnの開始位置をスロット2n，終了位置をスロット2n+1に保存する
+    /// ```text
+    /// save 2n
+    /// eのコード
+    /// save 2n+1
+    /// ```
+    fn gen_capture(&mut self, n: usize, e: &AST) -> Result<(), CodeGenError> {
+        let slot = n.checked_mul(2).ok_or(CodeGenError::PCOverflow)?;
+        self.insts.push(Instruction::Save(slot));
+        self.inc_pc()?;
+
+        self.gen_expr(e)?;
+
+        self.insts.push(Instruction::Save(slot + 1));
+        self.inc_pc()?;
+        Ok(())
+    }
+
+    /// {n,m} のコード生成器
+    ///
+    /// eのコードをn回並べ，その後にm-n回分の省略可能なeのコードを入れ子にして生成する。
+    /// 上限がない場合は，n回の後にe*のコードを生成する。
+    /// ```text
+    ///     eのコード       ; n回
+    ///     split L1, L3   ; m-n回
+    /// L1: eのコード
+    ///     split L2, L3
+    /// L2: eのコード
+    /// L3:
+    /// ```
+    /// lazyがtrueの場合は，各splitの分岐の優先順位を入れ替えた最短一致の繰り返しとなる。
+    /// 手で組み立てたASTなどで，上限が下限より小さい場合は `CodeGenError::FailRepeat` を返す。
+    fn gen_repeat(
+        &mut self,
+        e: &AST,
+        min: usize,
+        max: Option<usize>,
+        lazy: bool,
+    ) -> Result<(), CodeGenError> {
+        if max.is_some_and(|max| max < min) {
+            return Err(CodeGenError::FailRepeat);
+        }
+        for _ in 0..min {
+            self.gen_expr(e)?;
+        }
+
+        let Some(max) = max else {
+            return self.gen_star(e, lazy);
+        };
+
+        // split命令のアドレス，L3は最後にまとめて設定する
+        let mut splits = Vec::new();
+        for _ in min..max {
+            splits.push(self.pc);
+            self.inc_pc()?;
+            self.insts.push(Instruction::Split(self.pc, 0));
+            self.gen_expr(e)?;
+        }
+
+        // L3の値を設定
+        for addr in splits {
+            if let Some(Instruction::Split(_, l3)) = self.insts.get_mut(addr) {
+                *l3 = self.pc;
+            } else {
+                return Err(CodeGenError::FailRepeat);
+            }
+            if lazy {
+                self.swap_split(addr, CodeGenError::FailRepeat)?;
+            }
+        }
+
+        Ok(())
+    }
+
     fn gen_char(&mut self, c: char) -> Result<(), CodeGenError> {
         let inst = Instruction::Char(c);
         self.insts.push(inst);
@@ -67,7 +175,45 @@ impl Generator {
         Ok(())
     }
 
-    fn gen_question(&mut self, e: &AST) -> Result<(), CodeGenError> {
+    fn gen_char_ci(&mut self, c: char) -> Result<(), CodeGenError> {
+        let inst = Instruction::CharCI(c);
+        self.insts.push(inst);
+        self.inc_pc()?;
+        Ok(())
+    }
+
+    /// 文字クラスのコード生成器
+    ///
+    /// [^x] のように1文字のみを否定するクラスは，より小さく高速なchar_not命令とする
+    fn gen_class(&mut self, class: &CharClass) -> Result<(), CodeGenError> {
+        let inst = match class.ranges[..] {
+            [(lo, hi)]
+                if lo == hi
+                    && class.negated
+                    && !class.case_insensitive
+                    && class.matchers.is_empty() =>
+            {
+                Instruction::CharNot(lo)
+            }
+            _ => Instruction::Class(class.clone()),
+        };
+        self.insts.push(inst);
+        self.inc_pc()?;
+        Ok(())
+    }
+
+    fn gen_assert(&mut self, kind: AssertKind) -> Result<(), CodeGenError> {
+        self.insts.push(Instruction::Assert(kind));
+        self.inc_pc()?;
+        Ok(())
+    }
+
+    /// e? のコードを生成する
+    ///
+    /// split L1, L2 は先に評価するL1 (eのコード) を優先するため，e にマッチする方を優先する
+    /// 最長一致 (greedy) となり，* や + と同じ意味になる。
+    /// lazyの場合は split L2, L1 としてeを飛ばす方を優先する (e??)。
+    fn gen_question(&mut self, e: &AST, lazy: bool) -> Result<(), CodeGenError> {
         // split L1, L2
         let split_addr = self.pc;
         self.inc_pc()?;
@@ -80,13 +226,16 @@ impl Generator {
         // L2の値を設定
         if let Some(Instruction::Split(_, l2)) = self.insts.get_mut(split_addr) {
             *l2 = self.pc;
-            Ok(())
         } else {
-            Err(CodeGenError::FailQuestion)
+            return Err(CodeGenError::FailQuestion);
+        }
+        if lazy {
+            self.swap_split(split_addr, CodeGenError::FailQuestion)?;
         }
+        Ok(())
     }
 
-    fn gen_star(&mut self, e: &AST) -> Result<(), CodeGenError> {
+    fn gen_star(&mut self, e: &AST, lazy: bool) -> Result<(), CodeGenError> {
         // L1: split L2, L3
         let l1 = self.pc;
         self.inc_pc()?;
@@ -104,20 +253,27 @@ impl Generator {
         // L3の値を設定
         if let Some(Instruction::Split(_, l3)) = self.insts.get_mut(l1) {
             *l3 = self.pc;
-            Ok(())
         } else {
-            Err(CodeGenError::FailStar)
+            return Err(CodeGenError::FailStar);
+        }
+        if lazy {
+            self.swap_split(l1, CodeGenError::FailStar)?;
         }
+        Ok(())
     }
 
-    fn gen_plus(&mut self, e: &AST) -> Result<(), CodeGenError> {
+    fn gen_plus(&mut self, e: &AST, lazy: bool) -> Result<(), CodeGenError> {
         // L1: eのコード
         let l1 = self.pc;
         self.gen_expr(e)?;
 
-        // split L1, L2
+        // split L1, L2 (最短一致の場合は split L2, L1)
         self.inc_pc()?;
-        let split = Instruction::Split(l1, self.pc); // self.pcがL2
+        let split = if lazy {
+            Instruction::Split(self.pc, l1)
+        } else {
+            Instruction::Split(l1, self.pc) // self.pcがL2
+        };
         self.insts.push(split);
 
         Ok(())
@@ -134,6 +290,18 @@ impl Generator {
     /// L3:
     /// ```
     fn gen_or(&mut self, e1: &AST, e2: &AST) -> Result<(), CodeGenError> {
+        if let AST::Or(_, _) = e2 {
+            // a|b|c のように3つ以上の選択肢がある場合は，1つのsplit_nにまとめる
+            let mut branches = vec![e1];
+            let mut e = e2;
+            while let AST::Or(e1, e2) = e {
+                branches.push(e1);
+                e = e2;
+            }
+            branches.push(e);
+            return self.gen_or_n(&branches);
+        }
+
         // split L1, L2
         let split_addr = self.pc;
         self.inc_pc()?;
@@ -165,10 +333,146 @@ impl Generator {
 
         Ok(())
     }
+
+    /// 3つ以上の選択肢からなるOR演算子のコード生成器
+    ///
+    /// 以下のようなコードを生成
+    /// ```text
+    ///     split_n L1, L2, ..., Ln
+    /// L1: e1のコード
+    ///     jump END
+    /// L2: e2のコード
+    ///     jump END
+    /// ...
+    /// Ln: enのコード
+    /// END:
+    /// ```
+    fn gen_or_n(&mut self, branches: &[&AST]) -> Result<(), CodeGenError> {
+        // split_n L1, L2, ..., Ln
+        let split_addr = self.pc;
+        self.inc_pc()?;
+        self.insts.push(Instruction::SplitN(Vec::new())); // 分岐先は後で設定
+
+        let mut targets = Vec::new();
+        let mut jumps = Vec::new();
+        for (i, e) in branches.iter().enumerate() {
+            // Li: eiのコード
+            targets.push(self.pc);
+            self.gen_expr(e)?;
+
+            // 最後の選択肢以外は jump END
+            if i + 1 < branches.len() {
+                jumps.push(self.pc);
+                self.insts.push(Instruction::Jump(0)); // ENDは仮に0と設定
+                self.inc_pc()?;
+            }
+        }
+
+        // ENDと分岐先のアドレスを設定
+        for addr in jumps {
+            if let Some(Instruction::Jump(end)) = self.insts.get_mut(addr) {
+                *end = self.pc;
+            } else {
+                return Err(CodeGenError::FailOr);
+            }
+        }
+        if let Some(Instruction::SplitN(v)) = self.insts.get_mut(split_addr) {
+            *v = targets;
+        } else {
+            return Err(CodeGenError::FailOr);
+        }
+
+        Ok(())
+    }
 }
 
+/// ASTから命令列を生成する
+///
+/// `Regex::from_ast` と異なり，オプションの適用や命令数の上限の確認は行わない
 pub fn get_code(ast: &AST) -> Result<Vec<Instruction>, CodeGenError> {
     let mut generator = Generator::default();
     generator.gen_code(ast)?;
     Ok(generator.insts)
 }
+
+/// 命令列がアドレスbaseから配置されるものとしてコード生成する
+///
+/// 複数の命令列を連結する場合に，JumpやSplitのアドレスを配置先に合わせるために使う
+pub fn get_code_at(ast: &AST, base: usize) -> Result<Vec<Instruction>, CodeGenError> {
+    let mut insts = get_code(ast)?;
+    for inst in insts.iter_mut() {
+        match inst {
+            Instruction::Jump(addr) => safe_add(addr, &base, || CodeGenError::PCOverflow)?,
+            Instruction::Split(addr1, addr2) => {
+                safe_add(addr1, &base, || CodeGenError::PCOverflow)?;
+                safe_add(addr2, &base, || CodeGenError::PCOverflow)?;
+            }
+            Instruction::SplitN(addrs) => {
+                for addr in addrs {
+                    safe_add(addr, &base, || CodeGenError::PCOverflow)?;
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(insts)
+}
+
+/// ASTをコード生成した場合の命令数を，命令列を生成せずに見積もる
+///
+/// 末尾のmatch命令を含み，`get_code` が返す命令列の長さと一致する。
+/// {n,m} は繰り返し回数分の命令数を掛け合わせ，(a{100}){100} のように入れ子になった場合も
+/// 各段の倍率を掛け合わせるため，`get_code` を呼ぶ前に巨大なパターンを拒否する判定に使える。
+/// 命令数がusizeに収まらない場合はusize::MAXを返す。
+///
+/// # 利用例
+///
+/// ```
+/// use regex_engine::{estimate_code_size, parse};
+/// let ast = parse("a{3}b").unwrap();
+/// assert_eq!(estimate_code_size(&ast), 5);
+/// ```
+pub fn estimate_code_size(ast: &AST) -> usize {
+    estimate_expr(ast).saturating_add(1) // match
+}
+
+fn estimate_expr(ast: &AST) -> usize {
+    match ast {
+        AST::Char(_)
+        | AST::CharCI(_)
+        | AST::Class(_)
+        | AST::Start
+        | AST::End
+        | AST::LineStart
+        | AST::LineEnd
+        | AST::WordBoundary
+        | AST::AnchorPrevEnd => 1,
+        AST::Or(e1, e2) => {
+            // gen_orと同様に，3つ以上の選択肢は1つのsplit_nにまとめられる
+            // 選択肢がn個の場合，分岐命令とjumpを合わせてn個の命令が追加される
+            let mut size = estimate_expr(e1).saturating_add(2);
+            let mut e = e2.as_ref();
+            while let AST::Or(e1, e2) = e {
+                size = size.saturating_add(estimate_expr(e1)).saturating_add(1);
+                e = e2;
+            }
+            size.saturating_add(estimate_expr(e))
+        }
+        AST::Plus(e) | AST::Question(e) => estimate_expr(e).saturating_add(1),
+        AST::Star(e) | AST::Capture(_, e) => estimate_expr(e).saturating_add(2),
+        AST::Lazy(e) => estimate_expr(e),
+        AST::Seq(v) => v
+            .iter()
+            .fold(0, |acc, e| acc.saturating_add(estimate_expr(e))),
+        AST::Repeat(e, min, max) => {
+            let size = estimate_expr(e);
+            let rest = match max {
+                Some(max) => size
+                    .saturating_add(1)
+                    .saturating_mul(max.saturating_sub(*min)),
+                None => size.saturating_add(2),
+            };
+            size.saturating_mul(*min).saturating_add(rest)
+        }
+    }
+}
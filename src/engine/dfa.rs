@@ -0,0 +1,222 @@
+//! dfa.rs provides `ByteDfa`, a dense DFA built from a program for byte inputs.
+
+use super::{AssertKind, Instruction};
+use std::collections::HashMap;
+
+/// `ByteDfa` の状態数の上限
+///
+/// 遷移表は 256 × 4バイト × 状態数 のメモリを使うため，上限では約4MBとなる
+pub const MAX_DFA_STATES: usize = 4096;
+
+/// バイト列を入力とする密な遷移表を持つDFA
+///
+/// 命令列をNFAとみなし，部分集合構成法で状態ごとに256通りのバイトに対する遷移を求めたもの。
+/// 各バイトは同じ値のコードポイントを持つ文字 (0x00 〜 0xff) として命令と照合されるため，
+/// ASCII文字の入力では文字単位のマッチングと同じ結果となる。
+/// 0x80以上のバイトはUTF-8として解釈されないので，非ASCII文字を含む正規表現には向かない。
+///
+/// メモリ使用量は 256 × 4バイト × 状態数 で，`memory_usage` で確認できる。
+///
+/// # 利用例
+///
+/// ```
+/// use regex_engine::Regex;
+/// let dfa = Regex::new("a[0-9]+b").unwrap().compile_byte_dfa().unwrap();
+/// assert!(dfa.is_match(b"xa12b"));
+/// assert!(!dfa.is_match(b"xab"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ByteDfa {
+    table: Vec<[u32; 256]>,           // 状態ごとのバイトに対する遷移先
+    accept: Vec<bool>,                // 状態に到達した時点でマッチしたか
+    accept_at_end: Vec<bool>,         // 入力の末尾でこの状態にいる場合にマッチするか ($ を考慮)
+    accept_before_newline: Vec<bool>, // 末尾の改行の直前でこの状態にいる場合にマッチするか
+    empty_match: bool,                // 空の入力にマッチするか
+}
+
+impl ByteDfa {
+    /// 命令列からDFAを構築する
+    ///
+    /// 状態数が `MAX_DFA_STATES` を超える場合や，
+    /// 直前の文字に依存する行頭・行末の条件 ((?m)^ や (?m)$) や単語の境界 (\b) を含む場合はNoneを返す
+    pub(crate) fn new(inst: &[Instruction]) -> Option<Self> {
+        if inst.iter().any(|i| {
+            matches!(
+                i,
+                Instruction::Assert(
+                    AssertKind::LineStart | AssertKind::LineEnd | AssertKind::WordBoundary
+                )
+            )
+        }) {
+            return None;
+        }
+
+        let start = closure(inst, vec![0], true, EndPos::Inner);
+        let restart = closure(inst, vec![0], false, EndPos::Inner); // 次の位置から始まるマッチ
+        let empty_match = closure(inst, vec![0], true, EndPos::End)
+            .iter()
+            .any(|pc| matches!(inst[*pc], Instruction::Match | Instruction::MatchN(_)));
+
+        let mut dfa = ByteDfa {
+            table: Vec::new(),
+            accept: Vec::new(),
+            accept_at_end: Vec::new(),
+            accept_before_newline: Vec::new(),
+            empty_match,
+        };
+        let mut ids = HashMap::new();
+        let mut sets = Vec::new();
+        dfa.add_state(inst, start, &mut ids, &mut sets);
+
+        let mut id = 0;
+        while id < sets.len() {
+            let mut row = [0; 256];
+            for (byte, next) in row.iter_mut().enumerate() {
+                let c = char::from(byte as u8);
+                let seeds = sets[id]
+                    .iter()
+                    .filter(|pc| inst[**pc].matches_char(c))
+                    .map(|pc| pc + 1)
+                    .collect();
+                let mut set = closure(inst, seeds, false, EndPos::Inner);
+                set.extend(&restart);
+                set.sort_unstable();
+                set.dedup();
+                *next = dfa.add_state(inst, set, &mut ids, &mut sets)?;
+            }
+            dfa.table.push(row);
+            id += 1;
+        }
+
+        Some(dfa)
+    }
+
+    /// 状態を追加して状態番号を返す。既に存在する状態の場合はその番号を返す
+    fn add_state(
+        &mut self,
+        inst: &[Instruction],
+        set: Vec<usize>,
+        ids: &mut HashMap<Vec<usize>, u32>,
+        sets: &mut Vec<Vec<usize>>,
+    ) -> Option<u32> {
+        if let Some(id) = ids.get(&set) {
+            return Some(*id);
+        }
+        if sets.len() >= MAX_DFA_STATES {
+            return None;
+        }
+
+        let is_match = |pcs: &[usize]| {
+            pcs.iter()
+                .any(|pc| matches!(inst[*pc], Instruction::Match | Instruction::MatchN(_)))
+        };
+        self.accept.push(is_match(&set));
+        self.accept_at_end
+            .push(is_match(&closure(inst, set.clone(), false, EndPos::End)));
+        self.accept_before_newline.push(is_match(&closure(
+            inst,
+            set.clone(),
+            false,
+            EndPos::FinalNewline,
+        )));
+
+        let id = sets.len() as u32;
+        ids.insert(set.clone(), id);
+        sets.push(set);
+        Some(id)
+    }
+
+    /// バイト列中のいずれかの位置で正規表現がマッチするかを判定
+    pub fn is_match(&self, input: &[u8]) -> bool {
+        if input.is_empty() {
+            return self.empty_match;
+        }
+
+        let mut state = 0;
+        if self.accept[state] {
+            return true;
+        }
+        for (i, b) in input.iter().enumerate() {
+            if *b == b'\n' && i + 1 == input.len() && self.accept_before_newline[state] {
+                return true;
+            }
+            state = self.table[state][*b as usize] as usize;
+            if self.accept[state] {
+                return true;
+            }
+        }
+        self.accept_at_end[state]
+    }
+
+    /// 状態数を返す
+    pub fn state_count(&self) -> usize {
+        self.table.len()
+    }
+
+    /// 遷移表のメモリ使用量をバイト単位で返す (256 × 4バイト × 状態数)
+    pub fn memory_usage(&self) -> usize {
+        self.table.len() * std::mem::size_of::<[u32; 256]>()
+    }
+}
+
+/// `closure` で $ を判定する位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EndPos {
+    Inner,        // 末尾以外
+    FinalNewline, // 末尾の改行の直前
+    End,          // 入力の末尾
+}
+
+/// seedsから空遷移で到達できるpcの集合を昇順で返す
+///
+/// 文字を消費する命令とMatchが集合に含まれる。
+/// ^ と \G はat_startがtrueの場合のみ通過し，$ はendの位置で成り立つ場合は通過，
+/// 成り立たない場合は後で判定するため集合に含める。
+fn closure(inst: &[Instruction], seeds: Vec<usize>, at_start: bool, end: EndPos) -> Vec<usize> {
+    let mut visited = vec![false; inst.len()];
+    let mut set = Vec::new();
+    let mut stack = seeds;
+    while let Some(pc) = stack.pop() {
+        match visited.get_mut(pc) {
+            Some(false) => visited[pc] = true,
+            _ => continue,
+        }
+
+        match &inst[pc] {
+            Instruction::Jump(addr) => stack.push(*addr),
+            Instruction::Split(addr1, addr2) => stack.extend([*addr1, *addr2]),
+            Instruction::SplitN(addrs) => stack.extend(addrs),
+            Instruction::Save(_) => stack.push(pc + 1),
+            Instruction::Assert(AssertKind::Start | AssertKind::PrevEnd) => {
+                if at_start {
+                    stack.push(pc + 1);
+                }
+            }
+            Instruction::Assert(AssertKind::End) => {
+                if end == EndPos::End {
+                    stack.push(pc + 1);
+                } else {
+                    set.push(pc);
+                }
+            }
+            Instruction::Assert(AssertKind::EndNewline) => {
+                if end != EndPos::Inner {
+                    stack.push(pc + 1);
+                } else {
+                    set.push(pc);
+                }
+            }
+            Instruction::Char(_)
+            | Instruction::CharCI(_)
+            | Instruction::Class(_)
+            | Instruction::CharNot(_)
+            | Instruction::Match
+            | Instruction::MatchN(_) => set.push(pc),
+            Instruction::Assert(
+                AssertKind::LineStart | AssertKind::LineEnd | AssertKind::WordBoundary,
+            ) => (), // newで除外済み
+        }
+    }
+    set.sort_unstable();
+    set
+}
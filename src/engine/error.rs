@@ -0,0 +1,55 @@
+//! error.rs provides `RegexError`, the error type of the public API.
+
+use super::{codegen::CodeGenError, evaluator::EvalError, parser::ParseError};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// 正規表現のコンパイルやマッチングで発生するエラーを表現するための型
+///
+/// 表示される文字列は元のエラーと同じで，元のエラーは `Error::source` で取得できる。
+#[derive(Debug)]
+pub enum RegexError {
+    Parse(ParseError),     // パースエラー
+    CodeGen(CodeGenError), // コード生成エラー
+    Eval(EvalError),       // 評価エラー
+}
+
+impl Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegexError::Parse(e) => e.fmt(f),
+            RegexError::CodeGen(e) => e.fmt(f),
+            RegexError::Eval(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for RegexError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RegexError::Parse(e) => Some(e),
+            RegexError::CodeGen(e) => Some(e),
+            RegexError::Eval(e) => Some(e),
+        }
+    }
+}
+
+impl From<ParseError> for RegexError {
+    fn from(e: ParseError) -> Self {
+        RegexError::Parse(e)
+    }
+}
+
+impl From<CodeGenError> for RegexError {
+    fn from(e: CodeGenError) -> Self {
+        RegexError::CodeGen(e)
+    }
+}
+
+impl From<EvalError> for RegexError {
+    fn from(e: EvalError) -> Self {
+        RegexError::Eval(e)
+    }
+}
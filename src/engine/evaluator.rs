@@ -1,75 +1,891 @@
-use super::Instruction;
+use super::{AssertKind, Instruction};
 use crate::helper::safe_add;
 use std::{
-    collections::VecDeque,
+    collections::{HashSet, VecDeque},
     error::Error,
     fmt::{self, Display},
+    ops::Range,
 };
 
 #[derive(Debug)]
 pub enum EvalError {
     PCOverflow,
     SPOverflow,
-    InvalidPC,
+    InvalidPC(usize), // 命令列の範囲外のpc
     InvalidContext,
+    InvalidSlot,
+    BacktrackLimitExceeded, // バックトラックの深さが上限を超えた
+    StackOverflowPrevented, // 再帰の深さが上限を超えた
+    NoReachableMatch,       // 先頭から到達できるMatch命令がない
 }
 
 impl Display for EvalError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "EvalError: {:?}", self)
+        match self {
+            EvalError::InvalidPC(pc) => write!(f, "EvalError: invalid pc: pc = {pc}"),
+            _ => write!(f, "EvalError: {:?}", self),
+        }
     }
 }
 
 impl Error for EvalError {}
 
+/// マッチした範囲の開始位置と終了位置
+///
+/// 位置は，`Regex::find` などでは文字単位，`Regex::find_bytes` などではバイト単位のインデックス。
+/// 開始位置，終了位置の順に比較されるため，マッチを集めたベクタを整列すると文字列中の順に並ぶ。
+///
+/// # 利用例
+///
+/// ```
+/// use regex_engine::Match;
+/// let mut spans = vec![Match::new(3, 5), Match::new(0, 2), Match::new(0, 1)];
+/// spans.sort();
+/// assert_eq!(spans, [(0, 1), (0, 2), (3, 5)]);
+/// assert_eq!(spans[2].len(), 2);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Match {
+    pub start: usize, // 開始位置
+    pub end: usize,   // 終了位置 (この位置の文字は含まない)
+}
+
+impl Match {
+    /// 開始位置と終了位置から生成する
+    pub fn new(start: usize, end: usize) -> Self {
+        Match { start, end }
+    }
+
+    /// マッチした範囲の長さを返す
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// 空文字列へのマッチの場合にtrueを返す
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// マッチした範囲を `Range` で返す
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+}
+
+impl From<(usize, usize)> for Match {
+    fn from((start, end): (usize, usize)) -> Self {
+        Match { start, end }
+    }
+}
+
+impl From<Match> for (usize, usize) {
+    fn from(m: Match) -> Self {
+        (m.start, m.end)
+    }
+}
+
+impl PartialEq<(usize, usize)> for Match {
+    fn eq(&self, (start, end): &(usize, usize)) -> bool {
+        self.start == *start && self.end == *end
+    }
+}
+
+/// グループ番号でインデックスされたキャプチャ位置のベクタ
+///
+/// 各要素はグループにマッチした範囲で，マッチに参加しなかったグループはNoneとなる
+pub type Captures = Vec<Option<Match>>;
+
+/// 文字列のsp番目の位置の直前と直後の文字，および直後のさらに次の文字を返す
+///
+/// 文字列の先頭や末尾ではNoneとなる
+fn around(line: &[char], sp: usize) -> (Option<char>, Option<char>, Option<char>) {
+    let prev = sp.checked_sub(1).and_then(|i| line.get(i)).copied();
+    (prev, line.get(sp).copied(), line.get(sp + 1).copied())
+}
+
+/// 分岐を1段深く評価する際に，バックトラックの深さの残りを1減らす
+///
+/// limitは残りの深さで，Noneの場合は上限なし。残りが0の場合はエラーとなる
+fn descend(limit: Option<usize>) -> Result<Option<usize>, EvalError> {
+    match limit {
+        Some(0) => Err(EvalError::BacktrackLimitExceeded),
+        Some(n) => Ok(Some(n - 1)),
+        None => Ok(None),
+    }
+}
+
+/// 再帰呼び出しの前に，再帰の深さの残りを1減らす
+///
+/// depthは残りの深さで，Noneの場合は上限なし。
+/// 残りが0の場合は，スタックオーバーフローで異常終了する代わりにエラーとなる
+fn recurse(depth: Option<usize>) -> Result<Option<usize>, EvalError> {
+    match depth {
+        Some(0) => Err(EvalError::StackOverflowPrevented),
+        Some(n) => Ok(Some(n - 1)),
+        None => Ok(None),
+    }
+}
+
+/// 分岐命令 (Jump, Split, SplitN) で評価済みの (pc, sp) に再び到達したかを返す
+///
+/// 初めて到達した場合は記録してfalseを返す。同じ (pc, sp) からの評価結果は同じであり，
+/// 先に到達した方が優先順位が高いため，再び到達した分岐は評価せずに失敗として扱える。
+/// (a*)* のように空文字列にマッチする繰り返しが作る空遷移のループは，
+/// 必ず後方への分岐を通るため，これにより評価が停止する。
+fn revisited(
+    visited: &mut HashSet<(usize, usize)>,
+    inst: &Instruction,
+    pc: usize,
+    sp: usize,
+) -> bool {
+    matches!(
+        inst,
+        Instruction::Jump(_) | Instruction::Split(_, _) | Instruction::SplitN(_)
+    ) && !visited.insert((pc, sp))
+}
+
+/// 深さ優先でマッチングを行い，マッチした場合はその終了位置を返す
+///
+/// 再帰の代わりに，未評価の分岐をヒープ上のスタックに積むため，
+/// 入力が長く分岐が深くなってもスタックオーバーフローしない。
+/// prev_endは直前のマッチの終了位置で，\G はこの位置でのみ成り立つ。
+/// limitはバックトラックの深さ (未評価の分岐を抱えた入れ子の深さ) の残りで，Noneの場合は上限なし。
+/// depthは再帰していた場合の深さの残りで，0の状態で分岐しようとすると
+/// `EvalError::StackOverflowPrevented` を返す。Noneの場合は上限なし
 fn eval_depth(
     inst: &[Instruction],
     line: &[char],
-    mut pc: usize,
-    mut sp: usize,
-) -> Result<bool, EvalError> {
-    loop {
-        let next = if let Some(i) = inst.get(pc) {
-            i
-        } else {
-            return Err(EvalError::InvalidPC);
+    pc: usize,
+    sp: usize,
+    prev_end: usize,
+    limit: Option<usize>,
+    depth: Option<usize>,
+) -> Result<Option<usize>, EvalError> {
+    // 未評価の分岐 (pc, sp, バックトラックの深さの残り, 再帰の深さの残り)
+    let mut stack = vec![(pc, sp, limit, depth)];
+    let mut visited = HashSet::new();
+    while let Some((mut pc, mut sp, mut limit, mut depth)) = stack.pop() {
+        loop {
+            let Some(next) = inst.get(pc) else {
+                return Err(EvalError::InvalidPC(pc));
+            };
+            if revisited(&mut visited, next, pc, sp) {
+                break;
+            }
+
+            match next {
+                Instruction::Char(_)
+                | Instruction::CharCI(_)
+                | Instruction::Class(_)
+                | Instruction::CharNot(_) => {
+                    if !line.get(sp).is_some_and(|c| next.matches_char(*c)) {
+                        break;
+                    }
+                    safe_add(&mut pc, &1, || EvalError::PCOverflow)?;
+                    safe_add(&mut sp, &1, || EvalError::SPOverflow)?;
+                }
+                Instruction::Assert(AssertKind::PrevEnd) => {
+                    if sp != prev_end {
+                        break;
+                    }
+                    safe_add(&mut pc, &1, || EvalError::PCOverflow)?;
+                }
+                Instruction::Assert(_) => {
+                    let (prev, after, beyond) = around(line, sp);
+                    if !next.matches_position(prev, after, beyond) {
+                        break;
+                    }
+                    safe_add(&mut pc, &1, || EvalError::PCOverflow)?;
+                }
+                Instruction::Match | Instruction::MatchN(_) => return Ok(Some(sp)),
+                Instruction::Jump(addr) => pc = *addr,
+                Instruction::Save(_) => safe_add(&mut pc, &1, || EvalError::PCOverflow)?,
+                Instruction::Split(addr1, addr2) => {
+                    limit = descend(limit)?;
+                    depth = recurse(depth)?;
+                    stack.push((*addr2, sp, limit, depth));
+                    pc = *addr1;
+                }
+                Instruction::SplitN(addrs) => {
+                    let (limit, depth) = (descend(limit)?, recurse(depth)?);
+                    // 先頭の分岐が次に取り出されるよう逆順に積む
+                    stack.extend(addrs.iter().rev().map(|addr| (*addr, sp, limit, depth)));
+                    break;
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// 命令列が実行可能な形になっているかを，実行せずに検査する
+///
+/// すべての命令の遷移先 (Jump，Splitの分岐先や次の命令) が命令列の範囲内にあることと，
+/// 先頭の命令から到達できるMatchが存在することを確認する。
+/// 範囲外の遷移先がある場合は，最初に見つかったアドレスを持つ `EvalError::InvalidPC` を，
+/// Matchに到達できない場合は `EvalError::NoReachableMatch` を返す。
+///
+/// # 利用例
+///
+/// ```
+/// use regex_engine::{validate_program, EvalError, Instruction};
+/// let code = [Instruction::Char('a'), Instruction::Jump(5)];
+/// assert!(matches!(validate_program(&code), Err(EvalError::InvalidPC(5))));
+/// ```
+pub fn validate_program(inst: &[Instruction]) -> Result<(), EvalError> {
+    if inst.is_empty() {
+        return Err(EvalError::InvalidPC(0));
+    }
+    for (pc, i) in inst.iter().enumerate() {
+        if let Some(addr) = i.successors(pc).into_iter().find(|&a| a >= inst.len()) {
+            return Err(EvalError::InvalidPC(addr));
+        }
+    }
+
+    // 先頭から到達できる命令を辿り，Matchを探す
+    let mut visited = vec![false; inst.len()];
+    let mut pending = vec![0];
+    while let Some(pc) = pending.pop() {
+        if visited[pc] {
+            continue;
+        }
+        visited[pc] = true;
+        if matches!(inst[pc], Instruction::Match | Instruction::MatchN(_)) {
+            return Ok(());
+        }
+        pending.extend(inst[pc].successors(pc));
+    }
+    Err(EvalError::NoReachableMatch)
+}
+
+/// 文字列の先頭からマッチするかを判定する
+///
+/// マッチの終了位置が必要な場合は `eval_end` を使う
+pub fn eval(inst: &[Instruction], line: &[char], is_depth: bool) -> Result<bool, EvalError> {
+    Ok(eval_end(inst, line, is_depth)?.is_some())
+}
+
+/// 文字列の先頭からマッチングを行い，マッチした場合はその終了位置を返す
+///
+/// 深さ優先，幅優先のどちらでも，最初に到達したMatchの位置を返す。
+/// マッチしなかった場合は `Ok(None)` を返す。
+pub fn eval_end(
+    inst: &[Instruction],
+    line: &[char],
+    is_depth: bool,
+) -> Result<Option<usize>, EvalError> {
+    eval_limited(inst, line, is_depth, None)
+}
+
+/// バックトラックの深さの上限を指定してマッチングを行い，マッチの終了位置を返す
+///
+/// 深さ優先では未評価の分岐を抱えた再帰の深さ，幅優先では保存した分岐 (ctx) の数が
+/// limitを超えた場合に `EvalError::BacktrackLimitExceeded` を返す。Noneの場合は上限なし。
+pub fn eval_limited(
+    inst: &[Instruction],
+    line: &[char],
+    is_depth: bool,
+    limit: Option<usize>,
+) -> Result<Option<usize>, EvalError> {
+    if is_depth {
+        eval_depth(inst, line, 0, 0, 0, limit, None)
+    } else {
+        eval_width(inst, line, limit)
+    }
+}
+
+/// 文字列のsp番目の文字を開始位置として深さ優先でマッチングを行い，マッチの終了位置を返す
+///
+/// limitはバックトラックの深さの上限で，Noneの場合は上限なし。
+/// recursion_limitは再帰の深さの上限で，超えた場合は `EvalError::StackOverflowPrevented` を返す。
+/// Noneの場合は上限なし。
+/// 直前のマッチはないものとして，\G は文字列の先頭でのみ成り立つ
+pub fn eval_from(
+    inst: &[Instruction],
+    line: &[char],
+    sp: usize,
+    limit: Option<usize>,
+    recursion_limit: Option<usize>,
+) -> Result<Option<usize>, EvalError> {
+    eval_depth(inst, line, 0, sp, 0, limit, recursion_limit)
+}
+
+/// 文字列のsp番目の文字を開始位置として深さ優先でマッチングを行い，
+/// 実行した命令のアドレスとその時点のspをtraceに記録する
+///
+/// 分岐は `eval_depth` と同じ順で評価するが，再帰の代わりに未評価の分岐をスタックに積む。
+/// limitはバックトラックの深さ (スタックに積んだ分岐の数) の上限で，Noneの場合は上限なし。
+/// 直前のマッチはないものとして，\G は文字列の先頭でのみ成り立つ。
+/// `eval_depth` と同様に，分岐命令で評価済みの (pc, sp) に再び到達した場合は
+/// 失敗として扱い記録しないため，空文字列にマッチする繰り返しでも停止する。
+/// 記録のための処理が加わるため，デバッグ用途に限って使う。
+pub fn eval_traced(
+    inst: &[Instruction],
+    line: &[char],
+    sp: usize,
+    limit: Option<usize>,
+    trace: &mut Vec<(usize, usize)>,
+) -> Result<Option<usize>, EvalError> {
+    let mut stack = vec![(0, sp)];
+    let mut visited = HashSet::new();
+    while let Some((mut pc, mut sp)) = stack.pop() {
+        loop {
+            let Some(next) = inst.get(pc) else {
+                return Err(EvalError::InvalidPC(pc));
+            };
+            if revisited(&mut visited, next, pc, sp) {
+                break;
+            }
+            trace.push((pc, sp));
+
+            match next {
+                Instruction::Char(_)
+                | Instruction::CharCI(_)
+                | Instruction::Class(_)
+                | Instruction::CharNot(_) => {
+                    if !line.get(sp).is_some_and(|c| next.matches_char(*c)) {
+                        break;
+                    }
+                    safe_add(&mut pc, &1, || EvalError::PCOverflow)?;
+                    safe_add(&mut sp, &1, || EvalError::SPOverflow)?;
+                }
+                Instruction::Assert(_) => {
+                    let (prev, after, beyond) = around(line, sp);
+                    if !next.matches_position(prev, after, beyond) {
+                        break;
+                    }
+                    safe_add(&mut pc, &1, || EvalError::PCOverflow)?;
+                }
+                Instruction::Match | Instruction::MatchN(_) => return Ok(Some(sp)),
+                Instruction::Jump(addr) => pc = *addr,
+                Instruction::Save(_) => safe_add(&mut pc, &1, || EvalError::PCOverflow)?,
+                Instruction::Split(addr1, addr2) => {
+                    stack.push((*addr2, sp));
+                    pc = *addr1;
+                }
+                Instruction::SplitN(addrs) => {
+                    let Some((first, rest)) = addrs.split_first() else {
+                        break; // 分岐先がない場合はマッチ失敗
+                    };
+                    stack.extend(rest.iter().rev().map(|addr| (*addr, sp)));
+                    pc = *first;
+                }
+            }
+            if limit.is_some_and(|limit| stack.len() > limit) {
+                return Err(EvalError::BacktrackLimitExceeded);
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// 文字列のsp番目の文字を開始位置として深さ優先でマッチングを行い，
+/// 最も先の位置で失敗した命令を記録する
+///
+/// 命令が失敗した時点の (位置, アドレス) について，位置がfurthestに記録済みのもの以降の
+/// 場合にfurthestを更新する。同じ位置での失敗は，バックトラックで最後に試したものが残る。
+/// 分岐の評価順や上限，評価済みの (pc, sp) に再び到達した場合の扱いは `eval_traced` と同じで，
+/// マッチした場合は終了位置を返す。
+/// マッチしなかった理由を調べるためのデバッグ用途に限って使う。
+pub fn eval_furthest(
+    inst: &[Instruction],
+    line: &[char],
+    sp: usize,
+    limit: Option<usize>,
+    furthest: &mut Option<(usize, usize)>,
+) -> Result<Option<usize>, EvalError> {
+    let mut fail = |sp: usize, pc: usize| {
+        if furthest.is_none_or(|(max, _)| sp >= max) {
+            *furthest = Some((sp, pc));
+        }
+    };
+
+    let mut stack = vec![(0, sp)];
+    let mut visited = HashSet::new();
+    while let Some((mut pc, mut sp)) = stack.pop() {
+        loop {
+            let Some(next) = inst.get(pc) else {
+                return Err(EvalError::InvalidPC(pc));
+            };
+            if revisited(&mut visited, next, pc, sp) {
+                break;
+            }
+
+            match next {
+                Instruction::Char(_)
+                | Instruction::CharCI(_)
+                | Instruction::Class(_)
+                | Instruction::CharNot(_) => {
+                    if !line.get(sp).is_some_and(|c| next.matches_char(*c)) {
+                        fail(sp, pc);
+                        break;
+                    }
+                    safe_add(&mut pc, &1, || EvalError::PCOverflow)?;
+                    safe_add(&mut sp, &1, || EvalError::SPOverflow)?;
+                }
+                Instruction::Assert(_) => {
+                    let (prev, after, beyond) = around(line, sp);
+                    if !next.matches_position(prev, after, beyond) {
+                        fail(sp, pc);
+                        break;
+                    }
+                    safe_add(&mut pc, &1, || EvalError::PCOverflow)?;
+                }
+                Instruction::Match | Instruction::MatchN(_) => return Ok(Some(sp)),
+                Instruction::Jump(addr) => pc = *addr,
+                Instruction::Save(_) => safe_add(&mut pc, &1, || EvalError::PCOverflow)?,
+                Instruction::Split(addr1, addr2) => {
+                    stack.push((*addr2, sp));
+                    pc = *addr1;
+                }
+                Instruction::SplitN(addrs) => {
+                    let Some((first, rest)) = addrs.split_first() else {
+                        fail(sp, pc);
+                        break;
+                    };
+                    stack.extend(rest.iter().rev().map(|addr| (*addr, sp)));
+                    pc = *first;
+                }
+            }
+            if limit.is_some_and(|limit| stack.len() > limit) {
+                return Err(EvalError::BacktrackLimitExceeded);
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// 最長一致モードで見つかった最も長いマッチ (終了位置, スロット)
+type Longest = Option<(usize, Vec<Option<usize>>)>;
+
+/// `eval_captures` のスタックに積む，バックトラック時に行う処理
+enum Frame {
+    Thread(usize, usize, Option<usize>, Option<usize>), // 未評価の分岐 (pc, sp, limit, depth)
+    Restore(usize, Option<usize>),                      // スロットを元の値に戻す (スロット, 値)
+}
+
+/// キャプチャ位置をslotsに記録しながら深さ優先でマッチングを行う
+///
+/// マッチした場合はマッチ終了位置を返す。
+/// マッチに失敗した分岐で書き換えたスロットは元の値に戻される。
+/// (a)+ のように繰り返されるグループでは，繰り返しのたびにSaveがスロットを上書きするため，
+/// 最後の繰り返しでの位置が記録される。
+///
+/// longestがSomeの場合は最長一致モードとなり，Matchに到達しても終了せずに
+/// その位置とスロットをlongestに記録し，失敗として扱ってバックトラックを続ける。
+/// prev_endは直前のマッチの終了位置で，\G はこの位置でのみ成り立つ。
+/// limitとdepthは `eval_depth` と同様に扱い，Saveでもdepthを1減らす。
+#[allow(clippy::too_many_arguments)]
+fn eval_captures(
+    inst: &[Instruction],
+    line: &[char],
+    pc: usize,
+    sp: usize,
+    prev_end: usize,
+    slots: &mut [Option<usize>],
+    mut longest: Option<&mut Longest>,
+    limit: Option<usize>,
+    depth: Option<usize>,
+) -> Result<Option<usize>, EvalError> {
+    let mut stack = vec![Frame::Thread(pc, sp, limit, depth)];
+    let mut visited = HashSet::new();
+    while let Some(frame) = stack.pop() {
+        let (mut pc, mut sp, mut limit, mut depth) = match frame {
+            Frame::Thread(pc, sp, limit, depth) => (pc, sp, limit, depth),
+            Frame::Restore(slot, old) => {
+                slots[slot] = old; // 失敗したので元に戻す
+                continue;
+            }
         };
 
-        match next {
-            Instruction::Char(c) => {
-                if let Some(sp_c) = line.get(sp) {
-                    if c == sp_c {
-                        safe_add(&mut pc, &1, || EvalError::PCOverflow)?;
-                        safe_add(&mut sp, &1, || EvalError::SPOverflow)?;
-                    } else {
-                        return Ok(false);
+        loop {
+            let Some(next) = inst.get(pc) else {
+                return Err(EvalError::InvalidPC(pc));
+            };
+            if revisited(&mut visited, next, pc, sp) {
+                break;
+            }
+
+            match next {
+                Instruction::Char(_)
+                | Instruction::CharCI(_)
+                | Instruction::Class(_)
+                | Instruction::CharNot(_) => {
+                    if !line.get(sp).is_some_and(|c| next.matches_char(*c)) {
+                        break;
                     }
-                } else {
-                    return Ok(false);
+                    safe_add(&mut pc, &1, || EvalError::PCOverflow)?;
+                    safe_add(&mut sp, &1, || EvalError::SPOverflow)?;
+                }
+                Instruction::Assert(AssertKind::PrevEnd) => {
+                    if sp != prev_end {
+                        break;
+                    }
+                    safe_add(&mut pc, &1, || EvalError::PCOverflow)?;
+                }
+                Instruction::Assert(_) => {
+                    let (prev, after, beyond) = around(line, sp);
+                    if !next.matches_position(prev, after, beyond) {
+                        break;
+                    }
+                    safe_add(&mut pc, &1, || EvalError::PCOverflow)?;
+                }
+                Instruction::Match | Instruction::MatchN(_) => {
+                    let Some(best) = longest.as_deref_mut() else {
+                        return Ok(Some(sp));
+                    };
+                    if best.as_ref().is_none_or(|(end, _)| sp > *end) {
+                        *best = Some((sp, slots.to_vec()));
+                    }
+                    break;
+                }
+                Instruction::Jump(addr) => pc = *addr,
+                Instruction::Save(slot) => {
+                    let Some(s) = slots.get_mut(*slot) else {
+                        return Err(EvalError::InvalidSlot);
+                    };
+                    let old = s.replace(sp);
+                    depth = recurse(depth)?;
+                    stack.push(Frame::Restore(*slot, old));
+                    safe_add(&mut pc, &1, || EvalError::PCOverflow)?;
+                }
+                Instruction::Split(addr1, addr2) => {
+                    let (inner, deeper) = (descend(limit)?, recurse(depth)?);
+                    stack.push(Frame::Thread(*addr2, sp, limit, depth));
+                    (pc, limit, depth) = (*addr1, inner, deeper);
+                }
+                Instruction::SplitN(addrs) => {
+                    let Some((last, rest)) = addrs.split_last() else {
+                        break;
+                    };
+                    let (inner, deeper) = (descend(limit)?, recurse(depth)?);
+                    // 先頭の分岐が次に取り出されるよう逆順に積む
+                    stack.push(Frame::Thread(*last, sp, limit, depth));
+                    stack.extend(
+                        rest.iter()
+                            .rev()
+                            .map(|addr| Frame::Thread(*addr, sp, inner, deeper)),
+                    );
+                    break;
                 }
             }
-            Instruction::Match => {
+        }
+    }
+    Ok(None)
+}
+
+/// 文字列のsp番目の文字を開始位置としてマッチングを行い，マッチ終了位置を返す
+///
+/// キャプチャのスロットを確保しないため，Save命令を含まない命令列にのみ使える。
+/// prev_end，is_longest，limit，recursion_limitは `eval_captures_from` と同じ。
+pub fn eval_end_from(
+    inst: &[Instruction],
+    line: &[char],
+    sp: usize,
+    prev_end: usize,
+    is_longest: bool,
+    limit: Option<usize>,
+    recursion_limit: Option<usize>,
+) -> Result<Option<usize>, EvalError> {
+    if is_longest {
+        let mut longest = None;
+        eval_captures(
+            inst,
+            line,
+            0,
+            sp,
+            prev_end,
+            &mut [],
+            Some(&mut longest),
+            limit,
+            recursion_limit,
+        )?;
+        Ok(longest.map(|(end, _)| end))
+    } else {
+        eval_captures(
+            inst,
+            line,
+            0,
+            sp,
+            prev_end,
+            &mut [],
+            None,
+            limit,
+            recursion_limit,
+        )
+    }
+}
+
+/// 文字列のsp番目の文字を開始位置としてマッチングを行い，各キャプチャグループの位置を返す
+///
+/// 返り値はグループ番号でインデックスされたマッチ範囲 (`Match`) のベクタで，
+/// マッチに参加しなかったグループはNoneとなる。グループ0はマッチ全体。
+/// マッチしなかった場合は `Ok(None)` を返す。
+///
+/// prev_endは直前のマッチの終了位置で，\G はこの位置でのみ成り立つ。
+/// is_longestがtrueの場合は，最初に見つかったマッチではなく最も長いマッチを返す。
+/// 全ての分岐を探索するため，通常のマッチングより時間がかかる。
+/// limitはバックトラックの深さの上限で，Noneの場合は上限なし。
+/// recursion_limitは再帰の深さの上限で，超えた場合は `EvalError::StackOverflowPrevented` を返す。
+/// Noneの場合は上限なし。
+#[allow(clippy::too_many_arguments)]
+pub fn eval_captures_from(
+    inst: &[Instruction],
+    line: &[char],
+    sp: usize,
+    prev_end: usize,
+    captures_len: usize,
+    is_longest: bool,
+    limit: Option<usize>,
+    recursion_limit: Option<usize>,
+) -> Result<Option<Captures>, EvalError> {
+    let mut slots = vec![None; captures_len * 2];
+    let end = if is_longest {
+        let mut longest = None;
+        eval_captures(
+            inst,
+            line,
+            0,
+            sp,
+            prev_end,
+            &mut slots,
+            Some(&mut longest),
+            limit,
+            recursion_limit,
+        )?;
+        longest.map(|(end, best)| {
+            slots = best;
+            end
+        })
+    } else {
+        eval_captures(
+            inst,
+            line,
+            0,
+            sp,
+            prev_end,
+            &mut slots,
+            None,
+            limit,
+            recursion_limit,
+        )?
+    };
+
+    if let Some(end) = end {
+        slots[0] = Some(sp);
+        slots[1] = Some(end);
+
+        let caps = slots
+            .chunks(2)
+            .map(|s| match s {
+                [Some(b), Some(e)] => Some(Match::new(*b, *e)),
+                _ => None,
+            })
+            .collect();
+        Ok(Some(caps))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Thompson法によるマッチング
+///
+/// 命令列をNFAとみなし，入力を1文字ずつ読みながら到達可能な全てのpcを同時に追跡する。
+/// 入力を先頭から一度だけ読むため，イテレータを逐次的に消費でき，
+/// 実行時間は入力長と命令列の長さの積に比例する。
+/// 各位置から新たにスレッドを開始するため，文字列中のいずれかの位置でマッチすれば `Ok(true)` を返す。
+pub fn eval_thompson<I>(inst: &[Instruction], chars: I) -> Result<bool, EvalError>
+where
+    I: Iterator<Item = char>,
+{
+    // 文字列の末尾や末尾の改行の直前かを判定するため2文字先読みする
+    let mut chars = chars.fuse();
+    let mut ahead = (chars.next(), chars.next());
+    let mut visited = vec![false; inst.len()]; // 現在のステップで追加済みのpc
+    let mut clist = Vec::new(); // 現在のステップのスレッド
+    let mut nlist = Vec::new(); // 次のステップのスレッド
+
+    let at = (None, ahead.0, ahead.1);
+    if add_thread(inst, &mut clist, &mut visited, 0, at)? {
+        return Ok(true);
+    }
+
+    while let Some(c) = ahead.0 {
+        ahead = (ahead.1, chars.next());
+        let at = (Some(c), ahead.0, ahead.1); // 次の位置の直前と直後の文字
+        visited.fill(false);
+        for pc in clist.drain(..) {
+            let matched = inst.get(pc).is_some_and(|i| i.matches_char(c));
+            if matched && add_thread(inst, &mut nlist, &mut visited, pc + 1, at)? {
                 return Ok(true);
             }
-            Instruction::Jump(addr) => {
-                pc = *addr;
+        }
+
+        // 次の位置から始まるマッチのためのスレッド
+        if add_thread(inst, &mut nlist, &mut visited, 0, at)? {
+            return Ok(true);
+        }
+
+        std::mem::swap(&mut clist, &mut nlist);
+    }
+
+    Ok(false)
+}
+
+/// Thompson法で，文字列のsp番目の文字を開始位置とする最短のマッチを探し，その終了位置を返す
+///
+/// 開始位置のスレッドのみを追跡し，いずれかのスレッドがMatchに到達した時点で終了するため，
+/// 繰り返しの優先順位によらず終了位置が最も手前のマッチとなる。
+/// spが文字列の長さを超える場合やマッチしなかった場合は `Ok(None)` を返す。
+/// 直前のマッチはないものとして，\G は文字列の先頭でのみ成り立つ
+pub fn eval_shortest_from(
+    inst: &[Instruction],
+    line: &[char],
+    sp: usize,
+) -> Result<Option<usize>, EvalError> {
+    if sp > line.len() {
+        return Ok(None);
+    }
+    let mut visited = vec![false; inst.len()];
+    let mut clist = Vec::new();
+    let mut nlist = Vec::new();
+
+    if add_thread(inst, &mut clist, &mut visited, 0, around(line, sp))? {
+        return Ok(Some(sp));
+    }
+
+    for (pos, &c) in line.iter().enumerate().skip(sp) {
+        if clist.is_empty() {
+            break;
+        }
+        let at = around(line, pos + 1);
+        visited.fill(false);
+        for pc in clist.drain(..) {
+            let matched = inst.get(pc).is_some_and(|i| i.matches_char(c));
+            if matched && add_thread(inst, &mut nlist, &mut visited, pc + 1, at)? {
+                return Ok(Some(pos + 1));
             }
-            Instruction::Split(addr1, addr2) => {
-                if eval_depth(inst, line, *addr1, sp)? || eval_depth(inst, line, *addr2, sp)? {
-                    return Ok(true);
-                } else {
-                    return Ok(false);
+        }
+        std::mem::swap(&mut clist, &mut nlist);
+    }
+
+    Ok(None)
+}
+
+/// Thompson法で，連結された複数の命令列を同時にマッチングする
+///
+/// startsは各命令列の開始アドレスで，i番目の命令列は `Instruction::MatchN(i)` でマッチを表す。
+/// 入力を先頭から一度だけ読み，命令列ごとにいずれかの位置でマッチしたかを返す。
+pub fn eval_thompson_set<I>(
+    inst: &[Instruction],
+    starts: &[usize],
+    chars: I,
+) -> Result<Vec<bool>, EvalError>
+where
+    I: Iterator<Item = char>,
+{
+    let mut chars = chars.fuse();
+    let mut ahead = (chars.next(), chars.next());
+    let mut visited = vec![false; inst.len()];
+    let mut clist = Vec::new();
+    let mut nlist = Vec::new();
+    let mut matched = vec![false; starts.len()];
+
+    // 現在のステップで到達したMatchNの番号を記録し，全てマッチしたかを返す
+    let record = |visited: &[bool], matched: &mut [bool]| {
+        for (pc, i) in inst.iter().enumerate() {
+            if let Instruction::MatchN(id) = i
+                && visited[pc]
+                && let Some(m) = matched.get_mut(*id)
+            {
+                *m = true;
+            }
+        }
+        matched.iter().all(|m| *m)
+    };
+
+    let at = (None, ahead.0, ahead.1);
+    for &start in starts {
+        add_thread(inst, &mut clist, &mut visited, start, at)?;
+    }
+    if record(&visited, &mut matched) {
+        return Ok(matched);
+    }
+
+    while let Some(c) = ahead.0 {
+        ahead = (ahead.1, chars.next());
+        let at = (Some(c), ahead.0, ahead.1);
+        visited.fill(false);
+        for pc in clist.drain(..) {
+            if inst.get(pc).is_some_and(|i| i.matches_char(c)) {
+                add_thread(inst, &mut nlist, &mut visited, pc + 1, at)?;
+            }
+        }
+        for &start in starts {
+            add_thread(inst, &mut nlist, &mut visited, start, at)?;
+        }
+        if record(&visited, &mut matched) {
+            break;
+        }
+
+        std::mem::swap(&mut clist, &mut nlist);
+    }
+
+    Ok(matched)
+}
+
+/// pcから空遷移 (Jump, Split, SplitN, Save, ^, $) で到達できる文字命令をlistに追加する
+///
+/// 同一ステップで追加済みのpcはvisitedで除外するため，(a*)* のように
+/// 空文字列にマッチする式の繰り返しが空遷移のループを作っても，各命令は一度だけ追加されて停止する。
+/// atは現在の位置の (直前の文字, 直後の文字) で，^ や $ の判定に使う。
+/// Matchに到達した場合は `Ok(true)` を返す。到達したMatchもvisitedに記録される。
+fn add_thread(
+    inst: &[Instruction],
+    list: &mut Vec<usize>,
+    visited: &mut [bool],
+    pc: usize,
+    at: (Option<char>, Option<char>, Option<char>),
+) -> Result<bool, EvalError> {
+    let mut matched = false;
+    let mut stack = vec![pc];
+    while let Some(pc) = stack.pop() {
+        match visited.get_mut(pc) {
+            Some(true) => continue,
+            Some(v) => *v = true,
+            None => return Err(EvalError::InvalidPC(pc)),
+        }
+
+        match &inst[pc] {
+            Instruction::Char(_)
+            | Instruction::CharCI(_)
+            | Instruction::Class(_)
+            | Instruction::CharNot(_) => list.push(pc),
+            Instruction::Match | Instruction::MatchN(_) => matched = true,
+            Instruction::Jump(addr) => stack.push(*addr),
+            Instruction::Save(_) => stack.push(pc + 1),
+            Instruction::Assert(_) => {
+                if inst[pc].matches_position(at.0, at.1, at.2) {
+                    stack.push(pc + 1);
                 }
             }
+            Instruction::Split(addr1, addr2) => {
+                // addr1を優先するため後からpushする
+                stack.push(*addr2);
+                stack.push(*addr1);
+            }
+            Instruction::SplitN(addrs) => stack.extend(addrs.iter().rev()),
         }
     }
+    Ok(matched)
 }
 
-pub fn eval(inst: &[Instruction], line: &[char], is_depth: bool) -> Result<bool, EvalError> {
-    if is_depth {
-        eval_depth(inst, line, 0, 0)
+/// 保存した分岐の数がlimitを超えていればエラーを返す
+fn check_ctx_len(ctx: &VecDeque<(usize, usize)>, limit: Option<usize>) -> Result<(), EvalError> {
+    if limit.is_some_and(|limit| ctx.len() > limit) {
+        Err(EvalError::BacktrackLimitExceeded)
     } else {
-        eval_width(inst, line)
+        Ok(())
     }
 }
 
@@ -87,8 +903,13 @@ fn pop_ctx(
     }
 }
 
-fn eval_width(inst: &[Instruction], line: &[char]) -> Result<bool, EvalError> {
+fn eval_width(
+    inst: &[Instruction],
+    line: &[char],
+    limit: Option<usize>,
+) -> Result<Option<usize>, EvalError> {
     let mut ctx = VecDeque::new();
+    let mut visited = HashSet::new();
     let mut pc = 0;
     let mut sp = 0;
 
@@ -96,33 +917,69 @@ fn eval_width(inst: &[Instruction], line: &[char]) -> Result<bool, EvalError> {
         let next = if let Some(i) = inst.get(pc) {
             i
         } else {
-            return Err(EvalError::InvalidPC);
+            return Err(EvalError::InvalidPC(pc));
         };
 
+        if revisited(&mut visited, next, pc, sp) {
+            if ctx.is_empty() {
+                return Ok(None);
+            }
+            pop_ctx(&mut pc, &mut sp, &mut ctx)?;
+            continue;
+        }
+
         match next {
-            Instruction::Char(c) => {
-                if let Some(sp_c) = line.get(sp) {
-                    if c == sp_c {
-                        safe_add(&mut pc, &1, || EvalError::PCOverflow)?;
-                        safe_add(&mut sp, &1, || EvalError::SPOverflow)?;
-                    } else {
-                        if ctx.is_empty() {
-                            return Ok(false);
-                        } else {
-                            pop_ctx(&mut pc, &mut sp, &mut ctx)?;
-                        }
-                    }
+            Instruction::Char(_)
+            | Instruction::CharCI(_)
+            | Instruction::Class(_)
+            | Instruction::CharNot(_) => {
+                // 入力の末尾に達した場合も不一致として扱う
+                if line.get(sp).is_some_and(|c| next.matches_char(*c)) {
+                    safe_add(&mut pc, &1, || EvalError::PCOverflow)?;
+                    safe_add(&mut sp, &1, || EvalError::SPOverflow)?;
+                } else if ctx.is_empty() {
+                    return Ok(None);
+                } else {
+                    pop_ctx(&mut pc, &mut sp, &mut ctx)?;
                 }
             }
-            Instruction::Match => {
-                return Ok(true);
+            Instruction::Assert(_) => {
+                let (prev, after, beyond) = around(line, sp);
+                if next.matches_position(prev, after, beyond) {
+                    safe_add(&mut pc, &1, || EvalError::PCOverflow)?;
+                } else if ctx.is_empty() {
+                    return Ok(None);
+                } else {
+                    pop_ctx(&mut pc, &mut sp, &mut ctx)?;
+                }
+            }
+            Instruction::Match | Instruction::MatchN(_) => {
+                return Ok(Some(sp));
             }
             Instruction::Jump(addr) => {
                 pc = *addr;
             }
+            Instruction::Save(_) => {
+                safe_add(&mut pc, &1, || EvalError::PCOverflow)?;
+            }
             Instruction::Split(addr1, addr2) => {
                 pc = *addr1;
                 ctx.push_back((*addr2, sp));
+                check_ctx_len(&ctx, limit)?;
+                continue;
+            }
+            Instruction::SplitN(addrs) => {
+                let Some((first, rest)) = addrs.split_first() else {
+                    // 分岐先がない場合はマッチ失敗
+                    if ctx.is_empty() {
+                        return Ok(None);
+                    }
+                    pop_ctx(&mut pc, &mut sp, &mut ctx)?;
+                    continue;
+                };
+                pc = *first;
+                ctx.extend(rest.iter().rev().map(|addr| (*addr, sp)));
+                check_ctx_len(&ctx, limit)?;
                 continue;
             }
         }
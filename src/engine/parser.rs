@@ -1,19 +1,32 @@
 //! parser.rs parses a string (regex expression) into AST (Abstract Syntax Tree).
 
+use super::class::{posix_class, CharClass, NamedMatcher};
 use std::{
     error::Error,
     fmt::{self, Display},
+    iter::Enumerate,
     mem::take,
+    str::Chars,
 };
 
 /// パースエラーを表現するための型
 #[derive(Debug)]
 pub enum ParseError {
-    InvalidEscape(usize, char), // 誤ったエスケープシーケンス
-    InvalidRightParen(usize),   // 開き括弧なし
-    NoPrev(usize),              // +, |, *, ? の前に式がない
-    NoRightParen,               // 閉じ括弧がない
-    Empty,                      // 空のパターン
+    InvalidEscape(usize, char),        // 誤ったエスケープシーケンス
+    InvalidRightParen(usize),          // 開き括弧なし
+    NoPrev(usize),                     // +, |, *, ? の前に式がない
+    NoRightParen,                      // 閉じ括弧がない
+    Empty,                             // 空のパターン
+    InvalidGroupName(usize),           // 不正なグループ名
+    DuplicateGroupName(usize, String), // グループ名の重複
+    InvalidRepeat(usize),              // 繰り返し回数が不正 (下限が上限より大きいなど)
+    UnknownFlag(usize, char),          // 未知のフラグ
+    NoRightBracket(usize),             // ブラケット表現の閉じ括弧がない
+    InvalidClassRange(usize),          // 文字クラスの範囲の始点が終点より大きい
+    UnknownClassName(usize, String),   // 未知の文字クラス名
+    InvalidUtf8(usize),                // UTF-8として不正なバイト列 (位置はバイト単位)
+    UnknownProperty(usize, String),    // 未知のUnicodeプロパティ名
+    UnsupportedSyntax(usize, char),    // Syntax::Basicで使えない構文
 }
 
 impl Display for ParseError {
@@ -32,95 +45,451 @@ impl Display for ParseError {
                 write!(f, "ParseError: no right parenthesis")
             }
             ParseError::Empty => write!(f, "ParseError: empty expression"),
+            ParseError::InvalidGroupName(pos) => {
+                write!(f, "ParseError: invalid group name: pos = {pos}")
+            }
+            ParseError::DuplicateGroupName(pos, name) => {
+                write!(
+                    f,
+                    "ParseError: duplicate group name: pos = {pos}, name = '{name}'"
+                )
+            }
+            ParseError::InvalidRepeat(pos) => {
+                write!(f, "ParseError: invalid repetition range: pos = {pos}")
+            }
+            ParseError::UnknownFlag(pos, c) => {
+                write!(f, "ParseError: unknown flag: pos = {pos}, char = '{c}'")
+            }
+            ParseError::NoRightBracket(pos) => {
+                write!(f, "ParseError: no right bracket: pos = {pos}")
+            }
+            ParseError::InvalidClassRange(pos) => {
+                write!(f, "ParseError: invalid class range: pos = {pos}")
+            }
+            ParseError::UnknownClassName(pos, name) => {
+                write!(
+                    f,
+                    "ParseError: unknown class name: pos = {pos}, name = {name}"
+                )
+            }
+            ParseError::InvalidUtf8(pos) => {
+                write!(f, "ParseError: invalid UTF-8: pos = {pos}")
+            }
+            ParseError::UnknownProperty(pos, name) => {
+                write!(
+                    f,
+                    "ParseError: unknown unicode property: pos = {pos}, name = {name}"
+                )
+            }
+            ParseError::UnsupportedSyntax(pos, c) => {
+                write!(
+                    f,
+                    "ParseError: unsupported syntax: pos = {pos}, char = '{c}'"
+                )
+            }
         }
     }
 }
 
 impl Error for ParseError {}
 
+impl ParseError {
+    /// エラーが発生したパターン中の位置 (文字単位) を返す
+    ///
+    /// 閉じ括弧がない場合や空のパターンのように位置を持たないエラーと，
+    /// 位置がバイト単位の `ParseError::InvalidUtf8` ではNoneとなる。
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            ParseError::InvalidEscape(pos, _)
+            | ParseError::InvalidRightParen(pos)
+            | ParseError::NoPrev(pos)
+            | ParseError::InvalidGroupName(pos)
+            | ParseError::DuplicateGroupName(pos, _)
+            | ParseError::InvalidRepeat(pos)
+            | ParseError::UnknownFlag(pos, _)
+            | ParseError::NoRightBracket(pos)
+            | ParseError::InvalidClassRange(pos)
+            | ParseError::UnknownClassName(pos, _)
+            | ParseError::UnknownProperty(pos, _)
+            | ParseError::UnsupportedSyntax(pos, _) => Some(*pos),
+            ParseError::NoRightParen | ParseError::Empty | ParseError::InvalidUtf8(_) => None,
+        }
+    }
+
+    /// エラーメッセージの下にパターンを表示し，エラーの位置を ^ で示した文字列を返す
+    ///
+    /// exprはこのエラーを返したパターン。閉じ括弧がない場合はパターンの末尾を示し，
+    /// それ以外の位置を持たないエラーではエラーメッセージのみを返す。
+    /// ^ の位置は文字数で数えるため，全角文字などを含むと表示上の位置はずれる。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::parse;
+    /// let err = parse("ab)c").unwrap_err();
+    /// assert_eq!(
+    ///     err.render("ab)c"),
+    ///     "ParseError: invalid right parenthesis: pos = 2\n  ab)c\n    ^"
+    /// );
+    /// ```
+    pub fn render(&self, expr: &str) -> String {
+        let pos = match self {
+            ParseError::NoRightParen => Some(expr.chars().count()),
+            _ => self.position(),
+        };
+        match pos {
+            Some(pos) => format!("{self}\n  {expr}\n  {}^", " ".repeat(pos)),
+            None => self.to_string(),
+        }
+    }
+}
+
 /// 抽象構文木を表現するための型
-#[derive(Debug)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum AST {
-    Char(char),             // 単一の文字
-    Plus(Box<AST>),         // +: 1回以上の繰り返し
-    Star(Box<AST>),         // *: 0回以上の繰り返し
-    Question(Box<AST>),     // ?: 0回または1回の繰り返し
-    Or(Box<AST>, Box<AST>), // |: 選択肢
-    Seq(Vec<AST>),          // 正規表現のまとまり
+    Char(char),                             // 単一の文字
+    CharCI(char),                           // 大文字と小文字を区別しない単一の文字
+    Class(CharClass),                       // [...]: 文字クラス
+    Start,                                  // ^: 文字列の先頭
+    End,                                    // $: 文字列の末尾
+    LineStart,                              // (?m)^: 行頭
+    LineEnd,                                // (?m)$: 行末
+    AnchorPrevEnd,                          // \G: 直前のマッチの終了位置
+    WordBoundary,                           // \b: 単語の境界
+    Plus(Box<AST>),                         // +: 1回以上の繰り返し
+    Star(Box<AST>),                         // *: 0回以上の繰り返し
+    Question(Box<AST>),                     // ?: 0回または1回の繰り返し
+    Or(Box<AST>, Box<AST>),                 // |: 選択肢
+    Seq(Vec<AST>),                          // 正規表現のまとまり
+    Capture(usize, Box<AST>),               // (...): キャプチャグループ (グループ番号, 式)
+    Repeat(Box<AST>, usize, Option<usize>), // {n,m}: n回以上m回以下の繰り返し，mがNoneなら上限なし
+    Lazy(Box<AST>),                         // +?, *?, ??, {n,m}?: 最短一致の繰り返し (式は繰り返し)
 }
 
 /// parse_plus_star_question関数で利用する
+#[allow(clippy::upper_case_acronyms)]
 enum PSQ {
     Plus,
     Star,
     Question,
 }
 
+/// パース時に有効なフラグ
+///
+/// パターン中の (?i) や (?-i) で，そのグループの終わりまで切り替えられる
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Flags {
+    pub case_insensitive: bool, // i: 大文字と小文字を区別しない
+    pub multi_line: bool,       // m: ^ と $ が行頭と行末にもマッチする
+    pub lazy: bool,             // U: 繰り返しをデフォルトで最短一致とし，? を付けると最長一致にする
+}
+
+/// パターン中で特別な意味を持ち，\ でエスケープすると通常の文字となる文字
+const META_CHARS: &[char] = &[
+    '\\', '(', ')', '|', '+', '*', '?', '{', '}', '[', ']', '^', '$',
+];
+
+/// 文字列中の特別な意味を持つ文字を全てエスケープし，その文字列そのものにマッチするパターンを返す
+///
+/// # 利用例
+///
+/// ```
+/// use regex_engine::{escape, Regex};
+/// assert_eq!(escape("a+b"), "a\\+b");
+/// assert!(Regex::new(&escape("(1+2)*3")).unwrap().is_match("x(1+2)*3").unwrap());
+/// ```
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if META_CHARS.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// `Syntax::Basic` で \ によりエスケープできる文字
+const BASIC_META_CHARS: &[char] = &['\\', '(', ')', '|', '+', '*', '?'];
+
+/// パターンが文字，|, *, +, ?, (...) と，それらのエスケープのみからなるかを検査する
+///
+/// `Syntax::Basic` で使う。. や [, ^, $, {, (?...) のグループ拡張，+? のような最短一致，
+/// \d のようなエスケープシーケンスは，最初に現れた位置と文字を持つ
+/// `ParseError::UnsupportedSyntax` となる。
+/// このエンジンでは . は通常の文字だが，他の多くの実装で任意の1文字を表すため拒否する。
+pub fn check_basic_syntax(expr: &str) -> Result<(), ParseError> {
+    let mut it = expr.chars().enumerate().peekable();
+    while let Some((i, c)) = it.next() {
+        let unsupported = match c {
+            '\\' => match it.next() {
+                Some((j, e)) if !BASIC_META_CHARS.contains(&e) => Some((j, e)),
+                _ => None,
+            },
+            '(' | '+' | '*' | '?' => it.next_if(|(_, next)| *next == '?'),
+            '.' | '[' | '^' | '$' | '{' => Some((i, c)),
+            _ => None,
+        };
+        if let Some((pos, c)) = unsupported {
+            return Err(ParseError::UnsupportedSyntax(pos, c));
+        }
+    }
+    Ok(())
+}
+
 /// 正規表現の文字列をパースしてASTを生成する関数
 pub fn parse(expr: &str) -> Result<AST, ParseError> {
+    parse_with_captures(expr, Flags::default(), &[]).map(|(ast, _)| ast)
+}
+
+/// 正規表現の文字列をパースしてASTを生成する関数
+///
+/// `parse` と異なり最初のエラーで終了せず，回復可能なエラーの後もパースを続けて
+/// 全てのエラーを出現順に返す。エディタなどで全ての問題をまとめて報告するために使う。
+///
+/// 対応する開き括弧のない ')' や，直前にパターンのない '+' などの回復可能なエラーは，
+/// その文字を読み飛ばしてパースを続ける。
+/// 不正なグループ名や閉じ括弧の不足など，回復できないエラーが見つかった時点で終了する。
+///
+/// # 利用例
+///
+/// ```
+/// use regex_engine::{parse_all, ParseError};
+/// let errors = parse_all("a)|*b").unwrap_err();
+/// assert!(matches!(
+///     errors[..],
+///     [ParseError::InvalidRightParen(1), ParseError::NoPrev(3)]
+/// ));
+/// ```
+pub fn parse_all(expr: &str) -> Result<AST, Vec<ParseError>> {
+    let mut errors = Vec::new();
+    match parse_recover(expr, Flags::default(), &[], &mut errors) {
+        Ok((ast, _)) if errors.is_empty() => Ok(ast),
+        Ok(_) => Err(errors),
+        Err(e) => {
+            errors.push(e);
+            Err(errors)
+        }
+    }
+}
+
+/// 正規表現の文字列をパースし，ASTとキャプチャグループの名前一覧を返す関数
+///
+/// 名前一覧はグループ番号でインデックスされ，名前のないグループはNoneとなる。
+/// マッチ全体を表すグループ0も含まれるため，長さがキャプチャグループ数となる。
+/// たとえば，(a)(?P<x>b) の場合は [None, None, Some("x")] となる。
+///
+/// flagsはパターン先頭でのフラグの初期値となる。
+/// matchersはブラケット表現中の [:name:] で参照できる，利用者が定義した文字の判定で，
+/// 同じ名前のPOSIX文字クラスより優先される。
+pub fn parse_with_captures(
+    expr: &str,
+    flags: Flags,
+    matchers: &[NamedMatcher],
+) -> Result<(AST, Vec<Option<String>>), ParseError> {
+    let mut errors = Vec::new();
+    let result = parse_recover(expr, flags, matchers, &mut errors);
+
+    // 回復可能なエラーは回復できないエラーより前に出現しているので，最初のエラーを返す
+    match errors.into_iter().next() {
+        Some(e) => Err(e),
+        None => result,
+    }
+}
+
+/// エラーから回復しながらパースする
+///
+/// 回復可能なエラーはerrorsに追加してパースを続け，回復できないエラーはErrとして返す
+fn parse_recover(
+    expr: &str,
+    mut flags: Flags,
+    matchers: &[NamedMatcher],
+    errors: &mut Vec<ParseError>,
+) -> Result<(AST, Vec<Option<String>>), ParseError> {
     // 内部状態を表現するための型
     // Char: 文字列処理中
     // Escape: エスケープシーケンス処理中
+    // Quote: \Q から \E までの，全ての文字を通常の文字として扱う区間
     enum ParseState {
         Char,
         Escape,
+        Quote,
     }
 
     let mut seq = Vec::new(); // 現在のSeqのコンテキスト
     let mut seq_or = Vec::new(); // 現在のOrのコンテキスト
     let mut stack = Vec::new(); // コンテキストのスタック
     let mut state = ParseState::Char; // 初期状態は文字列処理中
+    let mut names = vec![None]; // グループ名の一覧，グループ0はマッチ全体
 
-    for (i, c) in expr.chars().enumerate() {
+    let mut it = expr.chars().enumerate();
+    while let Some((i, c)) = it.next() {
         match &state {
             ParseState::Char => {
                 match c {
-                    '+' => parse_plus_star_question(&mut seq, PSQ::Plus, i)?,
-                    '*' => parse_plus_star_question(&mut seq, PSQ::Star, i)?,
-                    '?' => parse_plus_star_question(&mut seq, PSQ::Question, i)?,
+                    '+' | '*' | '?' => {
+                        let ast_type = match c {
+                            '+' => PSQ::Plus,
+                            '*' => PSQ::Star,
+                            _ => PSQ::Question,
+                        };
+                        // 直前にパターンがない場合は，その演算子を読み飛ばす
+                        let lazy = parse_lazy_marker(&mut it) != flags.lazy;
+                        if let Err(e) = parse_plus_star_question(&mut seq, ast_type, lazy, i) {
+                            errors.push(e);
+                        }
+                    }
+                    '(' if it.clone().map(|(_, c)| c).take(2).eq("?#".chars()) => {
+                        // (?#...) はコメントなので，閉じ括弧まで読み飛ばす
+                        // コメント中では括弧の対応やエスケープは考慮しない
+                        if !it.any(|(_, c)| c == ')') {
+                            return Err(ParseError::NoRightParen);
+                        }
+                    }
                     '(' => {
-                        // 現在のコンテキストをスタックに保存
-                        // 現在のコンテキストを空の状態にする
-                        let prev = take(&mut seq);
-                        let perv_or = take(&mut seq_or);
-                        stack.push((prev, perv_or));
+                        let mut scoped = flags;
+                        match parse_flags(&mut it, &mut scoped, errors) {
+                            // (?i) などのフラグ指定は，現在のグループの終わりまで有効
+                            FlagSpec::Inline => flags = scoped,
+                            // (?i:...) は，フラグをグループ内でのみ有効にする，キャプチャしないグループ
+                            FlagSpec::Scoped => {
+                                let prev = take(&mut seq);
+                                let perv_or = take(&mut seq_or);
+                                stack.push((prev, perv_or, None, flags));
+                                flags = scoped;
+                            }
+                            FlagSpec::None => {
+                                // 現在のコンテキストをスタックに保存
+                                // 現在のコンテキストを空の状態にする
+                                let prev = take(&mut seq);
+                                let perv_or = take(&mut seq_or);
+                                stack.push((prev, perv_or, Some(names.len()), flags));
+
+                                // 開き括弧の出現順にグループ番号を割り当てる
+                                let name = if it.clone().map(|(_, c)| c).take(3).eq("?P<".chars()) {
+                                    // (?P<name>...) の場合は名前付きグループ
+                                    it.nth(2);
+                                    let name = parse_group_name(&mut it, i)?;
+                                    if names.iter().flatten().any(|n| *n == name) {
+                                        // 名前のないグループとしてパースを続ける
+                                        errors.push(ParseError::DuplicateGroupName(i, name));
+                                        None
+                                    } else {
+                                        Some(name)
+                                    }
+                                } else {
+                                    None
+                                };
+                                names.push(name);
+                            }
+                        }
                     }
                     ')' => {
                         // 現在のコンテキストをスタックからポップ
-                        if let Some((mut prev, prev_or)) = stack.pop() {
+                        if let Some((mut prev, prev_or, group, prev_flags)) = stack.pop() {
                             // "()" のように，式が空の場合はpushしない
-                            if !seq.is_empty() {
+                            // "(abc|)" のように '|' の右辺が空の場合は，空文字列にマッチする選択肢とする
+                            if !seq.is_empty() || !seq_or.is_empty() {
                                 seq_or.push(AST::Seq(seq));
                             }
-                            // orを生成
-                            if let Some(ast) = fold_or(seq_or) {
-                                prev.push(ast);
+                            // orを生成し，キャプチャグループとする
+                            // "()" の場合は空のSeqをキャプチャする
+                            // (?i:...) のようなキャプチャしないグループはそのまま追加する
+                            let ast = fold_or(seq_or).unwrap_or(AST::Seq(Vec::new()));
+                            match group {
+                                Some(group) => prev.push(AST::Capture(group, Box::new(ast))),
+                                None => prev.push(ast),
                             }
 
                             // 以前のコンテキストを現在のコンテキストにする
+                            // グループ内で変更されたフラグも元に戻す
                             seq = prev;
                             seq_or = prev_or;
+                            flags = prev_flags;
                         } else {
-                            return Err(ParseError::InvalidRightParen(i));
+                            // 対応する開き括弧のない ')' は読み飛ばす
+                            errors.push(ParseError::InvalidRightParen(i));
                         }
                     }
                     '|' => {
                         if seq.is_empty() {
-                            return Err(ParseError::NoPrev(i));
+                            // 左辺のない '|' は読み飛ばす
+                            errors.push(ParseError::NoPrev(i));
                         } else {
                             let prev = take(&mut seq);
                             seq_or.push(AST::Seq(prev));
                         }
                     }
+                    '{' => match parse_repeat_range(&mut it, i) {
+                        Ok(Some((min, max))) => {
+                            let lazy = parse_lazy_marker(&mut it) != flags.lazy;
+                            if let Err(e) = parse_repeat(&mut seq, min, max, lazy, i) {
+                                errors.push(e); // 不正な繰り返し指定は読み飛ばす
+                            }
+                        }
+                        Ok(None) => {
+                            // 繰り返し指定として解釈できない場合は通常の文字
+                            seq.push(literal(c, &flags));
+                        }
+                        Err(e) => {
+                            parse_lazy_marker(&mut it);
+                            errors.push(e); // 不正な繰り返し指定は読み飛ばす
+                        }
+                    },
+                    '^' if flags.multi_line => seq.push(AST::LineStart),
+                    '$' if flags.multi_line => seq.push(AST::LineEnd),
+                    '^' => seq.push(AST::Start),
+                    '$' => seq.push(AST::End),
+                    '[' => {
+                        let class = parse_class(&mut it, i, &flags, matchers)?;
+                        seq.push(AST::Class(class));
+                    }
                     '\\' => state = ParseState::Escape, // エスケープシーケンスの開始
-                    _ => seq.push(AST::Char(c)),
+                    _ => seq.push(literal(c, &flags)),
+                };
+            }
+            ParseState::Escape if c == 'Q' => state = ParseState::Quote,
+            ParseState::Escape if c == 'c' => {
+                let ast = match parse_control(&mut it, i) {
+                    Ok(c) => literal(c, &flags),
+                    Err(e) => {
+                        // 英字が続かない \c は通常の文字として扱う
+                        errors.push(e);
+                        literal(c, &flags)
+                    }
                 };
+                seq.push(ast);
+                state = ParseState::Char;
+            }
+            #[cfg(feature = "unicode")]
+            ParseState::Escape if c == 'p' || c == 'P' => {
+                let class = parse_property(&mut it, i, c == 'P', &flags)?;
+                seq.push(AST::Class(class));
+                state = ParseState::Char;
             }
             ParseState::Escape => {
-                let ast = parse_escape(i, c)?;
+                let ast = match parse_escape(i, c) {
+                    Ok(AST::Char(c)) => literal(c, &flags),
+                    Ok(ast) => ast,
+                    Err(e) => {
+                        // 不正なエスケープは通常の文字として扱う
+                        errors.push(e);
+                        literal(c, &flags)
+                    }
+                };
                 seq.push(ast);
                 state = ParseState::Char; // エスケープ処理が終わったので、状態を戻す
             }
+            ParseState::Quote => {
+                if c == '\\' && it.clone().next().is_some_and(|(_, c)| c == 'E') {
+                    // \E で区間を終了する。\E がない場合は末尾までが区間となる
+                    it.next();
+                    state = ParseState::Char;
+                } else {
+                    seq.push(literal(c, &flags));
+                }
+            }
         }
     }
 
@@ -129,25 +498,46 @@ pub fn parse(expr: &str) -> Result<AST, ParseError> {
     }
 
     // "()" のように，式がからの場合はpushしない
-    if !seq.is_empty() {
+    // "abc|" のように '|' の右辺が空の場合は，空文字列にマッチする選択肢とする
+    if !seq.is_empty() || !seq_or.is_empty() {
         seq_or.push(AST::Seq(seq));
     }
 
     // Orを生成し，成功した場合はそれを返す
     if let Some(ast) = fold_or(seq_or) {
-        Ok(ast)
+        Ok((ast, names))
     } else {
         Err(ParseError::Empty)
     }
 }
 
+/// 繰り返し指定の直後に最短一致を表す '?' があれば読み進め，trueを返す
+fn parse_lazy_marker(it: &mut Enumerate<Chars>) -> bool {
+    let marked = it.clone().next().is_some_and(|(_, c)| c == '?');
+    if marked {
+        it.next();
+    }
+    marked
+}
+
+/// 繰り返しのASTを，lazyがtrueの場合は最短一致の繰り返しにする
+fn with_greed(ast: AST, lazy: bool) -> AST {
+    if lazy {
+        AST::Lazy(Box::new(ast))
+    } else {
+        ast
+    }
+}
+
 /// +, *, ? をASTに変換する
 ///
 /// 後置記法で，+, *, ?の前にパターンがない場合はエラー
 /// 例: *ab, abc|+ などはエラー
+/// lazyがtrueの場合は最短一致の繰り返しとなる
 fn parse_plus_star_question(
     seq: &mut Vec<AST>,
     ast_type: PSQ,
+    lazy: bool,
     pos: usize,
 ) -> Result<(), ParseError> {
     if let Some(prev) = seq.pop() {
@@ -156,17 +546,189 @@ fn parse_plus_star_question(
             PSQ::Star => AST::Star(Box::new(prev)),
             PSQ::Question => AST::Question(Box::new(prev)),
         };
-        seq.push(ast);
+        seq.push(with_greed(ast, lazy));
         Ok(())
     } else {
         Err(ParseError::NoPrev(pos))
     }
 }
 
+/// {n}, {n,}, {n,m}, {,m} の形式の繰り返し指定を読み取り，(下限, 上限) を返す
+///
+/// itは '{' の直後を，posは '{' の位置を指している必要がある。{,m} は {0,m} と同じ意味となる。
+/// {}, {,}, {a}, {1,2,3} のように繰り返し指定として解釈できない場合はitを進めずに `Ok(None)` を返す。
+/// 回数がusizeに収まらない場合は，繰り返し指定を読み飛ばしてエラーを返す。
+fn parse_repeat_range(
+    it: &mut Enumerate<Chars>,
+    pos: usize,
+) -> Result<Option<(usize, Option<usize>)>, ParseError> {
+    let mut lookahead = it.clone();
+    let mut body = String::new();
+    loop {
+        match lookahead.next() {
+            Some((_, '}')) => break,
+            Some((_, c)) if c.is_ascii_digit() || c == ',' => body.push(c),
+            _ => return Ok(None),
+        }
+    }
+    if body.is_empty() || body == "," || body.matches(',').count() > 1 {
+        return Ok(None);
+    }
+    *it = lookahead;
+
+    let count = |s: &str| s.parse().map_err(|_| ParseError::InvalidRepeat(pos));
+    let range = match body.split_once(',') {
+        None => {
+            let n = count(&body)?;
+            (n, Some(n))
+        }
+        Some((min, max)) => {
+            let min = if min.is_empty() { 0 } else { count(min)? };
+            let max = if max.is_empty() {
+                None
+            } else {
+                Some(count(max)?)
+            };
+            (min, max)
+        }
+    };
+
+    Ok(Some(range))
+}
+
+/// {n,m} をASTに変換する
+///
+/// 直前にパターンがない場合や，下限が上限より大きい場合はエラー。
+/// lazyがtrueの場合は最短一致の繰り返しとなる
+fn parse_repeat(
+    seq: &mut Vec<AST>,
+    min: usize,
+    max: Option<usize>,
+    lazy: bool,
+    pos: usize,
+) -> Result<(), ParseError> {
+    if max.is_some_and(|max| max < min) {
+        return Err(ParseError::InvalidRepeat(pos));
+    }
+
+    if let Some(prev) = seq.pop() {
+        seq.push(with_greed(AST::Repeat(Box::new(prev), min, max), lazy));
+        Ok(())
+    } else {
+        Err(ParseError::NoPrev(pos))
+    }
+}
+
+/// フラグに応じて，文字cにマッチするASTを生成する
+fn literal(c: char, flags: &Flags) -> AST {
+    if flags.case_insensitive {
+        AST::CharCI(c)
+    } else {
+        AST::Char(c)
+    }
+}
+
+/// フラグ指定の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FlagSpec {
+    None,   // フラグ指定ではない
+    Inline, // (?i) のように，現在のグループの終わりまで有効なフラグ指定
+    Scoped, // (?i:...) のように，グループ内でのみ有効なフラグ指定
+}
+
+/// (?i) や (?-i)，(?i:...) のようなフラグ指定を読み取り，flagsを更新する
+///
+/// itは '(' の直後を指している必要がある。
+/// '-' より後ろのフラグは無効化される。たとえば，(?-i) は大文字と小文字を区別するようにする。
+/// (?i:...) の場合は ':' まで読み進める。(?:...) のようにフラグが空の場合も含む。
+/// フラグ指定の形式でない場合は，itを進めずに `FlagSpec::None` を返す。
+/// 未知のフラグはerrorsに記録し，残りのフラグの処理を続ける。
+fn parse_flags(
+    it: &mut Enumerate<Chars>,
+    flags: &mut Flags,
+    errors: &mut Vec<ParseError>,
+) -> FlagSpec {
+    let mut lookahead = it.clone();
+    if lookahead.next().map(|(_, c)| c) != Some('?') {
+        return FlagSpec::None;
+    }
+
+    // (? の後に英字と '-' のみが続き，) または : で閉じられている場合のみフラグ指定とみなす
+    let mut body = Vec::new();
+    let spec = loop {
+        match lookahead.next() {
+            Some((_, ')')) if !body.is_empty() => break FlagSpec::Inline,
+            Some((_, ':')) => break FlagSpec::Scoped,
+            Some((pos, c)) if c.is_ascii_alphabetic() || c == '-' => body.push((pos, c)),
+            _ => return FlagSpec::None,
+        }
+    };
+
+    *it = lookahead;
+
+    let mut enable = true;
+    for (pos, c) in body {
+        match c {
+            '-' if enable => enable = false,
+            'i' => flags.case_insensitive = enable,
+            'm' => flags.multi_line = enable,
+            'U' => flags.lazy = enable,
+            _ => errors.push(ParseError::UnknownFlag(pos, c)),
+        }
+    }
+    spec
+}
+
+/// (?P<name> の name> 部分を読み進め，グループ名を返す
+///
+/// グループ名は英数字とアンダースコアからなり，数字から始まってはならない
+fn parse_group_name(
+    it: &mut impl Iterator<Item = (usize, char)>,
+    pos: usize,
+) -> Result<String, ParseError> {
+    let mut name = String::new();
+    for (_, c) in it.by_ref() {
+        if c == '>' {
+            let valid = name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            return if valid {
+                Ok(name)
+            } else {
+                Err(ParseError::InvalidGroupName(pos))
+            };
+        }
+        name.push(c);
+    }
+
+    // 閉じる > がない
+    Err(ParseError::InvalidGroupName(pos))
+}
+
 /// 特殊文字のエスケープを処理する関数
+///
+/// \0 はヌル文字を表す。\1 〜 \9 は後方参照のために予約しており，ヌル文字とは区別する。
+/// \d, \w, \s とその否定の \D, \W, \S はASCII文字の文字クラスを表す。
 fn parse_escape(pos: usize, c: char) -> Result<AST, ParseError> {
     match c {
-        '\\' | '(' | ')' | '|' | '+' | '*' | '?' => Ok(AST::Char(c)),
+        c if META_CHARS.contains(&c) => Ok(AST::Char(c)),
+        '0' => Ok(AST::Char('\0')),
+        'G' => Ok(AST::AnchorPrevEnd),
+        'b' => Ok(AST::WordBoundary),
+        'd' | 'w' | 's' | 'D' | 'W' | 'S' => {
+            // \d, \w, \s はそれぞれ [[:digit:]], [[:word:]], [[:space:]] と同じ
+            // 大文字の場合は否定となる
+            let name = match c.to_ascii_lowercase() {
+                'd' => "digit",
+                'w' => "word",
+                _ => "space",
+            };
+            Ok(AST::Class(CharClass {
+                ranges: posix_class(name).unwrap_or_default().to_vec(),
+                negated: c.is_ascii_uppercase(),
+                case_insensitive: false,
+                matchers: Vec::new(),
+            }))
+        }
         _ => {
             let err = ParseError::InvalidEscape(pos, c);
             Err(err)
@@ -174,6 +736,170 @@ fn parse_escape(pos: usize, c: char) -> Result<AST, ParseError> {
     }
 }
 
+/// [...] のブラケット表現を読み取り，文字クラスを返す
+///
+/// itは '[' の直後を指している必要がある。posは '[' の位置。
+/// [^...] は否定，a-z は範囲を表し，[:alpha:] のようなPOSIX文字クラス名や，
+/// matchersに登録された名前も使える。
+/// 先頭の ']' と，先頭または末尾の '-' は通常の文字として扱う。
+/// 外側で特別な意味を持つ . * + ? ( ) | { } $ なども，ブラケット内では通常の文字となる。
+fn parse_class(
+    it: &mut Enumerate<Chars>,
+    pos: usize,
+    flags: &Flags,
+    matchers: &[NamedMatcher],
+) -> Result<CharClass, ParseError> {
+    let negated = it.clone().next().is_some_and(|(_, c)| c == '^');
+    if negated {
+        it.next();
+    }
+
+    let mut ranges = Vec::new();
+    let mut custom = Vec::new();
+    let mut first = true;
+    loop {
+        let Some((i, c)) = it.next() else {
+            return Err(ParseError::NoRightBracket(pos));
+        };
+
+        let lo = match c {
+            ']' if !first => break,
+            '[' => {
+                if let Some(name) = parse_class_name(it) {
+                    if let Some(m) = matchers.iter().find(|m| m.name == name) {
+                        custom.push(m.clone());
+                    } else if let Some(class) = posix_class(&name) {
+                        ranges.extend_from_slice(class);
+                    } else {
+                        return Err(ParseError::UnknownClassName(i, name));
+                    }
+                    first = false;
+                    continue;
+                }
+                c
+            }
+            '\\' => parse_class_escape(it, pos)?,
+            _ => c,
+        };
+        first = false;
+
+        // a-z のような範囲指定。直後が ']' の '-' は通常の文字
+        let mut lookahead = it.clone();
+        let hi = match (lookahead.next(), lookahead.next()) {
+            (Some((_, '-')), Some((_, c))) if c != ']' => {
+                it.next();
+                let (_, c) = it.next().ok_or(ParseError::NoRightBracket(pos))?;
+                let hi = if c == '\\' {
+                    parse_class_escape(it, pos)?
+                } else {
+                    c
+                };
+                if hi < lo {
+                    return Err(ParseError::InvalidClassRange(i));
+                }
+                hi
+            }
+            _ => lo,
+        };
+        ranges.push((lo, hi));
+    }
+
+    Ok(CharClass {
+        ranges,
+        negated,
+        case_insensitive: flags.case_insensitive,
+        matchers: custom,
+    })
+}
+
+/// ブラケット表現中の [:name:] を読み取り，文字クラス名を返す
+///
+/// itは '[' の直後を指している必要がある。名前には英数字と '_' が使える。
+/// [:name:] の形式でない場合は，itを進めずにNoneを返す。
+fn parse_class_name(it: &mut Enumerate<Chars>) -> Option<String> {
+    let mut lookahead = it.clone();
+    if lookahead.next().map(|(_, c)| c) != Some(':') {
+        return None;
+    }
+
+    let mut name = String::new();
+    loop {
+        match lookahead.next()? {
+            (_, ':') => break,
+            (_, c) if c.is_ascii_alphanumeric() || c == '_' => name.push(c),
+            _ => return None,
+        }
+    }
+    if lookahead.next().map(|(_, c)| c) != Some(']') {
+        return None;
+    }
+
+    *it = lookahead;
+    Some(name)
+}
+
+/// \p{name} または \P{name} のUnicodeプロパティを読み取り，文字クラスを返す
+///
+/// itは 'p' または 'P' の直後を指している必要がある。posは 'p' の位置。
+/// negatedがtrueの場合 (\P{name}) は否定された文字クラスとなる。
+#[cfg(feature = "unicode")]
+fn parse_property(
+    it: &mut Enumerate<Chars>,
+    pos: usize,
+    negated: bool,
+    flags: &Flags,
+) -> Result<CharClass, ParseError> {
+    let mut name = String::new();
+    if it.next().map(|(_, c)| c) != Some('{') {
+        return Err(ParseError::UnknownProperty(pos, name));
+    }
+    loop {
+        match it.next() {
+            Some((_, '}')) => break,
+            Some((_, c)) => name.push(c),
+            None => return Err(ParseError::UnknownProperty(pos, name)),
+        }
+    }
+
+    let Some(matcher) = super::unicode::property_matcher(&name) else {
+        return Err(ParseError::UnknownProperty(pos, name));
+    };
+    Ok(CharClass {
+        ranges: Vec::new(),
+        negated,
+        case_insensitive: flags.case_insensitive,
+        matchers: vec![matcher],
+    })
+}
+
+/// ブラケット表現中のエスケープシーケンスを読み取る
+///
+/// itは '\\' の直後を指している必要がある。posは '[' の位置。
+fn parse_class_escape(it: &mut Enumerate<Chars>, pos: usize) -> Result<char, ParseError> {
+    match it.next() {
+        Some((_, c @ ('\\' | ']' | '[' | '^' | '-'))) => Ok(c),
+        Some((_, '0')) => Ok('\0'),
+        Some((i, 'c')) => parse_control(it, i),
+        Some((i, c)) => Err(ParseError::InvalidEscape(i, c)),
+        None => Err(ParseError::NoRightBracket(pos)),
+    }
+}
+
+/// \cX の X を読み取り，対応する制御文字 (\cA 〜 \cZ はコードポイント1 〜 26) を返す
+///
+/// itは 'c' の直後を指している必要がある。posは 'c' の位置。
+/// 英字は大文字と小文字を区別しない。英字が続かない場合は，
+/// 続く文字を読み進めずに `ParseError::InvalidEscape` を返す。
+fn parse_control(it: &mut Enumerate<Chars>, pos: usize) -> Result<char, ParseError> {
+    match it.clone().next() {
+        Some((_, c)) if c.is_ascii_alphabetic() => {
+            it.next();
+            Ok(char::from(c.to_ascii_uppercase() as u8 & 0x1f))
+        }
+        _ => Err(ParseError::InvalidEscape(pos, 'c')),
+    }
+}
+
 /// Orで結合された複数の式をASTに変換
 ///
 /// たとえば，abc|def|ghi はAST::Or("abc", AST::Or("def", "ghi"))というASTとなる
@@ -0,0 +1,2196 @@
+//! regex.rs provides `Regex`, a compiled regular expression.
+
+use super::{
+    analysis,
+    class::{CharClass, CharMatcher, NamedMatcher},
+    codegen::{self, CodeGenError},
+    dfa::ByteDfa,
+    disassemble,
+    error::RegexError,
+    evaluator::{self, Captures, EvalError, Match},
+    parser::{self, Flags, ParseError, AST},
+    AssertKind, Instruction,
+};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    ffi::OsStr,
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// マッチングの実行統計
+///
+/// `Regex::stats` で取得する。最適化が効いているかを確認するために使う。
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// 評価器を起動した回数 (マッチングを試みた開始位置の数)
+    pub eval_calls: usize,
+    /// eval_callsのうち，キャプチャの位置を記録しながら評価した回数
+    pub capture_eval_calls: usize,
+    /// `Regex::with_input_cache` のキャッシュにより，評価器を起動せずに判定した回数
+    pub cache_hits: usize,
+}
+
+/// 実行統計のカウンタ
+#[derive(Debug, Default)]
+struct Counters {
+    eval_calls: AtomicUsize,
+    capture_eval_calls: AtomicUsize,
+    cache_hits: AtomicUsize,
+}
+
+/// `is_match` の入力と結果を保持するLRUキャッシュ
+///
+/// 容量は小さいことを想定し，先頭ほど最近使われていない線形のリストで管理する
+#[derive(Debug)]
+struct InputCache {
+    capacity: usize,
+    entries: VecDeque<(String, bool)>,
+}
+
+impl InputCache {
+    /// 入力に対する結果を返し，最近使われたものとして末尾に移動する
+    fn get(&mut self, line: &str) -> Option<bool> {
+        let i = self.entries.iter().position(|(s, _)| s == line)?;
+        let entry = self.entries.remove(i)?;
+        let result = entry.1;
+        self.entries.push_back(entry);
+        Some(result)
+    }
+
+    /// 結果を追加し，容量を超えた場合は最も長く使われていないものを捨てる
+    fn insert(&mut self, line: &str, result: bool) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((line.to_string(), result));
+    }
+}
+
+/// コンパイル済みの正規表現
+///
+/// パースとコード生成は `Regex::new` の時点で一度だけ行われるため，
+/// 同じ正規表現で何度もマッチングする場合は `do_matching` よりも効率的。
+///
+/// 入力は `char` (Unicodeスカラー値) 単位で照合され，位置も文字単位で数える。
+/// 😀 のようなBMP外の文字もUTF-16のサロゲートペアやUTF-8のバイト列に分かれず，1文字として扱われる。
+///
+/// # 利用例
+///
+/// ```
+/// use regex_engine::Regex;
+/// let re = Regex::new("a(bc)+").unwrap();
+/// assert!(re.is_match("xabcbc").unwrap());
+/// assert_eq!(re.captures_len(), 2);
+/// ```
+#[derive(Debug)]
+pub struct Regex {
+    expr: String,      // 元の正規表現の文字列
+    cache_key: String, // 元の文字列とオプションから作るキャッシュのキー
+    ast: AST,          // 結合のために保持する抽象構文木
+    code: Vec<Instruction>,
+    names: Vec<Option<String>>,        // グループ番号ごとのグループ名
+    required: Option<String>,          // マッチに必ず含まれる固定文字列
+    literal: Option<String>,           // 命令列が固定文字列のみからなる場合のその文字列
+    has_captures: bool,                // Save命令を含むかどうか
+    first_char: Option<char>,          // マッチの先頭が必ずこの文字になる
+    longest_match: bool,               // 最長一致モードかどうか
+    backtrack_limit: Option<usize>,    // バックトラックの深さの上限
+    recursion_limit: Option<usize>,    // 評価器の再帰の深さの上限
+    strict_end: bool,                  // $ が文字列の末尾のみで成り立つかどうか
+    always_matches: bool,              // 任意の文字列にマッチするかどうか
+    never_matches: bool,               // どの文字列にもマッチしないかどうか
+    anchored_start: bool,              // 必ず ^ から始まるかどうか
+    anchored_end: bool,                // 必ず $ で終わるかどうか
+    len_range: (usize, Option<usize>), // マッチする文字列の長さの範囲
+    lints: Vec<String>,                // 正規表現の問題点の警告
+    counters: Counters,
+    input_cache: Option<Mutex<InputCache>>, // is_matchの結果のキャッシュ
+}
+
+/// 繰り返しのデフォルトの一致の方法
+///
+/// `RegexBuilder::greediness` で指定する
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Greediness {
+    /// 繰り返しは最長一致を優先し，+? のように ? を付けると最短一致を優先する (Perl方式)
+    #[default]
+    Greedy,
+    /// 繰り返しは最短一致を優先し，+? のように ? を付けると最長一致を優先する
+    Lazy,
+    /// 繰り返しの優先順位にかかわらず，最も長いマッチを選ぶ (POSIX方式)
+    Longest,
+}
+
+/// パターンとして受け付ける構文
+///
+/// `RegexBuilder::syntax` で指定する
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// 文字，|, *, +, ?, (...) と，それらのエスケープのみからなる最初期の構文
+    Basic,
+    /// 文字クラスやアンカー，繰り返し回数の指定などを含む全ての構文
+    #[default]
+    Extended,
+}
+
+/// `RegexBuilder::size_limit` のデフォルト値 (命令数)
+pub const DEFAULT_SIZE_LIMIT: usize = 100_000;
+
+/// オプションを指定して `Regex` を生成するためのビルダー
+///
+/// # 利用例
+///
+/// ```
+/// use regex_engine::{Match, RegexBuilder};
+/// let re = RegexBuilder::new("a|aa").longest_match(true).build().unwrap();
+/// assert_eq!(re.find("aa").unwrap(), Some(Match::new(0, 2)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct RegexBuilder {
+    expr: String,
+    longest_match: bool,
+    backtrack_limit: Option<usize>,
+    recursion_limit: Option<usize>,
+    size_limit: usize,
+    whole_word: bool,
+    syntax: Syntax,
+    allow_empty: bool,
+    strict_end: bool,
+    flags: Flags,
+    matchers: Vec<NamedMatcher>,
+}
+
+impl RegexBuilder {
+    /// 正規表現を指定してビルダーを生成する
+    pub fn new(expr: &str) -> Self {
+        RegexBuilder {
+            expr: expr.to_string(),
+            longest_match: false,
+            backtrack_limit: None,
+            recursion_limit: None,
+            size_limit: DEFAULT_SIZE_LIMIT,
+            whole_word: false,
+            syntax: Syntax::Extended,
+            allow_empty: false,
+            strict_end: false,
+            flags: Flags::default(),
+            matchers: Vec::new(),
+        }
+    }
+
+    /// 最長一致モードを設定する (デフォルトはfalse)
+    ///
+    /// 通常のバックトラックによるマッチングでは，各開始位置で最初に見つかったマッチを返すため，
+    /// a|aa を "aa" に適用すると "a" にマッチする。
+    /// 最長一致モードでは，Matchに到達した後もバックトラックを続けて最も長いマッチを返すため，
+    /// "aa" にマッチする。
+    ///
+    /// 全ての分岐を探索するため，パターンによっては通常のマッチングより大幅に遅くなる。
+    /// `is_match` の結果には影響しない。
+    pub fn longest_match(&mut self, yes: bool) -> &mut Self {
+        self.longest_match = yes;
+        self
+    }
+
+    /// 繰り返しのデフォルトの一致の方法を設定する (デフォルトは `Greediness::Greedy`)
+    ///
+    /// `Greediness::Lazy` はパターン先頭に (?U) を書いた場合と同じで，
+    /// +? や *? のように ? を付けた繰り返しは逆に最長一致を優先する。
+    /// `Greediness::Longest` は `longest_match(true)` と同じで，個々の繰り返しの指定は結果に影響しない。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{Greediness, Match, RegexBuilder};
+    /// let re = RegexBuilder::new("a+").greediness(Greediness::Lazy).build().unwrap();
+    /// assert_eq!(re.find("aaa").unwrap(), Some(Match::new(0, 1)));
+    /// let re = RegexBuilder::new("a+?").greediness(Greediness::Lazy).build().unwrap();
+    /// assert_eq!(re.find("aaa").unwrap(), Some(Match::new(0, 3)));
+    /// ```
+    pub fn greediness(&mut self, greediness: Greediness) -> &mut Self {
+        self.flags.lazy = greediness == Greediness::Lazy;
+        self.longest_match = greediness == Greediness::Longest;
+        self
+    }
+
+    /// バックトラックの深さの上限を設定する (デフォルトは上限なし)
+    ///
+    /// 未評価の分岐を抱えたまま評価を進められる深さを制限するもので，
+    /// 評価器が使うスタックの大きさ，つまりメモリ使用量の上限となる。
+    /// 上限を超えた場合，マッチングは `EvalError::BacktrackLimitExceeded` を返す。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{EvalError, RegexBuilder};
+    /// let re = RegexBuilder::new("(a|b)*c").backtrack_limit(10).build().unwrap();
+    /// assert!(re.is_match("abc").unwrap());
+    /// let line = "ab".repeat(10) + "c";
+    /// assert!(matches!(re.is_match(&line), Err(EvalError::BacktrackLimitExceeded)));
+    /// ```
+    pub fn backtrack_limit(&mut self, limit: usize) -> &mut Self {
+        self.backtrack_limit = Some(limit);
+        self
+    }
+
+    /// 評価器の再帰の深さの上限を設定する (デフォルトは上限なし)
+    ///
+    /// 評価器は分岐やキャプチャの記録のたびに，バックトラックのための情報をヒープ上のスタックに積むため，
+    /// a* のような繰り返しでは入力の長さに比例してスタックが深くなる。
+    /// 上限を設定すると，分岐やキャプチャの記録の入れ子がこの深さを超えた時点で，
+    /// マッチングは `EvalError::StackOverflowPrevented` を返す。
+    /// 長い入力に対するメモリの使用量を制限したい場合に設定する。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{EvalError, RegexBuilder};
+    /// let re = RegexBuilder::new("(a)*$").recursion_limit(100).build().unwrap();
+    /// assert!(re.is_match(&"a".repeat(10)).unwrap());
+    /// let line = "a".repeat(100);
+    /// assert!(matches!(re.is_match(&line), Err(EvalError::StackOverflowPrevented)));
+    /// ```
+    pub fn recursion_limit(&mut self, limit: usize) -> &mut Self {
+        self.recursion_limit = Some(limit);
+        self
+    }
+
+    /// コンパイル後の命令数の上限を設定する (デフォルトは `DEFAULT_SIZE_LIMIT`)
+    ///
+    /// (a{100}){100} のような入れ子の繰り返しは命令数が掛け算で増えるため，
+    /// コード生成の前に `estimate_code_size` で命令数を見積もり，
+    /// 上限を超える場合は `CodeGenError::RepeatTooLarge` を返す。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{CodeGenError, RegexBuilder, RegexError};
+    /// let err = RegexBuilder::new("a{10}").size_limit(10).build().unwrap_err();
+    /// assert!(matches!(err, RegexError::CodeGen(CodeGenError::RepeatTooLarge)));
+    /// ```
+    pub fn size_limit(&mut self, limit: usize) -> &mut Self {
+        self.size_limit = limit;
+        self
+    }
+
+    /// 受け付ける構文を設定する (デフォルトは `Syntax::Extended`)
+    ///
+    /// `Syntax::Basic` では，文字，|, *, +, ?, (...) 以外の構文を含むパターンは
+    /// `ParseError::UnsupportedSyntax` となる。
+    /// 後から追加された構文によってパターンの意味が変わることを避けたい場合に使う。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{ParseError, RegexBuilder, RegexError, Syntax};
+    /// assert!(RegexBuilder::new("a(b|c)*").syntax(Syntax::Basic).build().is_ok());
+    /// let err = RegexBuilder::new("^ab").syntax(Syntax::Basic).build().unwrap_err();
+    /// assert!(matches!(err, RegexError::Parse(ParseError::UnsupportedSyntax(0, '^'))));
+    /// ```
+    pub fn syntax(&mut self, syntax: Syntax) -> &mut Self {
+        self.syntax = syntax;
+        self
+    }
+
+    /// 空のパターンを許可するかを設定する (デフォルトはfalse)
+    ///
+    /// 許可した場合，"" や "(?i)" のように式を含まないパターンは空文字列にマッチする
+    /// `match` のみの命令列となり，任意の文字列の先頭でマッチする。
+    /// 許可しない場合は，従来どおり `ParseError::Empty` を返す。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{Match, RegexBuilder};
+    /// let re = RegexBuilder::new("").allow_empty(true).build().unwrap();
+    /// assert_eq!(re.find("abc").unwrap(), Some(Match::new(0, 0)));
+    /// assert!(RegexBuilder::new("").build().is_err());
+    /// ```
+    pub fn allow_empty(&mut self, yes: bool) -> &mut Self {
+        self.allow_empty = yes;
+        self
+    }
+
+    /// 単語全体にのみマッチさせるかを設定する (デフォルトはfalse)
+    ///
+    /// パターン全体を \b(?:...)\b で囲んだ場合と同じで，マッチの前後が単語の境界である必要がある。
+    /// grep の -w に相当する。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::RegexBuilder;
+    /// let re = RegexBuilder::new("cat").whole_word(true).build().unwrap();
+    /// assert!(re.is_match("a cat").unwrap());
+    /// assert!(!re.is_match("category").unwrap());
+    /// ```
+    pub fn whole_word(&mut self, yes: bool) -> &mut Self {
+        self.whole_word = yes;
+        self
+    }
+
+    /// 複数行モードを設定する (デフォルトはfalse)
+    ///
+    /// 複数行モードでは，^ と $ が文字列の先頭と末尾に加えて，
+    /// 各行の行頭と行末 ('\n' の直後と直前) にもマッチする。
+    /// パターン先頭に (?m) を書いた場合と同じ。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::RegexBuilder;
+    /// let re = RegexBuilder::new("^b$").multi_line(true).build().unwrap();
+    /// assert!(re.is_match("a\nb\nc").unwrap());
+    /// ```
+    pub fn multi_line(&mut self, yes: bool) -> &mut Self {
+        self.flags.multi_line = yes;
+        self
+    }
+
+    /// 複数行モードでない $ を文字列の末尾のみで成り立たせるかを設定する (デフォルトはfalse)
+    ///
+    /// デフォルトではPerlと同様に，$ は文字列の末尾に加えて末尾の改行の直前でも成り立つため，
+    /// 改行を取り除いていないログの行などにも abc$ がマッチする。
+    /// trueの場合は，末尾の改行の直前では成り立たない。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::RegexBuilder;
+    /// assert!(RegexBuilder::new("abc$").build().unwrap().is_match("abc\n").unwrap());
+    /// let re = RegexBuilder::new("abc$").strict_end(true).build().unwrap();
+    /// assert!(!re.is_match("abc\n").unwrap());
+    /// assert!(re.is_match("abc").unwrap());
+    /// ```
+    pub fn strict_end(&mut self, yes: bool) -> &mut Self {
+        self.strict_end = yes;
+        self
+    }
+
+    /// 大文字と小文字を区別しないかを設定する (デフォルトはfalse)
+    ///
+    /// パターン先頭に (?i) を書いた場合と同じで，パターン中の (?-i) で解除できる。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::RegexBuilder;
+    /// let re = RegexBuilder::new("abc").case_insensitive(true).build().unwrap();
+    /// assert!(re.is_match("xAbC").unwrap());
+    /// ```
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut Self {
+        self.flags.case_insensitive = yes;
+        self
+    }
+
+    /// 文字の判定に名前を付けて登録する
+    ///
+    /// 登録した判定は，ブラケット表現中で [[:name:]] や [^[:name:]0-9] のように
+    /// POSIX文字クラスと同じ記法で参照できる。
+    /// 同じ名前のPOSIX文字クラスより優先されるため，[:digit:] などの意味を置き換えることもできる。
+    /// 同じ名前で複数回登録した場合は，後から登録したものが使われる。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{Match, RegexBuilder};
+    /// let re = RegexBuilder::new("[[:vowel:]]+")
+    ///     .char_matcher("vowel", |c: char| "aeiou".contains(c))
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(re.find("xaeiy").unwrap(), Some(Match::new(1, 4)));
+    /// ```
+    pub fn char_matcher(&mut self, name: &str, matcher: impl CharMatcher + 'static) -> &mut Self {
+        self.matchers.retain(|m| m.name != name);
+        self.matchers.push(NamedMatcher {
+            name: name.to_string(),
+            matcher: Arc::new(matcher),
+        });
+        self
+    }
+
+    /// 正規表現の文字列と，マッチングの結果に影響するオプションから作るキャッシュのキーを返す
+    ///
+    /// コンパイル済みの正規表現を `HashMap<String, Regex>` などで使い回す場合のキーとして使う。
+    /// 同じ文字列でも，大文字と小文字を区別するかなどのオプションが異なる場合は異なるキーとなる。
+    /// 結果に影響しない `size_limit` は含まない。
+    /// `char_matcher` で登録した判定は名前のみを含むため，同じ名前で異なる判定を登録する場合は区別されない。
+    /// ビルド後の `Regex::cache_key` と同じ文字列となるため，コンパイル前にキャッシュを引ける。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::RegexBuilder;
+    /// let key = RegexBuilder::new("abc").case_insensitive(true).cache_key();
+    /// assert_ne!(key, RegexBuilder::new("abc").cache_key());
+    /// ```
+    pub fn cache_key(&self) -> String {
+        let mut opts = Vec::new();
+        if self.flags.case_insensitive {
+            opts.push("i".to_string());
+        }
+        if self.flags.multi_line {
+            opts.push("m".to_string());
+        }
+        if self.flags.lazy {
+            opts.push("U".to_string());
+        }
+        if self.longest_match {
+            opts.push("longest".to_string());
+        }
+        if self.whole_word {
+            opts.push("word".to_string());
+        }
+        if self.syntax == Syntax::Basic {
+            opts.push("basic".to_string());
+        }
+        if self.allow_empty {
+            opts.push("allow_empty".to_string());
+        }
+        if self.strict_end {
+            opts.push("strict_end".to_string());
+        }
+        if let Some(limit) = self.backtrack_limit {
+            opts.push(format!("backtrack_limit={limit}"));
+        }
+        if let Some(limit) = self.recursion_limit {
+            opts.push(format!("recursion_limit={limit}"));
+        }
+        for m in &self.matchers {
+            opts.push(format!("matcher={}", m.name));
+        }
+        format!("({}){}", opts.join(","), self.expr)
+    }
+
+    /// 正規表現をパースしてコード生成する
+    ///
+    /// 入力された正規表現にエラーがあったり，内部的な実装エラーがある場合はErrを返す。
+    /// 命令数の見積もりが `size_limit` を超える場合は，コード生成を行わずにErrを返す。
+    pub fn build(&self) -> Result<Regex, RegexError> {
+        if self.syntax == Syntax::Basic {
+            parser::check_basic_syntax(&self.expr)?;
+        }
+        let (mut ast, names) =
+            match parser::parse_with_captures(&self.expr, self.flags, &self.matchers) {
+                Err(ParseError::Empty) if self.allow_empty => (AST::Seq(Vec::new()), vec![None]),
+                result => result?,
+            };
+        if self.whole_word {
+            ast = AST::Seq(vec![AST::WordBoundary, ast, AST::WordBoundary]);
+        }
+        Ok(self.compile(ast, names)?)
+    }
+
+    /// パース済みのASTとグループ名の一覧からコード生成する
+    fn compile(&self, ast: AST, names: Vec<Option<String>>) -> Result<Regex, CodeGenError> {
+        if codegen::estimate_code_size(&ast) > self.size_limit {
+            return Err(CodeGenError::RepeatTooLarge);
+        }
+        let mut code = codegen::get_code(&ast)?;
+        if self.strict_end {
+            for inst in code.iter_mut() {
+                if *inst == Instruction::Assert(AssertKind::EndNewline) {
+                    *inst = Instruction::Assert(AssertKind::End);
+                }
+            }
+        }
+        let has_captures = code.iter().any(|inst| matches!(inst, Instruction::Save(_)));
+        let literal = literal_program(&code);
+        Ok(Regex {
+            expr: self.expr.clone(),
+            cache_key: self.cache_key(),
+            code,
+            names,
+            required: analysis::required_literal(&ast),
+            literal,
+            has_captures,
+            first_char: analysis::first_char(&ast),
+            longest_match: self.longest_match,
+            backtrack_limit: self.backtrack_limit,
+            recursion_limit: self.recursion_limit,
+            strict_end: self.strict_end,
+            always_matches: analysis::always_matches(&ast),
+            never_matches: analysis::never_matches(&ast),
+            anchored_start: analysis::anchored_start(&ast),
+            anchored_end: analysis::anchored_end(&ast),
+            len_range: analysis::len_range(&ast),
+            lints: analysis::lint(&ast),
+            ast,
+            counters: Counters::default(),
+            input_cache: None,
+        })
+    }
+}
+
+impl Regex {
+    /// 正規表現をパースしてコード生成する
+    ///
+    /// 入力された正規表現にエラーがあったり，内部的な実装エラーがある場合はErrを返す
+    pub fn new(expr: &str) -> Result<Self, RegexError> {
+        RegexBuilder::new(expr).build()
+    }
+
+    /// バイト列で与えられた正規表現をパースしてコード生成する
+    ///
+    /// FFIなどから受け取ったバイト列を，UTF-8として検証してから `new` と同様に処理する。
+    /// UTF-8として不正な場合は，不正なバイトの位置を持つ `ParseError::InvalidUtf8` を返す。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{ParseError, Regex, RegexError};
+    /// assert!(Regex::new_bytes(b"a+b").unwrap().is_match("aab").unwrap());
+    /// assert!(matches!(
+    ///     Regex::new_bytes(b"a\xffb"),
+    ///     Err(RegexError::Parse(ParseError::InvalidUtf8(1)))
+    /// ));
+    /// ```
+    pub fn new_bytes(expr: &[u8]) -> Result<Self, RegexError> {
+        let expr = std::str::from_utf8(expr)
+            .map_err(|e| parser::ParseError::InvalidUtf8(e.valid_up_to()))?;
+        Regex::new(expr)
+    }
+
+    /// 利用者が構築したASTから，文字列に戻さずに直接コード生成する
+    ///
+    /// ASTは `parse` の結果を加工したものでも，手で組み立てたものでもよい。
+    /// `AST::Capture` のグループ番号は1から数え，キャプチャ位置のベクタは
+    /// 最大のグループ番号までの長さとなる。グループはいずれも名前を持たない。
+    /// オプションは `Regex::new` と同じ既定値となる。
+    /// 元の文字列を持たないため，`as_str` は空文字列を返す。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{Regex, AST};
+    /// let ast = AST::Seq(vec![AST::Char('a'), AST::Plus(Box::new(AST::Char('b')))]);
+    /// let re = Regex::from_ast(ast).unwrap();
+    /// assert!(re.is_match("xabb").unwrap());
+    /// ```
+    pub fn from_ast(ast: AST) -> Result<Self, CodeGenError> {
+        let names = vec![None; max_group(&ast) + 1];
+        let mut re = RegexBuilder::new("").compile(ast, names)?;
+        re.cache_key = format!("ast:{:?}", re.ast);
+        Ok(re)
+    }
+
+    /// 固定文字列の集合のいずれかにマッチする正規表現を，文字列のパースを経ずに作る
+    ///
+    /// 多数のキーワードを `kw1|kw2|...` の文字列としてパースする代わりに，
+    /// 共通の接頭辞をまとめたトライ木からASTを直接作るため，選択の入れ子が浅くなり，
+    /// 各位置で分岐するのは次の文字が異なる選択肢のみとなる。
+    /// 同じ位置で複数の文字列がマッチする場合は，登録順によらず長い方を優先する。
+    /// literalsが空の場合は何にもマッチしない。
+    /// `as_str` は各文字列をエスケープして | で連結した文字列を返す。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{Match, Regex};
+    /// let re = Regex::alternation_of(&["for", "foreach", "if"]).unwrap();
+    /// assert_eq!(re.find("x foreach").unwrap(), Some(Match::new(2, 9)));
+    /// assert!(!re.is_match("fo").unwrap());
+    /// ```
+    pub fn alternation_of(literals: &[&str]) -> Result<Self, CodeGenError> {
+        let ast = if literals.is_empty() {
+            AST::Class(CharClass {
+                ranges: Vec::new(),
+                negated: false,
+                case_insensitive: false,
+                matchers: Vec::new(),
+            })
+        } else {
+            let mut root = TrieNode::default();
+            for lit in literals {
+                root.insert(lit);
+            }
+            root.to_ast()
+        };
+        let mut re = Regex::from_ast(ast)?;
+        re.expr = literals
+            .iter()
+            .map(|lit| parser::escape(lit))
+            .collect::<Vec<_>>()
+            .join("|");
+        Ok(re)
+    }
+
+    /// 文字列中のいずれかの位置で正規表現がマッチするかを判定
+    ///
+    /// マッチに必ず含まれる固定文字列が正規表現から分かる場合，
+    /// その文字列を含まない入力は評価器を起動せずに不一致と判定する。
+    /// また，a* のように空文字列を含む任意の文字列にマッチする正規表現は，
+    /// 評価器を起動せずに一致と判定し，どの文字にもマッチしない文字クラスを必ず通る
+    /// 正規表現は，評価器を起動せずに不一致と判定する。
+    /// 命令列が固定文字列のみからなる正規表現は，評価器を使わず部分文字列の検索で判定する。
+    ///
+    /// lineには `&str` の他に `String` なども渡せる。
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("b+").unwrap();
+    /// assert!(re.is_match(String::from("abc")).unwrap());
+    /// ```
+    pub fn is_match(&self, line: impl AsRef<str>) -> Result<bool, EvalError> {
+        let line = line.as_ref();
+        if self.always_matches {
+            return Ok(true);
+        }
+        if let Some(lit) = &self.literal {
+            return Ok(line.contains(lit.as_str()));
+        }
+        if !self.may_match(line) {
+            return Ok(false);
+        }
+
+        let Some(cache) = &self.input_cache else {
+            return self.is_match_uncached(line);
+        };
+        if let Some(result) = cache.lock().unwrap().get(line) {
+            self.counters.cache_hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(result);
+        }
+        // 評価中はロックを保持せず，エラーとなった結果はキャッシュしない
+        let result = self.is_match_uncached(line)?;
+        cache.lock().unwrap().insert(line, result);
+        Ok(result)
+    }
+
+    /// `is_match` の評価器によるマッチング
+    fn is_match_uncached(&self, line: &str) -> Result<bool, EvalError> {
+        let line = line.chars().collect::<Vec<char>>();
+        for sp in self.start_positions(&line, 0) {
+            self.counters.eval_calls.fetch_add(1, Ordering::Relaxed);
+            if evaluator::eval_from(
+                &self.code,
+                &line,
+                sp,
+                self.backtrack_limit,
+                self.recursion_limit,
+            )?
+            .is_some()
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// `is_match` の入力と結果を，最大capacity件までLRU方式でキャッシュする
+    ///
+    /// 同じ短い文字列を何度も判定する場合に，メモリと引き換えに評価器の起動を省く。
+    /// キャッシュは `Mutex` で保護されるため，`&self` のまま複数のスレッドから使える。
+    /// capacityが0の場合はキャッシュしない。キャッシュの利用状況は `Stats::cache_hits` で確認できる。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("b+c").unwrap().with_input_cache(16);
+    /// assert!(re.is_match("abbc").unwrap());
+    /// assert!(re.is_match("abbc").unwrap());
+    /// assert_eq!(re.stats().cache_hits, 1);
+    /// ```
+    pub fn with_input_cache(mut self, capacity: usize) -> Self {
+        self.input_cache = (capacity > 0).then(|| {
+            Mutex::new(InputCache {
+                capacity,
+                entries: VecDeque::with_capacity(capacity),
+            })
+        });
+        self
+    }
+
+    /// `is_match` と同様に判定し，実行した命令のアドレスと，その時点の位置 (sp) の列を返す
+    ///
+    /// バックトラックの様子を調べるためのデバッグ用のメソッドで，
+    /// 固定文字列などによる事前の絞り込みは行わず，各開始位置からの評価をすべて記録する。
+    /// 記録は `is_match` などの通常のマッチングには影響しない。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("a|b").unwrap();
+    /// let (matched, trace) = re.is_match_traced("b").unwrap();
+    /// assert!(matched);
+    /// // split 0001, 0003 → char a (失敗) → char b → match
+    /// assert_eq!(trace, vec![(0, 0), (1, 0), (3, 0), (4, 1)]);
+    /// ```
+    pub fn is_match_traced(
+        &self,
+        line: impl AsRef<str>,
+    ) -> Result<(bool, Vec<(usize, usize)>), EvalError> {
+        let line = line.as_ref().chars().collect::<Vec<char>>();
+        let mut trace = Vec::new();
+        for sp in self.start_positions(&line, 0) {
+            self.counters.eval_calls.fetch_add(1, Ordering::Relaxed);
+            if evaluator::eval_traced(&self.code, &line, sp, self.backtrack_limit, &mut trace)?
+                .is_some()
+            {
+                return Ok((true, trace));
+            }
+        }
+        Ok((false, trace))
+    }
+
+    /// 文字列にマッチしなかった理由を調べるため，最も先まで進んだ部分的なマッチを説明する
+    ///
+    /// 各開始位置から評価し，失敗するまでに最も多くの文字を読み進めた試行について，
+    /// 読み進めた部分と，それ以上進めなくなった命令，その位置の文字を文字列で返す。
+    /// 位置は文字単位のインデックス。マッチした場合はマッチした範囲を返す。
+    /// 事前の絞り込みは行わず全ての開始位置を試すため，デバッグ用途に限って使う。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("abc").unwrap();
+    /// assert_eq!(
+    ///     re.explain_nonmatch("abx"),
+    ///     "no match: partial match \"ab\" at 0..2, then 0002: char c failed at 2 (found 'x')"
+    /// );
+    /// assert_eq!(re.explain_nonmatch("xabc"), "match at 1..4: \"abc\"");
+    /// ```
+    pub fn explain_nonmatch(&self, line: impl AsRef<str>) -> String {
+        let line = line.as_ref().chars().collect::<Vec<char>>();
+        let text = |start: usize, end: usize| line[start..end].iter().collect::<String>();
+
+        let mut best: Option<(usize, usize, usize)> = None; // (開始位置, 失敗した位置, アドレス)
+        for start in 0..=line.len() {
+            let mut furthest = None;
+            match evaluator::eval_furthest(
+                &self.code,
+                &line,
+                start,
+                self.backtrack_limit,
+                &mut furthest,
+            ) {
+                Ok(Some(end)) => {
+                    return format!("match at {start}..{end}: {:?}", text(start, end));
+                }
+                Ok(None) => (),
+                Err(e) => return format!("evaluation failed: {e}"),
+            }
+            if let Some((sp, pc)) = furthest
+                && best.is_none_or(|(s, max, _)| sp - start > max - s)
+            {
+                best = Some((start, sp, pc));
+            }
+        }
+
+        let Some((start, sp, pc)) = best else {
+            return "no match".to_string();
+        };
+        let found = match line.get(sp) {
+            Some(c) => format!("{c:?}"),
+            None => "end of input".to_string(),
+        };
+        format!(
+            "no match: partial match {:?} at {start}..{sp}, then {pc:>04}: {} failed at {sp} (found {found})",
+            text(start, sp),
+            self.code[pc]
+        )
+    }
+
+    /// 複数の文字列のそれぞれについて `is_match` で判定し，結果を入力と同じ順に返す
+    ///
+    /// `rayon` フィーチャーが有効な場合，コンパイル済みの正規表現を共有して並列に判定する。
+    /// 並列の場合も結果の順序は入力の順序と一致する。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("ERROR").unwrap();
+    /// let results = re.is_match_batch(&["INFO ok", "ERROR disk full"]);
+    /// assert_eq!(results.into_iter().map(|r| r.unwrap()).collect::<Vec<_>>(), [false, true]);
+    /// ```
+    pub fn is_match_batch(&self, lines: &[&str]) -> Vec<Result<bool, EvalError>> {
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            lines.par_iter().map(|line| self.is_match(line)).collect()
+        }
+        #[cfg(not(feature = "rayon"))]
+        {
+            lines.iter().map(|line| self.is_match(line)).collect()
+        }
+    }
+
+    /// ファイル名などの `OsStr` のいずれかの位置で正規表現がマッチするかを判定
+    ///
+    /// `OsStr` はUTF-8として正しいとは限らないため，`OsStr::to_string_lossy` で変換してから
+    /// マッチングする。UTF-8として不正な部分は U+FFFD (REPLACEMENT CHARACTER) に置き換えられるため，
+    /// 元のバイト列にかかわらず \u{FFFD} を含むパターンにマッチしうる。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// use std::path::Path;
+    /// let re = Regex::new("[.]rs$").unwrap();
+    /// assert!(re.is_match_os(Path::new("src/main.rs").as_os_str()).unwrap());
+    /// ```
+    pub fn is_match_os(&self, s: &OsStr) -> Result<bool, EvalError> {
+        self.is_match(s.to_string_lossy())
+    }
+
+    /// 文字のイテレータに対して，いずれかの位置で正規表現がマッチするかを判定
+    ///
+    /// 入力をスライスに変換せず，Thompson法の評価器で先頭から逐次的に消費する。
+    /// バックトラック型の評価器は任意の位置へ戻る必要があるため入力全体のバッファが必要だが，
+    /// Thompson法は1文字ずつ読むだけでよいため，ストリームなどの入力にも適用できる。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("b+c").unwrap();
+    /// assert!(re.is_match_iter("abbc".chars()).unwrap());
+    /// ```
+    pub fn is_match_iter<I>(&self, chars: I) -> Result<bool, EvalError>
+    where
+        I: Iterator<Item = char>,
+    {
+        self.counters.eval_calls.fetch_add(1, Ordering::Relaxed);
+        evaluator::eval_thompson(&self.code, chars)
+    }
+
+    /// コンパイル済みの命令列を返す
+    ///
+    /// 再コンパイルせずに命令列を調べたり，保存したりするために使う
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{Instruction, Regex};
+    /// let re = Regex::new("a").unwrap();
+    /// assert_eq!(re.as_program(), &[Instruction::Char('a'), Instruction::Match]);
+    /// ```
+    pub fn as_program(&self) -> &[Instruction] {
+        &self.code
+    }
+
+    /// コンパイル済みの命令列を "0000: char a" のような1行1命令のテキストで返す
+    ///
+    /// `print` が表示する命令列と同じ形式
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("a+").unwrap();
+    /// assert_eq!(re.disassemble(), "0000: char a\n0001: split 0000, 0002\n0002: match\n");
+    /// ```
+    pub fn disassemble(&self) -> String {
+        disassemble(&self.code)
+    }
+
+    /// 命令列の制御フローグラフを (アドレス, 命令, 遷移先のアドレス) の組で列挙する
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("a|b").unwrap();
+    /// let (pc, _, targets) = re.program_cfg().next().unwrap();
+    /// assert_eq!((pc, targets), (0, vec![1, 3])); // split 0001, 0003
+    /// ```
+    pub fn program_cfg(&self) -> impl Iterator<Item = (usize, &Instruction, Vec<usize>)> {
+        self.code
+            .iter()
+            .enumerate()
+            .map(|(pc, inst)| (pc, inst, inst.successors(pc)))
+    }
+
+    /// コンパイル済みの命令列が実行可能な形になっているかを検査する
+    ///
+    /// 遷移先が命令列の範囲内にあり，先頭から到達できるMatchが存在することを
+    /// `validate_program` で確認する。コード生成の誤りをマッチングの前に検出するために使う。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// assert!(Regex::new("a(b|c)*d").unwrap().validate().is_ok());
+    /// ```
+    pub fn validate(&self) -> Result<(), EvalError> {
+        evaluator::validate_program(&self.code)
+    }
+
+    /// 実行統計を返す
+    pub fn stats(&self) -> Stats {
+        Stats {
+            eval_calls: self.counters.eval_calls.load(Ordering::Relaxed),
+            capture_eval_calls: self.counters.capture_eval_calls.load(Ordering::Relaxed),
+            cache_hits: self.counters.cache_hits.load(Ordering::Relaxed),
+        }
+    }
+
+    /// マッチする文字列の長さ (文字数) の範囲を (最小, 最大) で返す
+    ///
+    /// 上限のない繰り返し (* や +) を含む場合，最大はNoneとなる。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// assert_eq!(Regex::new("ab?").unwrap().len_range(), (1, Some(2)));
+    /// assert_eq!(Regex::new("a*").unwrap().len_range(), (0, None));
+    /// ```
+    pub fn len_range(&self) -> (usize, Option<usize>) {
+        self.len_range
+    }
+
+    /// パターンが必ず ^ から始まる場合にtrueを返す
+    ///
+    /// trueの場合，マッチは文字列の先頭でのみ起こりうるため，先頭以外の位置からの探索を省略できる。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// assert!(Regex::new("^abc").unwrap().is_anchored_start());
+    /// assert!(!Regex::new("abc").unwrap().is_anchored_start());
+    /// ```
+    pub fn is_anchored_start(&self) -> bool {
+        self.anchored_start
+    }
+
+    /// パターンが必ず $ で終わる場合にtrueを返す
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// assert!(Regex::new("abc$").unwrap().is_anchored_end());
+    /// assert!(!Regex::new("abc").unwrap().is_anchored_end());
+    /// ```
+    pub fn is_anchored_end(&self) -> bool {
+        self.anchored_end
+    }
+
+    /// 正規表現の問題点を警告メッセージとして返す
+    ///
+    /// エラーではなく，マッチングの結果に影響しない冗長な記述などを報告する。
+    /// 現在は，手前の選択肢と同一の選択肢や，手前の選択肢に包含される1文字の選択肢を検出する。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// assert_eq!(Regex::new("a|b|a").unwrap().lint().len(), 1);
+    /// assert!(Regex::new("a|b").unwrap().lint().is_empty());
+    /// ```
+    pub fn lint(&self) -> Vec<String> {
+        self.lints.clone()
+    }
+
+    /// バイト列を入力とする密な遷移表のDFAにコンパイルする
+    ///
+    /// 1状態あたり256通りのバイトの遷移を持つため，メモリ使用量は 256 × 4バイト × 状態数 となる。
+    /// 状態数が `MAX_DFA_STATES` (4096) を超える場合や，複数行モードの ^ や $ を含む場合はNoneを返す。
+    /// ASCII文字の入力では `is_match` と同じ結果となる。
+    /// 非ASCII文字はUTF-8の各バイトが別々の文字として照合されるため，
+    /// 😀 のような4バイトの文字に [^x] は1文字としてマッチしない。
+    /// DFAは命令列を複製して構築するため，この違いが `is_match` などの文字単位のマッチングに及ぶことはない。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let dfa = Regex::new("^ab+$").unwrap().compile_byte_dfa().unwrap();
+    /// assert!(dfa.is_match(b"abbb"));
+    /// assert!(!dfa.is_match(b"xabb"));
+    /// ```
+    pub fn compile_byte_dfa(&self) -> Option<ByteDfa> {
+        ByteDfa::new(&self.code)
+    }
+
+    /// 必須の固定文字列を含むか，最小の長さ以上かで，マッチする可能性があるかを判定する
+    ///
+    /// どの文字列にもマッチしない正規表現では常にfalseとなる
+    fn may_match(&self, line: &str) -> bool {
+        let (min, _) = self.len_range;
+        !self.never_matches
+            && (min == 0 || line.chars().nth(min - 1).is_some())
+            && self
+                .required
+                .as_ref()
+                .is_none_or(|lit| line.contains(lit.as_str()))
+    }
+
+    /// start以降で，マッチの開始位置として評価器を起動する位置を順に返す
+    ///
+    /// ^ から始まるパターンは文字列の先頭でしかマッチしないため，先頭のみを試す。
+    /// マッチの先頭の文字が1つに決まる場合は，その文字が現れる位置まで読み飛ばす
+    fn start_positions<'a>(
+        &'a self,
+        line: &'a [char],
+        start: usize,
+    ) -> impl Iterator<Item = usize> + 'a {
+        let last = if self.anchored_start { 0 } else { line.len() };
+        let mut sp = start;
+        std::iter::from_fn(move || {
+            if let Some(c) = self.first_char {
+                sp += line.get(sp..)?.iter().position(|x| *x == c)?;
+            }
+            let pos = sp;
+            sp += 1;
+            (pos <= last).then_some(pos)
+        })
+    }
+
+    /// start以降で最も左にあるマッチを探し，各キャプチャグループの位置を返す
+    ///
+    /// グループを含まないパターンでは，スロットを確保しない評価器でマッチ終了位置のみを求める。
+    /// prev_endは直前のマッチの終了位置で，\G はこの位置でのみ成り立つ
+    fn search_captures(
+        &self,
+        line: &[char],
+        start: usize,
+        prev_end: usize,
+    ) -> Result<Option<Captures>, EvalError> {
+        for sp in self.start_positions(line, start) {
+            self.counters.eval_calls.fetch_add(1, Ordering::Relaxed);
+            let caps = if self.has_captures {
+                self.counters
+                    .capture_eval_calls
+                    .fetch_add(1, Ordering::Relaxed);
+                evaluator::eval_captures_from(
+                    &self.code,
+                    line,
+                    sp,
+                    prev_end,
+                    self.captures_len(),
+                    self.longest_match,
+                    self.backtrack_limit,
+                    self.recursion_limit,
+                )?
+            } else {
+                evaluator::eval_end_from(
+                    &self.code,
+                    line,
+                    sp,
+                    prev_end,
+                    self.longest_match,
+                    self.backtrack_limit,
+                    self.recursion_limit,
+                )?
+                .map(|end| vec![Some(Match::new(sp, end))])
+            };
+            if caps.is_some() {
+                return Ok(caps);
+            }
+        }
+        Ok(None)
+    }
+
+    /// コンパイル元の正規表現の文字列を返す
+    ///
+    /// `RegexBuilder` で指定したオプションやフラグは含まない。`Display` でも同じ文字列が表示される。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("a+b").unwrap();
+    /// assert_eq!(re.as_str(), "a+b");
+    /// assert_eq!(re.to_string(), "a+b");
+    /// ```
+    pub fn as_str(&self) -> &str {
+        &self.expr
+    }
+
+    /// コンパイル済みの正規表現をキャッシュする際のキーを返す
+    ///
+    /// 元の文字列と，マッチングの結果に影響するオプションから作られ，
+    /// ビルドに使った `RegexBuilder::cache_key` と同じ文字列となる。
+    /// `concat` や `alternate` で結合したものは結合元のキーから，
+    /// `from_ast` で作ったものはASTから作られる。
+    /// いずれの場合も，文字クラス中の判定 (`NamedMatcher`) は名前のみを含むため，
+    /// 同じ名前で異なる判定を持つ場合は区別されない。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{Regex, RegexBuilder};
+    /// let re = RegexBuilder::new("abc").multi_line(true).build().unwrap();
+    /// assert_ne!(re.cache_key(), Regex::new("abc").unwrap().cache_key());
+    /// ```
+    pub fn cache_key(&self) -> &str {
+        &self.cache_key
+    }
+
+    /// キャプチャグループの数を返す
+    ///
+    /// マッチ全体を表すグループ0を含むため，グループを持たない正規表現でも1となる
+    pub fn captures_len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// 文字列中で最も左にあるマッチを探し，各キャプチャグループの位置を返す
+    ///
+    /// 位置は文字単位のインデックスで，開始位置と終了位置を持つ `Match` となる。
+    /// 返り値はグループ番号でインデックスされ，グループ0はマッチ全体を表す。
+    /// マッチに参加しなかったグループはNoneとなる。
+    /// バイト単位の位置が必要な場合は `captures_bytes` を使う。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{Match, Regex};
+    /// let re = Regex::new("a(b|(c))").unwrap();
+    /// let caps = re.captures("xab").unwrap().unwrap();
+    /// assert_eq!(caps, vec![Some(Match::new(1, 3)), Some(Match::new(2, 3)), None]);
+    /// ```
+    pub fn captures(&self, line: impl AsRef<str>) -> Result<Option<Captures>, EvalError> {
+        let line = line.as_ref();
+        if !self.may_match(line) {
+            return Ok(None);
+        }
+
+        let line = line.chars().collect::<Vec<char>>();
+        self.search_captures(&line, 0, 0)
+    }
+
+    /// `captures` と同様にマッチを探し，各キャプチャグループの位置をslotsに書き込む
+    ///
+    /// slotsは長さ `captures_len` にリサイズされ，全てNoneにリセットされてから書き込まれる。
+    /// マッチした場合はtrue，しなかった場合はfalseを返し，後者ではslotsは全てNoneとなる。
+    /// 結果を呼び出し側のバッファに受け取るためのもので，探索自体は `captures` と同じく
+    /// 呼び出しごとに作業領域を確保する。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{Match, Regex};
+    /// let re = Regex::new("a(b)?").unwrap();
+    /// let mut slots = Vec::new();
+    /// assert!(re.captures_read(&mut slots, "xab").unwrap());
+    /// assert_eq!(slots, vec![Some(Match::new(1, 3)), Some(Match::new(2, 3))]);
+    /// assert!(re.captures_read(&mut slots, "xa").unwrap());
+    /// assert_eq!(slots, vec![Some(Match::new(1, 2)), None]);
+    /// ```
+    pub fn captures_read(
+        &self,
+        slots: &mut Vec<Option<Match>>,
+        line: &str,
+    ) -> Result<bool, EvalError> {
+        slots.clear();
+        slots.resize(self.captures_len(), None);
+
+        let Some(caps) = self.captures(line)? else {
+            return Ok(false);
+        };
+        for (slot, cap) in slots.iter_mut().zip(caps) {
+            *slot = cap;
+        }
+        Ok(true)
+    }
+
+    /// 文字列中で最も左にあるマッチの位置を `Match` で返す
+    ///
+    /// 位置は文字単位のインデックス。マッチしなかった場合は `Ok(None)` を返す。
+    /// 元の文字列をスライスするためのバイト単位の位置は `find_bytes` で得られる。
+    /// 命令列が固定文字列のみからなる場合は，評価器を使わず部分文字列の検索で探す。
+    pub fn find(&self, line: impl AsRef<str>) -> Result<Option<Match>, EvalError> {
+        if let Some(lit) = &self.literal {
+            let line = line.as_ref();
+            return Ok(line.find(lit.as_str()).map(|pos| {
+                let start = line[..pos].chars().count();
+                Match::new(start, start + lit.chars().count())
+            }));
+        }
+        Ok(self.captures(line)?.and_then(|caps| caps[0]))
+    }
+
+    /// 文字単位に分解した文字列のstart番目以降で，最も左にあるマッチの位置を返す
+    ///
+    /// 同じ文字列を繰り返し走査する場合に，処理済みの部分を再探索せずに途中から再開できる。
+    /// 位置は文字単位のインデックスで，startより前で始まるマッチは報告されない。
+    /// startが文字列の長さを超える場合は `Ok(None)` を返す。
+    /// \G はstartの位置で成り立つ。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{Match, Regex};
+    /// let re = Regex::new("[0-9]+").unwrap();
+    /// let line = "a12b345".chars().collect::<Vec<char>>();
+    /// assert_eq!(re.find_from(&line, 0).unwrap(), Some(Match::new(1, 3)));
+    /// assert_eq!(re.find_from(&line, 3).unwrap(), Some(Match::new(4, 7)));
+    /// ```
+    pub fn find_from(&self, line: &[char], start: usize) -> Result<Option<Match>, EvalError> {
+        Ok(self
+            .search_captures(line, start, start)?
+            .and_then(|caps| caps[0]))
+    }
+
+    /// 文字単位に分解した文字列のstart番目から始まる最短のマッチを探し，その終了位置を返す
+    ///
+    /// マッチはstartの位置に固定され，繰り返しの優先順位や `longest_match` によらず
+    /// 終了位置が最も手前のマッチを報告する。
+    /// 字句解析器で，各トークンのパターンを優先順に現在の位置で試す用途に使う。
+    /// startが文字列の長さを超える場合やマッチしなかった場合は `Ok(None)` を返す。
+    /// 直前のマッチはないものとして，\G は文字列の先頭でのみ成り立つ。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("[a-z]+").unwrap();
+    /// let line = "12abc".chars().collect::<Vec<char>>();
+    /// assert_eq!(re.shortest_match_at(&line, 2).unwrap(), Some(3));
+    /// assert_eq!(re.shortest_match_at(&line, 0).unwrap(), None);
+    /// ```
+    pub fn shortest_match_at(
+        &self,
+        line: &[char],
+        start: usize,
+    ) -> Result<Option<usize>, EvalError> {
+        self.counters.eval_calls.fetch_add(1, Ordering::Relaxed);
+        evaluator::eval_shortest_from(&self.code, line, start)
+    }
+
+    /// 最も左にあるマッチを，マッチした部分文字列とともに `MatchResult` で返す
+    ///
+    /// マッチしなかった場合は `Ok(None)` を返す。
+    pub fn find_match<'t>(&self, line: &'t str) -> Result<Option<MatchResult<'t>>, EvalError> {
+        let Some(Match { start, end }) = self.find(line)? else {
+            return Ok(None);
+        };
+        let offsets = byte_offsets(line);
+        Ok(Some(MatchResult {
+            text: &line[offsets[start]..offsets[end]],
+            start,
+            end,
+        }))
+    }
+
+    /// `find` と同様だが，位置をバイト単位のオフセットで返す
+    ///
+    /// 返り値はそのまま `&line[start..end]` のように元の文字列のスライスに使える。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{Match, Regex};
+    /// let re = Regex::new("い+").unwrap();
+    /// let line = "あいいう";
+    /// assert_eq!(re.find(line).unwrap(), Some(Match::new(1, 3)));
+    /// assert_eq!(re.find_bytes(line).unwrap(), Some(Match::new(3, 9)));
+    /// ```
+    pub fn find_bytes(&self, line: &str) -> Result<Option<Match>, EvalError> {
+        Ok(self.captures_bytes(line)?.and_then(|caps| caps[0]))
+    }
+
+    /// `captures` と同様だが，各グループの位置をバイト単位のオフセットで返す
+    pub fn captures_bytes(&self, line: &str) -> Result<Option<Captures>, EvalError> {
+        let Some(caps) = self.captures(line)? else {
+            return Ok(None);
+        };
+
+        let offsets = byte_offsets(line);
+        let caps = caps
+            .into_iter()
+            .map(|span| span.map(|m| Match::new(offsets[m.start], offsets[m.end])))
+            .collect();
+        Ok(Some(caps))
+    }
+
+    /// `captures` と同様だが，各グループにマッチした部分文字列を返す
+    ///
+    /// マッチに参加しなかったグループはNoneとなる。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("(a+)(b+)").unwrap();
+    /// let caps = re.captures_str("xaab").unwrap().unwrap();
+    /// assert_eq!(caps, vec![Some("aab"), Some("aa"), Some("b")]);
+    /// ```
+    pub fn captures_str<'t>(
+        &self,
+        line: &'t str,
+    ) -> Result<Option<Vec<Option<&'t str>>>, EvalError> {
+        let Some(caps) = self.captures_bytes(line)? else {
+            return Ok(None);
+        };
+        Ok(Some(
+            caps.into_iter()
+                .map(|span| span.map(|m| &line[m.range()]))
+                .collect(),
+        ))
+    }
+
+    /// 文字列中の重複しないマッチの位置を先頭から順に列挙する
+    ///
+    /// 位置は文字単位のインデックスで，開始位置と終了位置を持つ `Match` となる。
+    /// 空文字列へのマッチは，直前のマッチの終了位置と同じ位置では報告されない。
+    /// たとえば，a* を "bab" に適用すると (0, 0), (1, 2), (3, 3) となる。
+    ///
+    /// \G は直前のマッチの終了位置 (最初のマッチでは文字列の先頭) で成り立つため，
+    /// \G から始まるパターンでは，字句解析のように隙間なく連続するマッチのみが列挙される。
+    ///
+    /// 評価中にエラーが発生した場合は，その時点で列挙を終了する。
+    /// 発生したエラーは `Matches::error` で取得できる。
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("\\G[a-z]+,").unwrap();
+    /// assert_eq!(re.find_iter("ab,c,?d,").collect::<Vec<_>>(), vec![(0, 3), (3, 5)]);
+    /// ```
+    pub fn find_iter<'t>(&self, line: &'t str) -> Matches<'_, 't> {
+        Matches {
+            re: self,
+            line,
+            chars: None,
+            pos: 0,
+            last_end: None,
+            done: !self.may_match(line), // 必須の固定文字列を含まない場合は何も列挙しない
+            error: None,
+        }
+    }
+
+    /// `find_iter` と同じマッチを列挙し，隙間なく隣接するマッチを1つの範囲にまとめる
+    ///
+    /// あるマッチの終了位置が次のマッチの開始位置と等しい場合，2つのマッチを結合する。
+    /// 1文字ずつの字句を連続した1つの字句として扱う場合などに使う。
+    /// 評価中にエラーが発生した場合は，そのエラーを返す。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("a").unwrap();
+    /// assert_eq!(re.find_iter_merged("aaba").unwrap(), vec![(0, 2), (3, 4)]);
+    /// ```
+    pub fn find_iter_merged(&self, line: &str) -> Result<Vec<Match>, EvalError> {
+        let mut result: Vec<Match> = Vec::new();
+        for m in self.try_find_iter(line) {
+            let m = m?;
+            match result.last_mut() {
+                Some(last) if last.end == m.start => last.end = m.end,
+                _ => result.push(m),
+            }
+        }
+        Ok(result)
+    }
+
+    /// `find_iter` と同じマッチを，文字列の末尾側から順に列挙する
+    ///
+    /// 評価器は先頭から走査するため，全てのマッチを `find_iter` で求めてから逆順に返す。
+    /// 計算量は `find_iter` で全てのマッチを列挙する場合と同じで，加えてマッチの数に比例する
+    /// メモリを使う。最初の要素を返す前に文字列全体を走査するため，途中で列挙を打ち切っても
+    /// 走査の量は減らない。評価中にエラーが発生した場合は，マッチを列挙せずにそのエラーを返す。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("[0-9]+").unwrap();
+    /// assert_eq!(
+    ///     re.rfind_iter("1 22 333").unwrap().collect::<Vec<_>>(),
+    ///     vec![(5, 8), (2, 4), (0, 1)]
+    /// );
+    /// ```
+    pub fn rfind_iter(
+        &self,
+        line: &str,
+    ) -> Result<impl DoubleEndedIterator<Item = Match>, EvalError> {
+        let matches = self.try_find_iter(line).collect::<Result<Vec<_>, _>>()?;
+        Ok(matches.into_iter().rev())
+    }
+
+    /// 複数行の文字列中の重複しないマッチを列挙し，開始と終了の位置を (行, 列) で返す
+    ///
+    /// 行と列はどちらも0から数え，列は行頭からの文字数となる。
+    /// 行は '\n' で区切られ，"\r\n" の '\r' も改行の一部とみなすため，
+    /// '\r' の位置や直後の位置は行末の列として報告される。
+    /// 列挙するマッチは `find_iter` と同じで，評価中にエラーが発生した場合はそのエラーを返す。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("b+").unwrap();
+    /// assert_eq!(
+    ///     re.find_iter_line_col("ab\r\nxbb").unwrap(),
+    ///     vec![((0, 1), (0, 2)), ((1, 1), (1, 3))]
+    /// );
+    /// ```
+    pub fn find_iter_line_col(&self, text: &str) -> Result<Vec<LineColRange>, EvalError> {
+        let chars = text.chars().collect::<Vec<char>>();
+
+        // 各行の先頭の位置 (文字単位)
+        let mut starts = vec![0];
+        starts.extend(
+            chars
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| **c == '\n')
+                .map(|(i, _)| i + 1),
+        );
+
+        let line_col = |pos: usize| {
+            let line = starts.partition_point(|s| *s <= pos) - 1;
+            let end = match starts.get(line + 1) {
+                // 改行の '\n' と，その直前の '\r' を除いた行末
+                Some(next) if *next >= 2 && chars[next - 2] == '\r' => next - 2,
+                Some(next) => next - 1,
+                None => chars.len(),
+            };
+            (line, pos.min(end) - starts[line])
+        };
+
+        self.try_find_iter(text)
+            .map(|m| m.map(|Match { start, end }| (line_col(start), line_col(end))))
+            .collect()
+    }
+
+    /// 文字列中の重複しないマッチを先頭から順に列挙し，各マッチのキャプチャを返す
+    ///
+    /// 空文字列へのマッチの扱いは `find_iter` と同じ。
+    /// 評価中にエラーが発生した場合は，そのエラーを返して列挙を終了する。
+    pub(crate) fn captures_iter(
+        &self,
+        line: &str,
+    ) -> impl Iterator<Item = Result<Captures, EvalError>> {
+        let mut matches = self.find_iter(line);
+        std::iter::from_fn(move || matches.next_captures())
+    }
+
+    /// 文字列中の重複しないマッチを先頭から順に列挙する
+    ///
+    /// `find_iter` と同じマッチを列挙するが，評価中にエラーが発生した場合は，
+    /// そのエラーを返して列挙を終了する。
+    fn try_find_iter(&self, line: &str) -> impl Iterator<Item = Result<Match, EvalError>> {
+        self.captures_iter(line)
+            .filter_map(|caps| caps.map(|caps| caps[0]).transpose())
+    }
+
+    /// マッチした部分を全てreplacementで置換した文字列を返す
+    ///
+    /// replacementでは以下の記法でキャプチャグループを参照できる。
+    ///
+    /// - `$n`, `${n}`: n番目のグループにマッチした文字列 (`$0` はマッチ全体)
+    /// - `${name}`: 名前付きグループにマッチした文字列
+    /// - `$$`: `$` という文字
+    ///
+    /// 存在しないグループやマッチに参加しなかったグループは空文字列に置換される。
+    ///
+    /// また，以下の記法で置換後の文字列の大文字と小文字を変換できる。
+    /// 変換はグループの参照で展開された文字列にも適用される。
+    ///
+    /// - `\U`: 以降を `\E` まで大文字に変換する
+    /// - `\L`: 以降を `\E` まで小文字に変換する
+    /// - `\E`: 変換を終了する
+    /// - `\\`: `\` という文字
+    ///
+    /// それ以外の `\` は通常の文字として扱われる。
+    ///
+    /// 空文字列にマッチした場合は，次の1文字をそのままコピーしてから探索を再開する。
+    /// 直前のマッチの直後の空マッチは置換しないため，a* で "bab" を "-" に置換すると
+    /// "-b-b-" となる。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("(a+)(?P<b>b*)").unwrap();
+    /// assert_eq!(re.replace_all("xaabyab", "[${b}$1]").unwrap(), "x[baa]y[ba]");
+    /// assert_eq!(re.replace_all("xaby", "\\U$1\\E${b}").unwrap(), "xAby");
+    /// ```
+    pub fn replace_all(&self, line: &str, replacement: &str) -> Result<String, EvalError> {
+        let mut out = String::new();
+        self.replace_all_into(line, replacement, &mut out)?;
+        Ok(out)
+    }
+
+    /// `replace_all` と同様に置換し，結果をoutに書き込む
+    ///
+    /// outは最初にクリアされる。ループ内で同じバッファを使い回すことで，
+    /// 置換のたびに `String` を確保するコストを避けられる。
+    pub fn replace_all_into(
+        &self,
+        line: &str,
+        replacement: &str,
+        out: &mut String,
+    ) -> Result<(), EvalError> {
+        self.replace_into(line, replacement, 0, out)
+    }
+
+    /// 文字単位に分解した文字列中の全てのマッチを，replacementの文字列で置換する
+    ///
+    /// 評価器と同じ文字単位のまま処理するため，UTF-8への変換と分解を繰り返さずに済む。
+    /// replacementはそのまま挿入され，`replace_all` のようなグループの参照や大文字と小文字の変換は行わない。
+    /// 列挙するマッチは `find_iter` と同じ。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("a+").unwrap();
+    /// let line = "baaad".chars().collect::<Vec<_>>();
+    /// assert_eq!(re.replace_all_chars(&line, &['X']).unwrap(), vec!['b', 'X', 'd']);
+    /// ```
+    pub fn replace_all_chars(
+        &self,
+        line: &[char],
+        replacement: &[char],
+    ) -> Result<Vec<char>, EvalError> {
+        let mut out = Vec::with_capacity(line.len());
+        let mut pos = 0; // 次の探索開始位置
+        let mut last_end = None; // 直前のマッチの終了位置
+        while pos <= line.len() {
+            let Some(Match { start, end }) = self
+                .search_captures(line, pos, last_end.unwrap_or(0))?
+                .and_then(|caps| caps[0])
+            else {
+                break;
+            };
+            if start == end && last_end == Some(end) {
+                // 直前のマッチに隣接する空マッチは置換せず，1文字進めて再探索
+                pos = end + 1;
+                continue;
+            }
+
+            out.extend_from_slice(&line[last_end.unwrap_or(0)..start]);
+            out.extend_from_slice(replacement);
+            pos = if start == end { end + 1 } else { end };
+            last_end = Some(end);
+        }
+        out.extend_from_slice(&line[last_end.unwrap_or(0)..]);
+        Ok(out)
+    }
+
+    /// 先頭から最大limit個のマッチをreplacementで置換した文字列を返す
+    ///
+    /// limitが0の場合は全てのマッチを置換し，`replace_all` と同じ結果となる。
+    /// replacementの記法は `replace_all` と同じ。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("a+").unwrap();
+    /// assert_eq!(re.replacen("axaaxa", "-", 2).unwrap(), "-x-xa");
+    /// ```
+    pub fn replacen(
+        &self,
+        line: &str,
+        replacement: &str,
+        limit: usize,
+    ) -> Result<String, EvalError> {
+        let mut out = String::new();
+        self.replace_into(line, replacement, limit, &mut out)?;
+        Ok(out)
+    }
+
+    /// 先頭から最大limit個のマッチを置換し，結果をoutに書き込む (limitが0なら全て)
+    fn replace_into(
+        &self,
+        line: &str,
+        replacement: &str,
+        limit: usize,
+        out: &mut String,
+    ) -> Result<(), EvalError> {
+        out.clear();
+
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        let offsets = byte_offsets(line);
+        let mut last = 0; // 直前のマッチの終了位置 (バイト単位)
+        for caps in self.captures_iter(line).take(limit) {
+            let caps = caps?;
+            let Some(Match { start, end }) = caps[0] else {
+                continue;
+            };
+
+            out.push_str(&line[last..offsets[start]]);
+            self.expand(replacement, line, &offsets, &caps, out);
+            last = offsets[end];
+        }
+        out.push_str(&line[last..]);
+
+        Ok(())
+    }
+
+    /// 置換文字列中のグループ参照を展開してoutに追加する
+    fn expand(
+        &self,
+        replacement: &str,
+        line: &str,
+        offsets: &[usize],
+        caps: &Captures,
+        out: &mut String,
+    ) {
+        // グループにマッチした文字列を返す
+        let group = |index: Option<usize>| {
+            let m = index.and_then(|i| caps.get(i).copied().flatten())?;
+            Some(&line[offsets[m.start]..offsets[m.end]])
+        };
+
+        let mut case = CaseConv::Keep; // 大文字と小文字の変換状態
+        let mut it = replacement.chars().peekable();
+        while let Some(c) = it.next() {
+            if c == '\\' {
+                match it.peek() {
+                    Some('U') => case = CaseConv::Upper,
+                    Some('L') => case = CaseConv::Lower,
+                    Some('E') => case = CaseConv::Keep,
+                    Some('\\') => case.push(out, "\\"),
+                    _ => {
+                        case.push(out, "\\");
+                        continue;
+                    }
+                }
+                it.next();
+                continue;
+            }
+            if c != '$' {
+                case.push(out, c.encode_utf8(&mut [0; 4]));
+                continue;
+            }
+
+            match it.peek() {
+                Some('$') => {
+                    it.next();
+                    out.push('$');
+                }
+                Some(c) if c.is_ascii_digit() => {
+                    let mut n = String::new();
+                    while let Some(c) = it.next_if(|c| c.is_ascii_digit()) {
+                        n.push(c);
+                    }
+                    case.push(out, group(n.parse().ok()).unwrap_or(""));
+                }
+                Some('{') => {
+                    let mut lookahead = it.clone();
+                    lookahead.next();
+                    let mut name = String::new();
+                    let mut closed = false;
+                    for c in lookahead.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        name.push(c);
+                    }
+
+                    if closed && !name.is_empty() {
+                        it = lookahead;
+                        let index = name.parse().ok().or_else(|| self.name_to_index(&name));
+                        case.push(out, group(index).unwrap_or(""));
+                    } else {
+                        out.push('$'); // 閉じ括弧がない場合は通常の文字として扱う
+                    }
+                }
+                _ => out.push('$'),
+            }
+        }
+    }
+
+    /// マッチした部分を区切りとして文字列を分割する
+    ///
+    /// 評価中にエラーが発生した場合は，そのエラーを返す。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new(",|;").unwrap();
+    /// assert_eq!(re.split("a,b;;c").unwrap(), vec!["a", "b", "", "c"]);
+    /// ```
+    pub fn split<'t>(&self, line: &'t str) -> Result<Vec<&'t str>, EvalError> {
+        Ok(self
+            .split_with_delimiters(line)?
+            .into_iter()
+            .map(|(text, _)| text)
+            .collect())
+    }
+
+    /// `split` と同様だが，最大でlimit個の断片に分割する
+    ///
+    /// `str::splitn` と同じく，最後の断片には残りの文字列が区切りを含めてそのまま入る。
+    /// limitが1の場合は文字列全体を，0の場合は空のベクタを返す。
+    /// 評価中にエラーが発生した場合は，そのエラーを返す。
+    /// 区切りはlimit - 1個まで探索するため，それ以降の位置で発生するエラーは報告されない。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new(",").unwrap();
+    /// assert_eq!(re.splitn("a,b,c,d", 2).unwrap(), vec!["a", "b,c,d"]);
+    /// ```
+    pub fn splitn<'t>(&self, line: &'t str, limit: usize) -> Result<Vec<&'t str>, EvalError> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        let offsets = byte_offsets(line);
+        let mut result = Vec::new();
+        let mut last = 0; // 直前の区切りの終了位置 (バイト単位)
+        for m in self.try_find_iter(line).take(limit - 1) {
+            let Match { start, end } = m?;
+            result.push(&line[last..offsets[start]]);
+            last = offsets[end];
+        }
+        result.push(&line[last..]);
+
+        Ok(result)
+    }
+
+    /// `split` と同様だが，末尾の空の断片を1つだけ取り除く
+    ///
+    /// `str::split_terminator` と同じく，区切りを行の終端記号として扱う場合に使う。
+    /// 末尾で区切りが連続する場合は最後の空の断片のみが除かれ，空の文字列に対しては空のベクタを返す。
+    /// 評価中にエラーが発生した場合は，そのエラーを返す。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new(",").unwrap();
+    /// assert_eq!(re.split_terminator("a,b,").unwrap(), vec!["a", "b"]);
+    /// assert_eq!(re.split_terminator("a,b,,").unwrap(), vec!["a", "b", ""]);
+    /// ```
+    pub fn split_terminator<'t>(&self, line: &'t str) -> Result<Vec<&'t str>, EvalError> {
+        let mut result = self.split(line)?;
+        if result.last() == Some(&"") {
+            result.pop();
+        }
+        Ok(result)
+    }
+
+    /// `split` と同様に文字列を分割し，各断片とその直後の区切り文字列を組にして返す
+    ///
+    /// 最後の断片の区切りはNoneとなる。全ての断片と区切りを順に連結すると元の文字列になる。
+    /// 区切りが隣接する場合や，文字列の先頭・末尾で区切りにマッチした場合は，空の断片が含まれる。
+    /// 評価中にエラーが発生した場合は，そのエラーを返す。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new(",").unwrap();
+    /// assert_eq!(
+    ///     re.split_with_delimiters(",a").unwrap(),
+    ///     vec![("", Some(",")), ("a", None)]
+    /// );
+    /// ```
+    pub fn split_with_delimiters<'t>(
+        &self,
+        line: &'t str,
+    ) -> Result<Vec<(&'t str, Option<&'t str>)>, EvalError> {
+        let offsets = byte_offsets(line);
+        let mut result = Vec::new();
+        let mut last = 0; // 直前の区切りの終了位置 (バイト単位)
+
+        for m in self.try_find_iter(line) {
+            let Match { start, end } = m?;
+            let (start, end) = (offsets[start], offsets[end]);
+            result.push((&line[last..start], Some(&line[start..end])));
+            last = end;
+        }
+        result.push((&line[last..], None));
+
+        Ok(result)
+    }
+
+    /// selfの後にotherが続く正規表現を生成する
+    ///
+    /// 文字列として連結する場合と異なり，エスケープし直す必要がない。
+    /// `as_str` はそれぞれの文字列を (?:...) で囲んで連結したものとなる。
+    /// otherのキャプチャグループは，selfのグループの後に続く番号に付け替えられる。
+    /// 同じ名前のグループがある場合，`name_to_index` はselfのグループを返す。
+    /// 最長一致モードなどのオプションはselfのものを引き継ぐ。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{Match, Regex};
+    /// let re = Regex::new("(a+)").unwrap().concat(&Regex::new("(b+)").unwrap()).unwrap();
+    /// assert_eq!(re.captures("xaab").unwrap().unwrap()[2], Some(Match::new(3, 4)));
+    /// ```
+    pub fn concat(&self, other: &Regex) -> Result<Regex, RegexError> {
+        let expr = format!("(?:{})(?:{})", self.expr, other.expr);
+        self.combine(other, "concat", expr, |e1, e2| AST::Seq(vec![e1, e2]))
+    }
+
+    /// selfとotherのいずれかにマッチする正規表現を生成する
+    ///
+    /// `concat` と同様に，otherのキャプチャグループはselfのグループの後に続く番号となる。
+    /// `as_str` はそれぞれの文字列を (?:...) で囲んで | でつないだものとなる
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{Match, Regex};
+    /// let re = Regex::new("ab").unwrap().alternate(&Regex::new("c+").unwrap()).unwrap();
+    /// assert_eq!(re.find("xccab").unwrap(), Some(Match::new(1, 3)));
+    /// ```
+    pub fn alternate(&self, other: &Regex) -> Result<Regex, RegexError> {
+        let expr = format!("(?:{})|(?:{})", self.expr, other.expr);
+        self.combine(other, "alternate", expr, |e1, e2| {
+            AST::Or(Box::new(e1), Box::new(e2))
+        })
+    }
+
+    /// selfとotherのASTをfで結合し，結合後の文字列をexprとしてコード生成する
+    ///
+    /// キャッシュのキーは，結合の種類opと両者のキーから作る
+    fn combine(
+        &self,
+        other: &Regex,
+        op: &str,
+        expr: String,
+        f: impl FnOnce(AST, AST) -> AST,
+    ) -> Result<Regex, RegexError> {
+        let mut rhs = other.ast.clone();
+        shift_groups(&mut rhs, self.names.len() - 1);
+        let names = self
+            .names
+            .iter()
+            .chain(&other.names[1..])
+            .cloned()
+            .collect();
+
+        let key = format!("{op}[{}][{}]", self.cache_key, other.cache_key);
+        let builder = RegexBuilder {
+            longest_match: self.longest_match,
+            backtrack_limit: self.backtrack_limit,
+            recursion_limit: self.recursion_limit,
+            strict_end: self.strict_end,
+            ..RegexBuilder::new(&expr)
+        };
+        let mut re = builder.compile(f(self.ast.clone(), rhs), names)?;
+        re.cache_key = key;
+        Ok(re)
+    }
+
+    /// 名前付きグループ `(?P<name>...)` のグループ番号を返す
+    pub fn name_to_index(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n.as_deref() == Some(name))
+    }
+
+    /// 最も左にあるマッチについて，名前付きグループの位置を返す
+    ///
+    /// 該当する名前のグループが存在しない場合や，マッチしなかった場合，
+    /// グループがマッチに参加しなかった場合は `Ok(None)` を返す
+    pub fn captures_name(&self, line: &str, name: &str) -> Result<Option<Match>, EvalError> {
+        let Some(index) = self.name_to_index(name) else {
+            return Ok(None);
+        };
+        Ok(self.captures(line)?.and_then(|caps| caps[index]))
+    }
+}
+
+impl fmt::Display for Regex {
+    /// 元の正規表現の文字列 (`as_str`) を表示する
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expr)
+    }
+}
+
+impl FromStr for Regex {
+    type Err = RegexError;
+
+    /// `Regex::new` と同じ
+    fn from_str(expr: &str) -> Result<Self, Self::Err> {
+        Regex::new(expr)
+    }
+}
+
+impl TryFrom<&str> for Regex {
+    type Error = RegexError;
+
+    /// `Regex::new` と同じ
+    fn try_from(expr: &str) -> Result<Self, Self::Error> {
+        Regex::new(expr)
+    }
+}
+
+/// `Regex::find_match` が返す，マッチした部分文字列とその位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchResult<'t> {
+    text: &'t str, // マッチした部分文字列
+    start: usize,  // 開始位置 (文字単位)
+    end: usize,    // 終了位置 (文字単位)
+}
+
+impl<'t> MatchResult<'t> {
+    /// マッチの開始位置を文字単位のインデックスで返す
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// マッチの終了位置を文字単位のインデックスで返す
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// マッチした部分文字列を返す
+    pub fn as_str(&self) -> &'t str {
+        self.text
+    }
+
+    /// マッチした部分文字列をdelimで区切り，n番目 (0始まり) の要素を返す
+    ///
+    /// タブ区切りのような表形式のデータから列を取り出すために使う。
+    /// 要素がn個以下の場合はNoneを返す。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::Regex;
+    /// let re = Regex::new("id=[^ ]+").unwrap();
+    /// let m = re.find_match("log id=7,alice,admin end").unwrap().unwrap();
+    /// assert_eq!(m.field(1, ','), Some("alice"));
+    /// assert_eq!(m.field(3, ','), None);
+    /// ```
+    pub fn field(&self, n: usize, delim: char) -> Option<&'t str> {
+        self.text.split(delim).nth(n)
+    }
+}
+
+/// `Regex::find_iter` が返す，重複しないマッチの位置を列挙するイテレータ
+///
+/// 'rは正規表現，'tは入力文字列の寿命で，構造体のフィールドなどに保持できる。
+///
+/// # 利用例
+///
+/// ```
+/// use regex_engine::{Matches, Regex};
+/// struct Tokens<'r, 't> {
+///     matches: Matches<'r, 't>,
+/// }
+/// let re = Regex::new("[0-9]+").unwrap();
+/// let tokens = Tokens { matches: re.find_iter("a1b22") };
+/// assert_eq!(tokens.matches.collect::<Vec<_>>(), vec![(1, 2), (3, 5)]);
+/// ```
+#[derive(Debug)]
+pub struct Matches<'r, 't> {
+    re: &'r Regex,
+    line: &'t str,
+    chars: Option<Vec<char>>, // 入力を文字単位に分解したもの (最初の探索時に生成)
+    pos: usize,               // 次の探索開始位置
+    last_end: Option<usize>,  // 直前のマッチの終了位置
+    done: bool,               // 列挙を終了したか
+    error: Option<EvalError>, // 列挙を終了させたエラー
+}
+
+impl Matches<'_, '_> {
+    /// 評価中のエラーで列挙を終了した場合，そのエラーを返す
+    ///
+    /// イテレータはエラーが発生するとNoneを返して終了するため，
+    /// 文字列の末尾まで走査したのかエラーで打ち切られたのかをこのメソッドで区別する。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex_engine::{EvalError, RegexBuilder};
+    /// let re = RegexBuilder::new("(a|b)*,").backtrack_limit(3).build().unwrap();
+    /// let mut matches = re.find_iter("ababababab,");
+    /// assert_eq!(matches.next(), None);
+    /// assert!(matches!(matches.error(), Some(EvalError::BacktrackLimitExceeded)));
+    /// ```
+    pub fn error(&self) -> Option<&EvalError> {
+        self.error.as_ref()
+    }
+
+    /// 次のマッチを探し，キャプチャを返す
+    ///
+    /// エラーが発生した場合は，そのエラーを返して列挙を終了する
+    fn next_captures(&mut self) -> Option<Result<Captures, EvalError>> {
+        let line = self
+            .chars
+            .get_or_insert_with(|| self.line.chars().collect());
+        while !self.done && self.pos <= line.len() {
+            let caps = match self
+                .re
+                .search_captures(line, self.pos, self.last_end.unwrap_or(0))
+            {
+                Ok(Some(caps)) => caps,
+                Ok(None) => break,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            };
+            let Match { start, end } = caps[0]?;
+
+            if start == end && self.last_end == Some(end) {
+                // 直前のマッチに隣接する空マッチは報告せず，1文字進めて再探索
+                self.pos = end + 1;
+                continue;
+            }
+
+            self.pos = if start == end { end + 1 } else { end };
+            self.last_end = Some(end);
+            return Some(Ok(caps));
+        }
+
+        self.done = true;
+        None
+    }
+}
+
+impl Iterator for Matches<'_, '_> {
+    type Item = Match;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_captures()? {
+            Ok(caps) => caps[0],
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+}
+
+/// 置換文字列中の \\U, \\L による大文字と小文字の変換状態
+enum CaseConv {
+    Keep,  // 変換しない
+    Upper, // 大文字に変換する
+    Lower, // 小文字に変換する
+}
+
+impl CaseConv {
+    /// 変換状態に応じてsを変換し，outに追加する
+    fn push(&self, out: &mut String, s: &str) {
+        match self {
+            CaseConv::Keep => out.push_str(s),
+            CaseConv::Upper => out.push_str(&s.to_uppercase()),
+            CaseConv::Lower => out.push_str(&s.to_lowercase()),
+        }
+    }
+}
+
+/// `Regex::alternation_of` で固定文字列の集合を表すトライ木の節点
+#[derive(Debug, Default)]
+struct TrieNode {
+    end: bool,                          // この節点で終わる文字列があるか
+    children: BTreeMap<char, TrieNode>, // 次の文字ごとの子の節点
+}
+
+impl TrieNode {
+    /// 文字列sを追加する
+    fn insert(&mut self, s: &str) {
+        let node = s
+            .chars()
+            .fold(self, |node, c| node.children.entry(c).or_default());
+        node.end = true;
+    }
+
+    /// この節点以降の文字列のいずれかにマッチするASTを返す
+    ///
+    /// 子の節点ごとの選択肢は先頭の文字が異なるため，右に入れ子になった選択として
+    /// コード生成で1つの split_n にまとめられる。
+    /// この節点で終わる文字列がある場合は，続きを省略可能 (欲張り) とし長い方を優先する
+    fn to_ast(&self) -> AST {
+        let mut branches = self.children.iter().map(|(c, child)| match child.to_ast() {
+            AST::Seq(v) if v.is_empty() => AST::Char(*c),
+            AST::Seq(mut v) => {
+                v.insert(0, AST::Char(*c));
+                AST::Seq(v)
+            }
+            e => AST::Seq(vec![AST::Char(*c), e]),
+        });
+        let Some(first) = branches.next_back() else {
+            return AST::Seq(Vec::new());
+        };
+        let alt = branches
+            .rev()
+            .fold(first, |acc, e| AST::Or(Box::new(e), Box::new(acc)));
+        if self.end {
+            AST::Question(Box::new(alt))
+        } else {
+            alt
+        }
+    }
+}
+
+/// 命令列が Char の並びと Match のみからなる場合に，その固定文字列を返す
+fn literal_program(code: &[Instruction]) -> Option<String> {
+    let (Instruction::Match, chars) = code.split_last()? else {
+        return None;
+    };
+    chars
+        .iter()
+        .map(|inst| match inst {
+            Instruction::Char(c) => Some(*c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// AST中のキャプチャグループの番号の最大値を返す (グループを含まない場合は0)
+fn max_group(ast: &AST) -> usize {
+    match ast {
+        AST::Capture(n, e) => (*n).max(max_group(e)),
+        AST::Plus(e) | AST::Star(e) | AST::Question(e) | AST::Repeat(e, _, _) | AST::Lazy(e) => {
+            max_group(e)
+        }
+        AST::Or(e1, e2) => max_group(e1).max(max_group(e2)),
+        AST::Seq(v) => v.iter().map(max_group).max().unwrap_or(0),
+        AST::Char(_)
+        | AST::CharCI(_)
+        | AST::Class(_)
+        | AST::Start
+        | AST::End
+        | AST::LineStart
+        | AST::LineEnd
+        | AST::WordBoundary
+        | AST::AnchorPrevEnd => 0,
+    }
+}
+
+/// AST中のキャプチャグループの番号をoffsetだけずらす
+fn shift_groups(ast: &mut AST, offset: usize) {
+    match ast {
+        AST::Capture(n, e) => {
+            *n += offset;
+            shift_groups(e, offset);
+        }
+        AST::Plus(e) | AST::Star(e) | AST::Question(e) | AST::Repeat(e, _, _) | AST::Lazy(e) => {
+            shift_groups(e, offset)
+        }
+        AST::Or(e1, e2) => {
+            shift_groups(e1, offset);
+            shift_groups(e2, offset);
+        }
+        AST::Seq(v) => v.iter_mut().for_each(|e| shift_groups(e, offset)),
+        AST::Char(_)
+        | AST::CharCI(_)
+        | AST::Class(_)
+        | AST::Start
+        | AST::End
+        | AST::LineStart
+        | AST::LineEnd
+        | AST::WordBoundary
+        | AST::AnchorPrevEnd => (),
+    }
+}
+
+/// (行, 列) で表したマッチの開始と終了の位置
+type LineColRange = ((usize, usize), (usize, usize));
+
+/// 文字単位のインデックスからバイト単位のオフセットへの対応表を作る
+///
+/// 対応表の長さは文字数 + 1 で，最後の要素は文字列のバイト長となる
+fn byte_offsets(line: &str) -> Vec<usize> {
+    line.char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(line.len()))
+        .collect()
+}
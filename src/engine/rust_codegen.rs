@@ -0,0 +1,207 @@
+//! rust_codegen.rs generates Rust source code of a matching function from a regex.
+
+use super::{
+    class::CharClass,
+    codegen::{self, CodeGenError},
+    error::RegexError,
+    parser, AssertKind, Instruction,
+};
+use std::fmt::Write;
+
+/// 正規表現を，その正規表現専用のマッチング関数のRustのソースコードに変換する
+///
+/// 生成される関数は `fn fn_name(input: &[char]) -> bool` で，
+/// inputのいずれかの位置でマッチするかを返す (`Regex::is_match` と同じ)。
+/// 命令列をpcのループ中の `match` として展開し，分岐は関数内のスタックでバックトラックするため，
+/// 実行時のパースやコード生成が不要で，このクレートへの依存もない。
+/// \G は直前のマッチがないものとして文字列の先頭でのみ成り立つ。
+/// 後方への分岐 (繰り返し) を含む場合は，評価済みの (pc, sp) の組を記録して再評価しないため，
+/// (a*)* のように空文字列にマッチする繰り返しでも停止し，実行時間は入力長と命令数の積に比例する。
+///
+/// fn_nameがRustの識別子として不正な場合は `CodeGenError::InvalidFunctionName` を，
+/// Unicodeプロパティなど，ソースコードとして表現できない文字の判定を含む場合は
+/// `CodeGenError::UnsupportedMatcher` を返す。
+///
+/// # 利用例
+///
+/// ```
+/// let src = regex_engine::codegen_rust("a+b", "is_ab").unwrap();
+/// assert!(src.starts_with("fn is_ab(input: &[char]) -> bool {"));
+/// ```
+pub fn codegen_rust(expr: &str, fn_name: &str) -> Result<String, RegexError> {
+    if !is_identifier(fn_name) {
+        return Err(CodeGenError::InvalidFunctionName(fn_name.to_string()).into());
+    }
+    let ast = parser::parse(expr)?;
+    let code = codegen::get_code(&ast)?;
+
+    // 各命令を，スレッドを続行する場合はpcとspを更新し，失敗する場合はbreakする文に変換する
+    let mut arms = String::new();
+    for (pc, inst) in code.iter().enumerate() {
+        let next = pc + 1;
+        let body = match inst {
+            Instruction::Char(_)
+            | Instruction::CharCI(_)
+            | Instruction::Class(_)
+            | Instruction::CharNot(_) => {
+                let cond = char_condition(inst)?;
+                format!(
+                    "match input.get(sp) {{ Some(&c) if {cond} => {{ pc = {next}; sp += 1; }} _ => break, }}"
+                )
+            }
+            Instruction::Assert(AssertKind::Start | AssertKind::PrevEnd) => {
+                format!("if sp == 0 {{ pc = {next}; }} else {{ break; }}")
+            }
+            Instruction::Assert(AssertKind::End) => {
+                format!("if sp == input.len() {{ pc = {next}; }} else {{ break; }}")
+            }
+            Instruction::Assert(AssertKind::EndNewline) => format!(
+                "if sp == input.len() || (sp + 1 == input.len() && input[sp] == '\\n') {{ pc = {next}; }} else {{ break; }}"
+            ),
+            Instruction::Assert(AssertKind::LineStart) => {
+                format!("if sp == 0 || input[sp - 1] == '\\n' {{ pc = {next}; }} else {{ break; }}")
+            }
+            Instruction::Assert(AssertKind::LineEnd) => format!(
+                "if sp == input.len() || input[sp] == '\\n' {{ pc = {next}; }} else {{ break; }}"
+            ),
+            Instruction::Assert(AssertKind::WordBoundary) => format!(
+                "if is_word(sp.checked_sub(1).map(|i| input[i])) != is_word(input.get(sp).copied()) {{ pc = {next}; }} else {{ break; }}"
+            ),
+            Instruction::Match | Instruction::MatchN(_) => "return true;".to_string(),
+            Instruction::Jump(addr) => format!("pc = {addr};"),
+            Instruction::Split(addr1, addr2) => {
+                format!("stack.push(({addr2}, sp)); pc = {addr1};")
+            }
+            Instruction::SplitN(addrs) => match addrs.split_first() {
+                Some((first, rest)) => {
+                    let mut body = String::new();
+                    for addr in rest.iter().rev() {
+                        write!(body, "stack.push(({addr}, sp)); ").unwrap();
+                    }
+                    write!(body, "pc = {first};").unwrap();
+                    body
+                }
+                None => "break;".to_string(),
+            },
+            Instruction::Save(_) => format!("pc = {next};"),
+        };
+        writeln!(arms, "                {pc} => {{ {body} }}").unwrap();
+    }
+
+    let mut src = String::new();
+    writeln!(src, "fn {fn_name}(input: &[char]) -> bool {{").unwrap();
+    writeln!(src, "    // {expr:?}").unwrap();
+    writeln!(
+        src,
+        "    // 各開始位置のスレッドを (pc, sp) としてスタックに積む"
+    )
+    .unwrap();
+    writeln!(
+        src,
+        "    let mut stack: Vec<(usize, usize)> = (0..=input.len()).rev().map(|sp| (0, sp)).collect();"
+    )
+    .unwrap();
+    let has_loop = code
+        .iter()
+        .enumerate()
+        .any(|(pc, inst)| inst.successors(pc).iter().any(|addr| *addr <= pc));
+    if has_loop {
+        writeln!(
+            src,
+            "    // 評価済みの (pc, sp) を記録し，空文字列にマッチする繰り返しでも停止させる"
+        )
+        .unwrap();
+        writeln!(
+            src,
+            "    let mut visited = vec![false; {} * (input.len() + 1)];",
+            code.len()
+        )
+        .unwrap();
+    }
+    if code.contains(&Instruction::Assert(AssertKind::WordBoundary)) {
+        writeln!(
+            src,
+            "    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric() || c == '_');"
+        )
+        .unwrap();
+    }
+    writeln!(src, "    while let Some((mut pc, mut sp)) = stack.pop() {{").unwrap();
+    writeln!(src, "        loop {{").unwrap();
+    if has_loop {
+        writeln!(src, "            let state = pc * (input.len() + 1) + sp;").unwrap();
+        writeln!(src, "            if visited[state] {{ break; }}").unwrap();
+        writeln!(src, "            visited[state] = true;").unwrap();
+    }
+    writeln!(src, "            match pc {{").unwrap();
+    src.push_str(&arms);
+    writeln!(src, "                _ => unreachable!(),").unwrap();
+    writeln!(src, "            }}").unwrap();
+    writeln!(src, "        }}").unwrap();
+    writeln!(src, "    }}").unwrap();
+    writeln!(src, "    false").unwrap();
+    writeln!(src, "}}").unwrap();
+    Ok(src)
+}
+
+/// 文字を消費する命令について，文字cがマッチする条件を表すRustの式を返す
+fn char_condition(inst: &Instruction) -> Result<String, CodeGenError> {
+    let cond = match inst {
+        Instruction::Char(x) => format!("c == {x:?}"),
+        Instruction::CharCI(x) => {
+            format!("(c == {x:?} || c.to_lowercase().eq({x:?}.to_lowercase()))")
+        }
+        Instruction::CharNot(x) => format!("c != {x:?}"),
+        Instruction::Class(class) => class_condition(class)?,
+        _ => "false".to_string(),
+    };
+    Ok(cond)
+}
+
+/// 文字クラスについて，文字cがマッチする条件を表すRustの式を返す
+///
+/// `CharClass::matches` と同じ判定となる
+fn class_condition(class: &CharClass) -> Result<String, CodeGenError> {
+    if let Some(m) = class.matchers.first() {
+        return Err(CodeGenError::UnsupportedMatcher(m.name.clone()));
+    }
+
+    let ranges = class
+        .ranges
+        .iter()
+        .map(|(lo, hi)| format!("({lo:?}..={hi:?}).contains(&x)"))
+        .collect::<Vec<_>>();
+    let contains = if ranges.is_empty() {
+        "false".to_string()
+    } else {
+        ranges.join(" || ")
+    };
+
+    let found = if class.case_insensitive {
+        format!(
+            "{{ let f = |x: char| {contains}; f(c) || c.to_lowercase().chain(c.to_uppercase()).any(f) }}"
+        )
+    } else {
+        format!("{{ let x = c; {contains} }}")
+    };
+    Ok(if class.negated {
+        format!("!{found}")
+    } else {
+        found
+    })
+}
+
+/// nameがRustの識別子 (予約語を除く) として使えるかを判定する
+fn is_identifier(name: &str) -> bool {
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+        "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+        "true", "type", "unsafe", "use", "where", "while", "_",
+    ];
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&name)
+}
@@ -0,0 +1,181 @@
+//! ser_ast.rs provides a flat, serializable representation of AST.
+
+use super::{class::CharClass, parser::AST};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// SerAstとASTの間の変換エラーを表現するための型
+#[derive(Debug)]
+pub enum SerAstError {
+    Empty,                      // ノードが1つもない
+    InvalidIndex(usize, usize), // 子ノードの参照先が不正 (ノード番号, 参照先)
+    SharedNode(usize),          // 同じノードが複数回参照されている
+    UnusedNode(usize),          // ルートから参照されないノードがある
+    InvalidGroup(usize),        // 不正なグループ番号 (ノード番号)
+    UnsupportedMatcher(String), // シリアライズできない文字の判定を含む (判定の名前)
+}
+
+impl Display for SerAstError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SerAstError: {:?}", self)
+    }
+}
+
+impl Error for SerAstError {}
+
+/// SerAstの1ノード
+///
+/// 子ノードはBoxではなく，SerAst::nodes中のインデックスで参照する
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SerNode {
+    Char(char),
+    CharCI(char),
+    Class(CharClass),
+    Start,
+    End,
+    LineStart,
+    LineEnd,
+    AnchorPrevEnd,
+    WordBoundary,
+    Plus(usize),
+    Star(usize),
+    Question(usize),
+    Or(usize, usize),
+    Seq(Vec<usize>),
+    Capture(usize, usize),               // (グループ番号, 式)
+    Repeat(usize, usize, Option<usize>), // (式, 下限, 上限)
+    Lazy(usize),                         // 最短一致の繰り返し
+}
+
+/// シリアライズ可能なフラットなAST
+///
+/// ノードは後行順に並べられ，子ノードは必ず親ノードより前に置かれる。
+/// 最後のノードがルートとなる。
+///
+/// # 利用例
+///
+/// ```
+/// use regex_engine::{parse, SerAst, AST};
+/// let ast = parse("a(b|c)*").unwrap();
+/// let ser = SerAst::try_from(&ast).unwrap();
+/// assert_eq!(AST::try_from(ser).unwrap(), ast);
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SerAst {
+    pub nodes: Vec<SerNode>,
+}
+
+impl TryFrom<&AST> for SerAst {
+    type Error = SerAstError;
+
+    /// ASTをSerAstに変換する
+    ///
+    /// \p{Greek} のようなUnicodeプロパティや `RegexBuilder::char_matcher` で登録した判定は
+    /// シリアライズできず，復元すると何にもマッチしなくなるため，
+    /// これらを含む文字クラスがある場合は `SerAstError::UnsupportedMatcher` を返す
+    fn try_from(ast: &AST) -> Result<Self, Self::Error> {
+        let mut nodes = Vec::new();
+        push_node(ast, &mut nodes)?;
+        Ok(SerAst { nodes })
+    }
+}
+
+/// astを後行順でnodesに追加し，追加したノードのインデックスを返す
+fn push_node(ast: &AST, nodes: &mut Vec<SerNode>) -> Result<usize, SerAstError> {
+    let node = match ast {
+        AST::Char(c) => SerNode::Char(*c),
+        AST::CharCI(c) => SerNode::CharCI(*c),
+        AST::Class(class) => match class.matchers.first() {
+            Some(m) => return Err(SerAstError::UnsupportedMatcher(m.name.clone())),
+            None => SerNode::Class(class.clone()),
+        },
+        AST::Start => SerNode::Start,
+        AST::End => SerNode::End,
+        AST::LineStart => SerNode::LineStart,
+        AST::LineEnd => SerNode::LineEnd,
+        AST::AnchorPrevEnd => SerNode::AnchorPrevEnd,
+        AST::WordBoundary => SerNode::WordBoundary,
+        AST::Plus(e) => SerNode::Plus(push_node(e, nodes)?),
+        AST::Star(e) => SerNode::Star(push_node(e, nodes)?),
+        AST::Question(e) => SerNode::Question(push_node(e, nodes)?),
+        AST::Or(e1, e2) => {
+            let n1 = push_node(e1, nodes)?;
+            let n2 = push_node(e2, nodes)?;
+            SerNode::Or(n1, n2)
+        }
+        AST::Seq(v) => SerNode::Seq(
+            v.iter()
+                .map(|e| push_node(e, nodes))
+                .collect::<Result<_, _>>()?,
+        ),
+        AST::Capture(n, e) => SerNode::Capture(*n, push_node(e, nodes)?),
+        AST::Repeat(e, min, max) => SerNode::Repeat(push_node(e, nodes)?, *min, *max),
+        AST::Lazy(e) => SerNode::Lazy(push_node(e, nodes)?),
+    };
+    nodes.push(node);
+    Ok(nodes.len() - 1)
+}
+
+impl TryFrom<SerAst> for AST {
+    type Error = SerAstError;
+
+    /// SerAstをASTに変換する
+    ///
+    /// 子ノードが親ノードより前にない場合や，ノードが共有されている場合，
+    /// ルートから到達できないノードがある場合はエラーとなる
+    fn try_from(ser: SerAst) -> Result<Self, Self::Error> {
+        // 変換済みのノード，親ノードに取り出されるとNoneになる
+        let mut built: Vec<Option<AST>> = Vec::with_capacity(ser.nodes.len());
+
+        for (i, node) in ser.nodes.into_iter().enumerate() {
+            let mut child = |n: usize| -> Result<Box<AST>, SerAstError> {
+                if n >= i {
+                    return Err(SerAstError::InvalidIndex(i, n));
+                }
+                built[n]
+                    .take()
+                    .map(Box::new)
+                    .ok_or(SerAstError::SharedNode(n))
+            };
+
+            let ast = match node {
+                SerNode::Char(c) => AST::Char(c),
+                SerNode::CharCI(c) => AST::CharCI(c),
+                SerNode::Class(class) => AST::Class(class),
+                SerNode::Start => AST::Start,
+                SerNode::End => AST::End,
+                SerNode::LineStart => AST::LineStart,
+                SerNode::LineEnd => AST::LineEnd,
+                SerNode::AnchorPrevEnd => AST::AnchorPrevEnd,
+                SerNode::WordBoundary => AST::WordBoundary,
+                SerNode::Plus(n) => AST::Plus(child(n)?),
+                SerNode::Star(n) => AST::Star(child(n)?),
+                SerNode::Question(n) => AST::Question(child(n)?),
+                SerNode::Or(n1, n2) => AST::Or(child(n1)?, child(n2)?),
+                SerNode::Seq(v) => {
+                    let seq = v
+                        .into_iter()
+                        .map(|n| child(n).map(|e| *e))
+                        .collect::<Result<_, _>>()?;
+                    AST::Seq(seq)
+                }
+                SerNode::Capture(0, _) => return Err(SerAstError::InvalidGroup(i)),
+                SerNode::Capture(group, n) => AST::Capture(group, child(n)?),
+                SerNode::Repeat(n, min, max) => AST::Repeat(child(n)?, min, max),
+                SerNode::Lazy(n) => AST::Lazy(child(n)?),
+            };
+            built.push(Some(ast));
+        }
+
+        let root = built.pop().flatten().ok_or(SerAstError::Empty)?;
+        // ルート以外は全て親ノードに取り出されているはず
+        if let Some(n) = built.iter().position(|n| n.is_some()) {
+            return Err(SerAstError::UnusedNode(n));
+        }
+
+        Ok(root)
+    }
+}
@@ -0,0 +1,72 @@
+//! set.rs provides `RegexSet`, which matches many regular expressions at once.
+
+use super::{
+    codegen,
+    error::RegexError,
+    evaluator::{self, EvalError},
+    parser, Instruction,
+};
+
+/// 複数の正規表現をまとめてコンパイルしたもの
+///
+/// 各正規表現の命令列を1つに連結し，Thompson法の評価器で入力を一度だけ読んで，
+/// どの正規表現がマッチしたかを判定する。
+/// i番目の正規表現の命令列は，Matchの代わりに番号を持つ `Instruction::MatchN(i)` で終わる。
+///
+/// # 利用例
+///
+/// ```
+/// use regex_engine::RegexSet;
+/// let set = RegexSet::new(&["a+", "b+", "c+"]).unwrap();
+/// assert_eq!(set.matches("ac").unwrap(), vec![0, 2]);
+/// ```
+#[derive(Debug)]
+pub struct RegexSet {
+    code: Vec<Instruction>,
+    starts: Vec<usize>, // 各正規表現の命令列の開始アドレス
+}
+
+impl RegexSet {
+    /// 各正規表現をパースしてコード生成し，命令列を連結する
+    ///
+    /// いずれかの正規表現にエラーがある場合はErrを返す
+    pub fn new(patterns: &[&str]) -> Result<Self, RegexError> {
+        let mut code = Vec::new();
+        let mut starts = Vec::new();
+        for (id, expr) in patterns.iter().enumerate() {
+            let ast = parser::parse(expr)?;
+            starts.push(code.len());
+            let inst = codegen::get_code_at(&ast, code.len())?;
+            code.extend(inst.into_iter().map(|inst| match inst {
+                Instruction::Match => Instruction::MatchN(id),
+                inst => inst,
+            }));
+        }
+        Ok(RegexSet { code, starts })
+    }
+
+    /// 文字列中のいずれかの位置でマッチした正規表現の番号を昇順で返す
+    pub fn matches(&self, line: &str) -> Result<Vec<usize>, EvalError> {
+        let matched = evaluator::eval_thompson_set(&self.code, &self.starts, line.chars())?;
+        Ok(matched
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, m)| m.then_some(i))
+            .collect())
+    }
+
+    /// 文字列中のいずれかの位置で，いずれかの正規表現がマッチするかを判定
+    pub fn is_match(&self, line: &str) -> Result<bool, EvalError> {
+        Ok(!self.matches(line)?.is_empty())
+    }
+
+    /// 含まれる正規表現の数を返す
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// 正規表現を1つも含まない場合にtrueを返す
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+}
@@ -0,0 +1,51 @@
+//! unicode.rs provides Unicode property classes such as `\p{L}` and `\p{Greek}`.
+
+use super::class::{CharMatcher, NamedMatcher};
+use std::sync::Arc;
+use unicode_properties::{GeneralCategoryGroup, UnicodeGeneralCategory};
+use unicode_script::{Script, UnicodeScript};
+
+/// \p{...} で指定されるUnicodeの文字プロパティ
+#[derive(Debug, Clone, Copy)]
+enum Property {
+    Category(GeneralCategoryGroup), // 一般カテゴリ (L, N など)
+    Script(Script),                 // 用字 (Greek, Han など)
+}
+
+impl CharMatcher for Property {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Property::Category(group) => c.general_category_group() == *group,
+            Property::Script(script) => c.script() == *script,
+        }
+    }
+}
+
+/// プロパティ名に対応する文字の判定を返す
+///
+/// 一般カテゴリは L や Letter のように1文字の略称と完全な名前のどちらでも指定でき，
+/// 用字は Greek や Grek のようにUnicodeの名前か4文字の略称で指定する。
+/// 判定の名前は \p{name} の形式となる。未知の名前の場合はNoneを返す。
+pub(crate) fn property_matcher(name: &str) -> Option<NamedMatcher> {
+    let category = match name {
+        "L" | "Letter" => Some(GeneralCategoryGroup::Letter),
+        "M" | "Mark" => Some(GeneralCategoryGroup::Mark),
+        "N" | "Number" => Some(GeneralCategoryGroup::Number),
+        "P" | "Punctuation" => Some(GeneralCategoryGroup::Punctuation),
+        "S" | "Symbol" => Some(GeneralCategoryGroup::Symbol),
+        "Z" | "Separator" => Some(GeneralCategoryGroup::Separator),
+        "C" | "Other" => Some(GeneralCategoryGroup::Other),
+        _ => None,
+    };
+    let property = match category {
+        Some(group) => Property::Category(group),
+        None => Script::from_full_name(name)
+            .or_else(|| Script::from_short_name(name))
+            .map(Property::Script)?,
+    };
+
+    Some(NamedMatcher {
+        name: format!("\\p{{{name}}}"),
+        matcher: Arc::new(property),
+    })
+}
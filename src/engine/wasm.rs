@@ -0,0 +1,52 @@
+//! wasm.rs provides matching entry points for WebAssembly, exchanging only strings.
+
+use super::{evaluator::Match, regex::Regex};
+use std::fmt::Write;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// 正規表現exprで文字列lineを検索し，結果をJSON文字列で返す
+///
+/// WASMの境界で文字列のみを受け渡すため，結果は以下のいずれかの形式のJSONとなる。
+/// 位置は文字 (Unicodeのコードポイント) 単位のインデックス。
+///
+/// - マッチした場合: `{"matched":true,"start":1,"end":3}`
+/// - マッチしなかった場合: `{"matched":false,"start":null,"end":null}`
+/// - エラーの場合: `{"error":"ParseError: ..."}`
+///
+/// # 利用例
+///
+/// ```
+/// use regex_engine::match_json;
+/// assert_eq!(match_json("b+", "abbc"), r#"{"matched":true,"start":1,"end":3}"#);
+/// ```
+#[wasm_bindgen]
+pub fn match_json(expr: &str, line: &str) -> String {
+    let result = Regex::new(expr).and_then(|re| Ok(re.find(line)?));
+    match result {
+        Ok(Some(Match { start, end })) => {
+            format!(r#"{{"matched":true,"start":{start},"end":{end}}}"#)
+        }
+        Ok(None) => r#"{"matched":false,"start":null,"end":null}"#.to_string(),
+        Err(e) => format!(r#"{{"error":{}}}"#, json_string(&e.to_string())),
+    }
+}
+
+/// 文字列をエスケープしてJSONの文字列リテラルに変換する
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -21,4 +21,5 @@ where
     }
 }
 
+#[allow(dead_code)] // ライブラリの公開APIは RegexError を返すため，CLIからのみ利用する
 pub type DynError = Box<dyn std::error::Error + Send + Sync + 'static>;
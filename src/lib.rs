@@ -12,4 +12,15 @@
 mod engine;
 mod helper;
 
-pub use engine::{do_matching, print};
+pub use engine::{
+    codegen_rust, do_matching, dump_ast, escape, estimate_code_size, get_code, parse, parse_all,
+    print, validate_program, AssertKind, ByteDfa, Captures, CharClass, CharMatcher, CodeGenError,
+    EvalError, Greediness, Instruction, Match, MatchResult, Matches, NamedMatcher, ParseError,
+    Regex, RegexBuilder, RegexError, RegexSet, Stats, Syntax, AST, DEFAULT_SIZE_LIMIT,
+};
+
+#[cfg(feature = "serde")]
+pub use engine::{SerAst, SerAstError, SerNode};
+
+#[cfg(feature = "wasm")]
+pub use engine::match_json;
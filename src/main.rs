@@ -1,40 +1,225 @@
+#[allow(dead_code, unused_imports)] // ライブラリ向けのAPIにはCLIから利用しないものもある
 mod engine;
 mod helper;
 
+use engine::{EvalError, Match, ParseError, Regex, RegexBuilder, RegexError};
 use helper::DynError;
 use std::{
     env,
     fs::File,
-    io::{BufRead, BufReader},
+    io::{self, BufRead, BufReader, IsTerminal, Write},
 };
 
-fn match_file(expr: &str, file: &str) -> Result<(), DynError> {
+/// マッチした部分を強調表示するかどうか
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum ColorMode {
+    #[default]
+    Never, // 強調表示しない
+    Always, // 常に強調表示する
+    Auto,   // 標準出力が端末の場合のみ強調表示する
+}
+
+/// コマンドラインオプション
+#[derive(Debug, Default)]
+struct Options {
+    only_matching: bool, // -o: マッチした部分のみを表示
+    line_number: bool,   // -n: 行番号を表示
+    word_regexp: bool,   // -w: 単語全体にマッチする場合のみ一致とする
+    color: ColorMode,    // --color: マッチした部分を強調表示
+}
+
+/// 強調表示の開始と終了のエスケープシーケンス (赤の太字)
+const COLOR_START: &str = "\x1b[1;31m";
+const COLOR_END: &str = "\x1b[0m";
+
+/// 文字単位のインデックスからバイト単位のオフセットへの対応表を作る
+fn byte_offsets(line: &str) -> Vec<usize> {
+    line.char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(line.len()))
+        .collect()
+}
+
+/// lineのspansの範囲をANSIエスケープシーケンスで強調表示した文字列を返す
+///
+/// spansは文字単位のマッチ範囲で，先頭から順に並び重複しない必要がある。
+/// 空の範囲は無視する。
+fn highlight(line: &str, spans: impl Iterator<Item = Match>) -> String {
+    let offsets = byte_offsets(line);
+    let mut out = String::new();
+    let mut last = 0;
+    for Match { start, end } in spans.filter(|m| !m.is_empty()) {
+        let (start, end) = (offsets[start], offsets[end]);
+        out.push_str(&line[last..start]);
+        out.push_str(COLOR_START);
+        out.push_str(&line[start..end]);
+        out.push_str(COLOR_END);
+        last = end;
+    }
+    out.push_str(&line[last..]);
+    out
+}
+
+/// 行中の重複しないマッチの範囲を先頭から順に返す
+///
+/// `find_iter` と異なり，評価中にエラーが発生した場合はマッチを読み飛ばさずにErrを返す
+fn find_all(re: &Regex, line: &str) -> Result<Vec<Match>, EvalError> {
+    re.captures_iter(line)
+        .filter_map(|caps| caps.map(|caps| caps[0]).transpose())
+        .collect()
+}
+
+/// 1行分の出力を生成する
+///
+/// マッチしなかった行は何も出力しない。
+/// -o の場合は空でないマッチごとにマッチした部分を，それ以外の場合は行全体を出力する。
+/// -n の場合は各出力の先頭に "行番号:" を付ける。
+/// 行全体を出力する場合，colorが `ColorMode::Always` ならマッチした部分を強調表示する。
+fn format_line(
+    re: &Regex,
+    line: &str,
+    lineno: usize,
+    opts: &Options,
+) -> Result<Vec<String>, DynError> {
+    let prefix = if opts.line_number {
+        format!("{lineno}:")
+    } else {
+        String::new()
+    };
+
+    if !opts.only_matching {
+        if !re.is_match(line)? {
+            return Ok(Vec::new());
+        }
+        if opts.color == ColorMode::Always {
+            return Ok(vec![format!(
+                "{prefix}{}",
+                highlight(line, find_all(re, line)?.into_iter())
+            )]);
+        }
+        return Ok(vec![format!("{prefix}{line}")]);
+    }
+
+    let offsets = byte_offsets(line);
+    let out = find_all(re, line)?
+        .into_iter()
+        .filter(|m| !m.is_empty())
+        .map(|m| format!("{prefix}{}", &line[offsets[m.start]..offsets[m.end]]))
+        .collect();
+    Ok(out)
+}
+
+fn match_file(expr: &str, file: &str, opts: &Options) -> Result<(), DynError> {
     let f = File::open(file)?;
     let reader = BufReader::new(f);
 
     engine::print(expr)?;
     println!();
 
-    for line in reader.lines() {
-        let line = line?;
-        for (i, _) in line.char_indices() {
-            if engine::do_matching(expr, &line[i..], true)? {
-                println!("{line}");
-                break;
-            }
+    let re = RegexBuilder::new(expr)
+        .whole_word(opts.word_regexp)
+        .build()?;
+    match_reader(&re, reader, &mut io::stdout().lock(), opts)
+}
+
+/// readerから1行ずつ読み込み，マッチした行をoutに書き出す
+///
+/// 行ごとにStringを確保しないよう，1つのバッファを使い回して読み込む。
+/// 行末の改行 (\n または \r\n) は取り除いてからマッチングする。
+fn match_reader(
+    re: &Regex,
+    mut reader: impl BufRead,
+    out: &mut impl Write,
+    opts: &Options,
+) -> Result<(), DynError> {
+    let mut buf = String::new();
+    let mut lineno = 0;
+    loop {
+        buf.clear();
+        if reader.read_line(&mut buf)? == 0 {
+            break;
+        }
+        lineno += 1;
+
+        let line = buf.strip_suffix('\n').unwrap_or(&buf);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        for s in format_line(re, line, lineno, opts)? {
+            writeln!(out, "{s}")?;
         }
     }
 
     Ok(())
 }
 
+/// エラーを表示用の文字列に変換する
+///
+/// パターンのパースエラーの場合は，パターン中のエラーの位置を ^ で示す
+fn format_error(expr: &str, err: &(dyn std::error::Error + 'static)) -> String {
+    let parse_error = match err.downcast_ref::<RegexError>() {
+        Some(RegexError::Parse(e)) => Some(e),
+        _ => err.downcast_ref::<ParseError>(),
+    };
+    match parse_error {
+        Some(e) => e.render(expr),
+        None => err.to_string(),
+    }
+}
+
+/// コマンドライン引数からオプションと，それ以外の引数を取り出す
+///
+/// -on のように複数のオプションをまとめて指定できる。
+/// --color は --color=auto と同じで，--color=always, --color=never も指定できる。
+fn parse_args(args: &[String]) -> Result<(Options, Vec<&str>), DynError> {
+    let mut opts = Options::default();
+    let mut rest = Vec::new();
+    for arg in args {
+        if let Some(long) = arg.strip_prefix("--") {
+            opts.color = match long {
+                "color" | "color=auto" => ColorMode::Auto,
+                "color=always" => ColorMode::Always,
+                "color=never" => ColorMode::Never,
+                _ => return Err(format!("unknown option: {arg}").into()),
+            };
+            continue;
+        }
+
+        match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() => {
+                for c in flags.chars() {
+                    match c {
+                        'o' => opts.only_matching = true,
+                        'n' => opts.line_number = true,
+                        'w' => opts.word_regexp = true,
+                        _ => return Err(format!("unknown option: -{c}").into()),
+                    }
+                }
+            }
+            _ => rest.push(arg.as_str()),
+        }
+    }
+    Ok((opts, rest))
+}
+
 fn main() -> Result<(), DynError> {
     let args: Vec<String> = env::args().collect();
-    if args.len() <= 2 {
-        eprintln!("usage: {} regex file", args[0]);
+    let (mut opts, rest) = parse_args(&args[1..])?;
+    if opts.color == ColorMode::Auto {
+        opts.color = if io::stdout().is_terminal() {
+            ColorMode::Always
+        } else {
+            ColorMode::Never
+        };
+    }
+
+    if rest.len() < 2 {
+        eprintln!(
+            "usage: {} [-o] [-n] [-w] [--color[=WHEN]] regex file",
+            args[0]
+        );
         return Err("invalid arguments".into());
-    } else {
-        match_file(&args[1], &args[2])?;
+    } else if let Err(e) = match_file(rest[0], rest[1], &opts) {
+        eprintln!("{}", format_error(rest[0], e.as_ref()));
+        std::process::exit(2);
     }
 
     Ok(())
@@ -44,11 +229,15 @@ fn main() -> Result<(), DynError> {
 #[cfg(test)]
 mod tests {
     use crate::{
-        engine::{do_matching, print},
+        engine::{
+            codegen::get_code, do_matching, estimate_code_size, evaluator, parse, AssertKind,
+            EvalError, Instruction, Match, Matches, Regex, RegexBuilder, RegexSet,
+        },
         helper::{safe_add, SafeAdd},
     };
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_safe_add() {
         let n: usize = 10;
         assert_eq!(Some(30), n.safe_add(&20));
@@ -82,4 +271,2890 @@ mod tests {
         assert!(!do_matching("(ab|cd)+", "", true).unwrap());
         assert!(do_matching("abc?", "acb", true).is_ok());
     }
+
+    #[test]
+    fn test_captures_len() {
+        assert_eq!(Regex::new("(a)(b)").unwrap().captures_len(), 3);
+        assert_eq!(Regex::new("abc").unwrap().captures_len(), 1);
+        assert_eq!(Regex::new("(a(b))|(c)").unwrap().captures_len(), 4);
+
+        // グループ化しても従来通りマッチングできる
+        let re = Regex::new("x(ab|cd)+y").unwrap();
+        assert!(re.is_match("--xabcdy--").unwrap());
+        assert!(!re.is_match("--xy--").unwrap());
+    }
+
+    #[test]
+    fn test_named_captures() {
+        let re = Regex::new("(?P<n>(0|1|2|3|4|5|6|7|8|9)+)").unwrap();
+        assert_eq!(re.name_to_index("n"), Some(1));
+        assert_eq!(re.name_to_index("m"), None);
+        assert_eq!(
+            re.captures_name("year 2024", "n").unwrap(),
+            Some(Match::new(5, 9))
+        );
+        assert_eq!(re.captures_name("no digits", "n").unwrap(), None);
+
+        let re = Regex::new("(a)(?P<second>b)").unwrap();
+        assert_eq!(re.captures_len(), 3);
+        assert_eq!(re.name_to_index("second"), Some(2));
+
+        // 不正なグループ名と重複したグループ名
+        assert!(Regex::new("(?P<1a>x)").is_err());
+        assert!(Regex::new("(?P<a-b>x)").is_err());
+        assert!(Regex::new("(?P<>x)").is_err());
+        assert!(Regex::new("(?P<abc").is_err());
+        assert!(Regex::new("(?P<a>x)(?P<a>y)").is_err());
+    }
+
+    #[test]
+    fn test_split_with_delimiters() {
+        let re = Regex::new(",|;").unwrap();
+        assert_eq!(
+            re.split_with_delimiters("a,b;c").unwrap(),
+            vec![("a", Some(",")), ("b", Some(";")), ("c", None)]
+        );
+
+        // 隣接する区切りと，先頭・末尾の区切り
+        assert_eq!(
+            re.split_with_delimiters(",a;;").unwrap(),
+            vec![
+                ("", Some(",")),
+                ("a", Some(";")),
+                ("", Some(";")),
+                ("", None)
+            ]
+        );
+        assert_eq!(re.split_with_delimiters("").unwrap(), vec![("", None)]);
+
+        // 断片と区切りを連結すると元の文字列に戻る
+        let line = "あ,い;う,";
+        let joined: String = re
+            .split_with_delimiters(line)
+            .unwrap()
+            .into_iter()
+            .flat_map(|(text, delim)| [text, delim.unwrap_or("")])
+            .collect();
+        assert_eq!(joined, line);
+
+        assert_eq!(re.split("あ,い;う").unwrap(), vec!["あ", "い", "う"]);
+
+        // 評価中のエラーは分割を打ち切らずに返す
+        let re = RegexBuilder::new("(a|b)*,")
+            .backtrack_limit(3)
+            .build()
+            .unwrap();
+        let line = "a,ababababab,b";
+        assert!(matches!(
+            re.split(line),
+            Err(EvalError::BacktrackLimitExceeded)
+        ));
+        assert!(matches!(
+            re.split_with_delimiters(line),
+            Err(EvalError::BacktrackLimitExceeded)
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_ser_ast_round_trip() {
+        use crate::engine::{parse, SerAst, SerNode, AST};
+
+        let ast = parse("a(b|c)*").unwrap();
+        let json = serde_json::to_string(&SerAst::try_from(&ast).unwrap()).unwrap();
+        let ser: SerAst = serde_json::from_str(&json).unwrap();
+        assert_eq!(AST::try_from(ser).unwrap(), ast);
+
+        // 子ノードが親ノードより後ろにある
+        let ser = SerAst {
+            nodes: vec![SerNode::Plus(1), SerNode::Char('a')],
+        };
+        assert!(AST::try_from(ser).is_err());
+
+        // 同じノードを2回参照している
+        let ser = SerAst {
+            nodes: vec![SerNode::Char('a'), SerNode::Or(0, 0)],
+        };
+        assert!(AST::try_from(ser).is_err());
+
+        // ルートから参照されないノードがある
+        let ser = SerAst {
+            nodes: vec![SerNode::Char('a'), SerNode::Char('b')],
+        };
+        assert!(AST::try_from(ser).is_err());
+
+        // 文字の判定はシリアライズできないため，意味が変わらないようエラーとなる
+        let class = crate::engine::CharClass {
+            ranges: vec![('0', '9')],
+            negated: false,
+            case_insensitive: false,
+            matchers: vec![crate::engine::NamedMatcher {
+                name: "vowel".to_string(),
+                matcher: std::sync::Arc::new(|c: char| "aeiou".contains(c)),
+            }],
+        };
+        let ast = AST::Plus(Box::new(AST::Class(class)));
+        assert!(matches!(
+            SerAst::try_from(&ast),
+            Err(crate::engine::SerAstError::UnsupportedMatcher(name)) if name == "vowel"
+        ));
+        #[cfg(feature = "unicode")]
+        assert!(matches!(
+            SerAst::try_from(&parse("a\\p{Greek}+").unwrap()),
+            Err(crate::engine::SerAstError::UnsupportedMatcher(name)) if name == "\\p{Greek}"
+        ));
+    }
+
+    #[test]
+    fn test_required_literal() {
+        // ab+c は "ab" を必ず含む
+        let re = Regex::new("x*ab+c").unwrap();
+        assert!(!re.is_match("acbbbc").unwrap());
+        assert!(re.find("zzz").unwrap().is_none());
+        assert_eq!(re.stats().eval_calls, 0); // 評価器は起動されない
+
+        assert!(re.is_match("xxabbbc").unwrap());
+        assert!(re.stats().eval_calls > 0);
+
+        // 必須の固定文字列がない場合も正しくマッチングできる
+        let re = Regex::new("a+|b").unwrap();
+        assert!(re.is_match("b").unwrap());
+        assert!(re.stats().eval_calls > 0);
+    }
+
+    #[test]
+    fn test_is_match_iter() {
+        let re = Regex::new("a(b|c)+").unwrap();
+        assert!(re.is_match_iter("abc".chars()).unwrap());
+        assert!(re.is_match_iter("xxacb".chars()).unwrap());
+        assert!(!re.is_match_iter("xxa".chars()).unwrap());
+        assert!(!re.is_match_iter("".chars()).unwrap());
+
+        // フィルタしたイテレータなど，スライスでない入力
+        assert!(re
+            .is_match_iter("a-b-c".chars().filter(|c| *c != '-'))
+            .unwrap());
+
+        let re = Regex::new("x*").unwrap();
+        assert!(re.is_match_iter("".chars()).unwrap());
+    }
+
+    #[test]
+    fn test_longest_match() {
+        let re = Regex::new("a|aa").unwrap();
+        assert_eq!(re.find("aa").unwrap(), Some(Match::new(0, 1)));
+
+        let re = RegexBuilder::new("a|aa")
+            .longest_match(true)
+            .build()
+            .unwrap();
+        assert_eq!(re.find("aa").unwrap(), Some(Match::new(0, 2)));
+        assert_eq!(re.find("baaa").unwrap(), Some(Match::new(1, 3)));
+        assert_eq!(re.find("b").unwrap(), None);
+
+        // キャプチャも最長のマッチのものが返る
+        let re = RegexBuilder::new("(a|ab)(c|bcd)")
+            .longest_match(true)
+            .build()
+            .unwrap();
+        let caps = re.captures("abcd").unwrap().unwrap();
+        assert_eq!(
+            caps,
+            vec![
+                Some(Match::new(0, 4)),
+                Some(Match::new(0, 1)),
+                Some(Match::new(1, 4))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let re = Regex::new("a+").unwrap();
+        let expected = ["0000: char a", "0001: split 0000, 0002", "0002: match"];
+        assert_eq!(re.disassemble().lines().collect::<Vec<_>>(), expected);
+
+        let re = Regex::new("(a|b)").unwrap();
+        let expected = [
+            "0000: save 2",
+            "0001: split 0002, 0004",
+            "0002: char a",
+            "0003: jump 0005",
+            "0004: char b",
+            "0005: save 3",
+            "0006: match",
+        ];
+        assert_eq!(re.disassemble().lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_repeat_without_min() {
+        use crate::engine::ParseError;
+
+        // 全体にマッチするかを (0, 文字数) と比較して確認する
+        let re = Regex::new("a{,2}").unwrap();
+        assert_eq!(re.find("").unwrap(), Some(Match::new(0, 0)));
+        assert_eq!(re.find("a").unwrap(), Some(Match::new(0, 1)));
+        assert_eq!(re.find("aa").unwrap(), Some(Match::new(0, 2)));
+        assert_ne!(re.find("aaa").unwrap(), Some(Match::new(0, 3)));
+
+        // {n}, {n,}, {n,m} も引き続き使える
+        assert_eq!(
+            Regex::new("a{2}").unwrap().find("aaa").unwrap(),
+            Some(Match::new(0, 2))
+        );
+        assert_eq!(
+            Regex::new("a{2,}").unwrap().find("aaaa").unwrap(),
+            Some(Match::new(0, 4))
+        );
+        assert_eq!(
+            Regex::new("a{1,3}").unwrap().find("aaaa").unwrap(),
+            Some(Match::new(0, 3))
+        );
+        assert!(!Regex::new("(ab){2}").unwrap().is_match("abxab").unwrap());
+
+        // 繰り返し指定として解釈できない場合は通常の文字
+        assert_eq!(
+            Regex::new("a{,}").unwrap().find("a{,}").unwrap(),
+            Some(Match::new(0, 4))
+        );
+        assert_eq!(
+            Regex::new("a{x}").unwrap().find("a{x}").unwrap(),
+            Some(Match::new(0, 4))
+        );
+        assert_eq!(
+            Regex::new("a{1,2,3}").unwrap().find("a{1,2,3}").unwrap(),
+            Some(Match::new(0, 8))
+        );
+
+        // 不正な繰り返し指定
+        assert!(Regex::new("a{3,1}").is_err());
+        assert!(Regex::new("{,2}").is_err());
+
+        // usizeに収まらない回数は通常の文字ではなくエラー
+        let huge = "99999999999999999999999";
+        for expr in [
+            format!("a{{{huge}}}"),
+            format!("a{{{huge},}}"),
+            format!("a{{1,{huge}}}"),
+            format!("a{{,{huge}}}?"),
+        ] {
+            assert!(
+                matches!(parse(&expr), Err(ParseError::InvalidRepeat(1))),
+                "{expr}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_program_cfg() {
+        // 0000: split 0001, 0006
+        // 0001: save 2
+        // 0002: char a
+        // 0003: char b
+        // 0004: save 3
+        // 0005: jump 0000
+        // 0006: match
+        let re = Regex::new("(ab)*").unwrap();
+        let cfg = re.program_cfg().collect::<Vec<_>>();
+        assert_eq!(cfg.len(), 7);
+
+        assert!(matches!(cfg[0].1, Instruction::Split(1, 6)));
+        assert_eq!(cfg[0].2, vec![1, 6]);
+        assert!(matches!(cfg[5].1, Instruction::Jump(0)));
+        assert_eq!(cfg[5].2, vec![0]);
+        assert_eq!(cfg[2].2, vec![3]);
+        assert!(cfg[6].2.is_empty());
+    }
+
+    #[test]
+    fn test_byte_offsets() {
+        let line = "価格: 100円, 税込";
+        let re = Regex::new("(1|0)+(円)").unwrap();
+
+        assert_eq!(re.find(line).unwrap(), Some(Match::new(4, 8)));
+        let m = re.find_bytes(line).unwrap().unwrap();
+        assert_eq!(m, (8, 14));
+        assert_eq!(&line[m.range()], "100円");
+
+        let caps = re.captures_bytes(line).unwrap().unwrap();
+        let m = caps[2].unwrap();
+        assert_eq!(&line[m.range()], "円");
+
+        assert_eq!(re.find_bytes("なし").unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_pc() {
+        // jump先が命令列の範囲外
+        let code = vec![Instruction::Char('a'), Instruction::Jump(5)];
+        let line = ['a'];
+        for is_depth in [true, false] {
+            let err = evaluator::eval(&code, &line, is_depth).unwrap_err();
+            assert!(matches!(err, EvalError::InvalidPC(5)));
+            assert_eq!(err.to_string(), "EvalError: invalid pc: pc = 5");
+        }
+
+        // Matchがないまま命令列の末尾を越える
+        let code = vec![Instruction::Char('a')];
+        let err = evaluator::eval(&code, &line, true).unwrap_err();
+        assert!(matches!(err, EvalError::InvalidPC(1)));
+    }
+
+    #[test]
+    fn test_replace_all_into() {
+        let re = Regex::new("(a+)b").unwrap();
+        let mut out = String::from("garbage");
+
+        re.replace_all_into("xaabyab", "<$1>", &mut out).unwrap();
+        assert_eq!(out, "x<aa>y<a>");
+
+        // 同じバッファを再利用しても前回の結果は残らない
+        re.replace_all_into("ab", "[$0]", &mut out).unwrap();
+        assert_eq!(out, "[ab]");
+
+        re.replace_all_into("none", "-", &mut out).unwrap();
+        assert_eq!(out, "none");
+
+        assert_eq!(
+            re.replace_all("ab", "$$1 ${1}x $9 ${").unwrap(),
+            "$1 ax  ${"
+        );
+        assert_eq!(re.replace_all("あab", "い").unwrap(), "あい");
+    }
+
+    #[test]
+    fn test_comment_group() {
+        let re = Regex::new("a(?#note)b").unwrap();
+        assert_eq!(re.find("ab").unwrap(), Some(Match::new(0, 2)));
+        assert!(!re.is_match("a(?#note)b").unwrap());
+
+        // コメントはグループ番号を消費しない
+        let re = Regex::new("(?#first)(a)(?# b )c").unwrap();
+        assert_eq!(re.captures_len(), 2);
+        assert_eq!(
+            re.captures("ac").unwrap().unwrap()[1],
+            Some(Match::new(0, 1))
+        );
+
+        // グループの中のコメント
+        assert!(Regex::new("(x(?#c)|y)+").unwrap().is_match("xyx").unwrap());
+
+        // 閉じ括弧のないコメント
+        assert!(Regex::new("a(?#note").is_err());
+    }
+
+    #[test]
+    fn test_inline_flags() {
+        let re = Regex::new("(?i)abc").unwrap();
+        assert!(re.is_match("xAbC").unwrap());
+        assert!(re.is_match_iter("ABC".chars()).unwrap());
+
+        // (?-i) 以降は大文字と小文字を区別する
+        let re = Regex::new("(?i)ab(?-i)c").unwrap();
+        assert!(re.is_match("ABc").unwrap());
+        assert!(!re.is_match("ABC").unwrap());
+
+        // フラグはグループの終わりまで有効で，グループ番号を消費しない
+        let re = Regex::new("((?i)a)a").unwrap();
+        assert_eq!(re.captures_len(), 2);
+        assert_eq!(re.find("Aa").unwrap(), Some(Match::new(0, 2)));
+        assert!(!re.is_match("AA").unwrap());
+
+        let re = RegexBuilder::new("a(?-i)b")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match("Ab").unwrap());
+        assert!(!re.is_match("AB").unwrap());
+
+        // 未知のフラグ
+        assert!(Regex::new("(?x)a").is_err());
+        assert!(Regex::new("(?P<n>a)").is_ok());
+    }
+
+    #[test]
+    fn test_parse_all() {
+        use crate::engine::{parse_all, ParseError};
+
+        assert!(parse_all("a(b|c)+").is_ok());
+
+        // 2つのエラーの位置がともに報告される
+        let errors = parse_all("a)b\\x*").unwrap_err();
+        assert!(
+            matches!(
+                errors[..],
+                [
+                    ParseError::InvalidRightParen(1),
+                    ParseError::InvalidEscape(4, 'x')
+                ]
+            ),
+            "{errors:?}"
+        );
+
+        let errors = parse_all("+a|{2}(?P<n>b)(?P<n>c)").unwrap_err();
+        assert!(matches!(
+            errors[..],
+            [
+                ParseError::NoPrev(0),
+                ParseError::NoPrev(3),
+                ParseError::DuplicateGroupName(14, _)
+            ]
+        ));
+
+        // 回復できないエラーで終了する
+        let errors = parse_all(")(a").unwrap_err();
+        assert!(matches!(
+            errors[..],
+            [ParseError::InvalidRightParen(0), ParseError::NoRightParen]
+        ));
+    }
+
+    #[test]
+    fn test_posix_class() {
+        let re = Regex::new("[[:digit:]]+").unwrap();
+        assert_eq!(re.find("x42y").unwrap(), Some(Match::new(1, 3)));
+        assert!(!re.is_match("xy").unwrap());
+
+        // 他のクラスの要素との組み合わせ
+        let re = Regex::new("[[:digit:]a-f]+").unwrap();
+        assert_eq!(re.find("0x1fz").unwrap(), Some(Match::new(0, 1)));
+        assert_eq!(re.find("x1fz").unwrap(), Some(Match::new(1, 3)));
+
+        let re = Regex::new("[^[:space:]]+").unwrap();
+        assert_eq!(re.find(" \tab c").unwrap(), Some(Match::new(2, 4)));
+        assert!(Regex::new("[[:alpha:][:punct:]]")
+            .unwrap()
+            .is_match("!")
+            .unwrap());
+        assert!(Regex::new("(?i)[[:lower:]]")
+            .unwrap()
+            .is_match("A")
+            .unwrap());
+
+        // [: の後に :] が続かない場合は通常の文字
+        assert!(Regex::new("[[:]").unwrap().is_match(":").unwrap());
+
+        assert!(Regex::new("[[:nope:]]").is_err());
+        assert!(Regex::new("[[:digit:]").is_err());
+    }
+
+    #[test]
+    fn test_always_matches() {
+        // 空文字列にマッチできる正規表現は評価器を起動しない
+        let re = Regex::new("a*").unwrap();
+        assert!(re.is_match("").unwrap());
+        assert!(re.is_match("bbb").unwrap());
+        assert_eq!(re.stats().eval_calls, 0);
+
+        let re = Regex::new("(x|y?){2}").unwrap();
+        assert!(re.is_match("z").unwrap());
+        assert_eq!(re.stats().eval_calls, 0);
+
+        // アンカーを含む場合は通常どおり評価する
+        let re = Regex::new("^a*$").unwrap();
+        assert!(!re.is_match("b").unwrap());
+        assert!(re.is_match("").unwrap());
+        assert!(re.is_match("aa").unwrap());
+        assert!(re.stats().eval_calls > 0);
+
+        // アンカーの動作
+        let re = Regex::new("^ab|c$").unwrap();
+        assert_eq!(re.find("xab abc").unwrap(), Some(Match::new(6, 7)));
+        assert!(!re.is_match("xab cx").unwrap());
+        assert!(re.is_match_iter("abx".chars()).unwrap());
+        assert!(re.is_match_iter("xc".chars()).unwrap());
+        assert!(!re.is_match_iter("xcx".chars()).unwrap());
+        assert!(do_matching("^a+$", "aaa", true).unwrap());
+        assert!(!do_matching("^a+$", "aab", false).unwrap());
+        assert!(Regex::new("\\^\\$").unwrap().is_match("^$").unwrap());
+    }
+
+    #[cfg(feature = "wasm")]
+    #[test]
+    fn test_match_json() {
+        use crate::engine::match_json;
+        use serde_json::{json, Value};
+
+        let parse = |s: String| serde_json::from_str::<Value>(&s).unwrap();
+
+        let v = parse(match_json("a+", "xaab"));
+        assert_eq!(v, json!({"matched": true, "start": 1, "end": 3}));
+
+        let v = parse(match_json("a+", "xyz"));
+        assert_eq!(v["matched"], json!(false));
+        assert!(v["start"].is_null());
+
+        // エラーメッセージはJSONの文字列としてエスケープされる
+        let v = parse(match_json("a\\\"", "a"));
+        assert!(v["error"].as_str().unwrap().starts_with("ParseError"));
+        assert!(v.get("matched").is_none());
+    }
+
+    #[test]
+    fn test_regex_set() {
+        let set = RegexSet::new(&["a+", "b+", "c+"]).unwrap();
+        assert_eq!(set.len(), 3);
+        assert_eq!(set.matches("ac").unwrap(), vec![0, 2]);
+        assert_eq!(set.matches("cba").unwrap(), vec![0, 1, 2]);
+        assert!(set.matches("xyz").unwrap().is_empty());
+        assert!(!set.is_match("").unwrap());
+
+        // アンカーや繰り返しを含む正規表現
+        let set = RegexSet::new(&["^ab", "b(c|d)*$", "x?", "(?i)D"]).unwrap();
+        assert_eq!(set.matches("abcd").unwrap(), vec![0, 1, 2, 3]);
+        assert_eq!(set.matches("zab").unwrap(), vec![1, 2]);
+
+        assert!(RegexSet::new(&[]).unwrap().matches("a").unwrap().is_empty());
+        assert!(RegexSet::new(&["a", "*"]).is_err());
+    }
+
+    #[test]
+    fn test_repeated_group_captures() {
+        // 繰り返されるグループは最後の繰り返しでの位置をキャプチャする
+        let re = Regex::new("(a)+").unwrap();
+        assert_eq!(
+            re.captures("aaa").unwrap().unwrap()[1],
+            Some(Match::new(2, 3))
+        );
+
+        let re = Regex::new("(a|b)*c").unwrap();
+        assert_eq!(
+            re.captures("abac").unwrap().unwrap()[1],
+            Some(Match::new(2, 3))
+        );
+
+        let re = Regex::new("(a){2}").unwrap();
+        assert_eq!(
+            re.captures("aaa").unwrap().unwrap()[1],
+            Some(Match::new(1, 2))
+        );
+
+        // 失敗した繰り返しで書き換えたスロットは元に戻される
+        let re = Regex::new("(ab|a)+c").unwrap();
+        assert_eq!(
+            re.captures("abac").unwrap().unwrap()[1],
+            Some(Match::new(2, 3))
+        );
+        let re = Regex::new("(a)+ab").unwrap();
+        assert_eq!(
+            re.captures("aaab").unwrap().unwrap()[1],
+            Some(Match::new(1, 2))
+        );
+
+        // 最長一致モードでも同様
+        let re = RegexBuilder::new("(a|ab)+")
+            .longest_match(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            re.captures("aab").unwrap().unwrap()[1],
+            Some(Match::new(1, 3))
+        );
+    }
+
+    #[test]
+    fn test_len_range() {
+        let len_range = |expr| Regex::new(expr).unwrap().len_range();
+        assert_eq!(len_range("ab?"), (1, Some(2)));
+        assert_eq!(len_range("a*"), (0, None));
+        assert_eq!(len_range("abc"), (3, Some(3)));
+        assert_eq!(len_range("a+b"), (2, None));
+        assert_eq!(len_range("ab|c(de)?"), (1, Some(3)));
+        assert_eq!(len_range("(ab){2,3}x"), (5, Some(7)));
+        assert_eq!(len_range("a{2,}"), (2, None));
+        assert_eq!(len_range("^[a-z]$"), (1, Some(1)));
+        assert_eq!(len_range("(^)*a"), (1, Some(1)));
+
+        // 最小の長さに満たない文字列は評価器を起動せずに不一致と判定する
+        let re = Regex::new("[a-c]{3}").unwrap();
+        assert!(!re.is_match("ab").unwrap());
+        assert_eq!(re.stats().eval_calls, 0);
+        assert_eq!(re.find("xabc").unwrap(), Some(Match::new(1, 4)));
+    }
+
+    #[test]
+    fn test_char_matcher() {
+        let re = RegexBuilder::new("[[:vowel:]]+")
+            .char_matcher("vowel", |c: char| "aeiou".contains(c))
+            .build()
+            .unwrap();
+        assert_eq!(re.find("xaeiy").unwrap(), Some(Match::new(1, 4)));
+        assert!(!re.is_match("xyz").unwrap());
+
+        // 否定や範囲，大文字と小文字を区別しないモードとの組み合わせ
+        let re = RegexBuilder::new("(?i)[^[:vowel:]0-9]")
+            .char_matcher("vowel", |c: char| "aeiou".contains(c))
+            .build()
+            .unwrap();
+        assert_eq!(re.find("A1b").unwrap(), Some(Match::new(2, 3)));
+
+        // POSIX文字クラスの意味を置き換える
+        let re = RegexBuilder::new("^[[:digit:]]+$")
+            .char_matcher("digit", |c: char| c.is_numeric())
+            .build()
+            .unwrap();
+        assert!(re.is_match("１２3").unwrap());
+
+        // 登録されていない名前
+        assert!(Regex::new("[[:vowel:]]").is_err());
+    }
+
+    #[test]
+    fn test_regex_error_source() {
+        use crate::engine::{ParseError, RegexError};
+        use std::error::Error;
+
+        let err = Regex::new("a)").unwrap_err();
+        assert!(matches!(
+            err,
+            RegexError::Parse(ParseError::InvalidRightParen(1))
+        ));
+
+        // RegexError -> ParseError の順にたどれる
+        let source = err.source().unwrap();
+        assert!(matches!(
+            source.downcast_ref::<ParseError>(),
+            Some(ParseError::InvalidRightParen(1))
+        ));
+        assert!(source.source().is_none());
+
+        // 表示される文字列は元のエラーと同じ
+        assert_eq!(err.to_string(), source.to_string());
+
+        // Box<dyn Error> に変換してもたどれる
+        let err: Box<dyn Error> = do_matching("*", "a", true).unwrap_err().into();
+        let source = err.source().unwrap();
+        assert!(source.is::<ParseError>());
+    }
+
+    #[test]
+    fn test_only_matching() {
+        use crate::{format_line, parse_args, Options};
+
+        let re = Regex::new("a+").unwrap();
+        let opts = Options {
+            only_matching: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            format_line(&re, "baaad aa", 1, &opts).unwrap(),
+            vec!["aaa", "aa"]
+        );
+        assert!(format_line(&re, "bcd", 1, &opts).unwrap().is_empty());
+
+        // -n との組み合わせ
+        let args = ["-on", "a+", "file"].map(String::from);
+        let (opts, rest) = parse_args(&args).unwrap();
+        assert_eq!(rest, vec!["a+", "file"]);
+        assert_eq!(format_line(&re, "あaa", 3, &opts).unwrap(), vec!["3:aa"]);
+
+        // -o を指定しない場合は行全体
+        let (opts, _) = parse_args(&["-n".to_string()]).unwrap();
+        assert_eq!(
+            format_line(&re, "baaad", 2, &opts).unwrap(),
+            vec!["2:baaad"]
+        );
+
+        // 空文字列へのマッチは表示しない
+        let re = Regex::new("a*").unwrap();
+        let (opts, _) = parse_args(&["-o".to_string()]).unwrap();
+        assert_eq!(format_line(&re, "bab", 1, &opts).unwrap(), vec!["a"]);
+
+        assert!(parse_args(&["-x".to_string()]).is_err());
+
+        // 評価中のエラーはマッチを読み飛ばさずにErrとなる
+        let re = RegexBuilder::new("a+b")
+            .backtrack_limit(10)
+            .build()
+            .unwrap();
+        let line = "a".repeat(50) + "b";
+        assert!(format_line(&re, &line, 1, &opts).is_err());
+    }
+
+    #[test]
+    fn test_highlight() {
+        use crate::{format_line, highlight, parse_args, ColorMode};
+
+        let re = Regex::new("a+").unwrap();
+        assert_eq!(
+            highlight("baaad aa", re.find_iter("baaad aa")),
+            "b\x1b[1;31maaa\x1b[0md \x1b[1;31maa\x1b[0m"
+        );
+        assert_eq!(
+            highlight("あaい", re.find_iter("あaい")),
+            "あ\x1b[1;31ma\x1b[0mい"
+        );
+        assert_eq!(highlight("xyz", re.find_iter("xyz")), "xyz");
+
+        // 空の範囲は強調表示しない
+        let re = Regex::new("b*").unwrap();
+        assert_eq!(highlight("ab", re.find_iter("ab")), "a\x1b[1;31mb\x1b[0m");
+
+        let (opts, _) = parse_args(&["--color=always".to_string()]).unwrap();
+        assert_eq!(opts.color, ColorMode::Always);
+        assert_eq!(
+            format_line(&re, "ab", 1, &opts).unwrap(),
+            vec!["a\x1b[1;31mb\x1b[0m"]
+        );
+        let (opts, _) = parse_args(&["--color".to_string()]).unwrap();
+        assert_eq!(opts.color, ColorMode::Auto);
+        assert!(parse_args(&["--colour".to_string()]).is_err());
+
+        // 行がマッチしても，後続のマッチの評価中のエラーはErrとなる
+        let re = RegexBuilder::new("a*b")
+            .backtrack_limit(10)
+            .build()
+            .unwrap();
+        let (opts, _) = parse_args(&["--color=always".to_string()]).unwrap();
+        let line = "ab ".to_string() + &"a".repeat(50);
+        assert!(re.is_match(&line).unwrap());
+        assert!(format_line(&re, &line, 1, &opts).is_err());
+    }
+
+    #[test]
+    fn test_replace_case_conversion() {
+        let re = Regex::new("(?P<w>[[:alpha:]]+)=([[:alpha:]]+)").unwrap();
+        assert_eq!(
+            re.replace_all("x=y, ab=Cd", "\\U$1\\E=$2").unwrap(),
+            "X=y, AB=Cd"
+        );
+        assert_eq!(re.replace_all("ab=Cd", "\\L${2}-\\U${w}").unwrap(), "cd-AB");
+
+        // 固定の文字列も変換される
+        assert_eq!(re.replace_all("a=b", "\\Uv$2:\\E$1").unwrap(), "VB:a");
+
+        // \\ は \ で，それ以外の \ は通常の文字
+        assert_eq!(re.replace_all("a=b", "$1\\\\$2\\n").unwrap(), "a\\b\\n");
+        assert_eq!(re.replace_all("a=b", "$1\\").unwrap(), "a\\");
+    }
+
+    #[test]
+    fn test_lint() {
+        let lint = |expr| Regex::new(expr).unwrap().lint();
+
+        let lints = lint("a|a");
+        assert_eq!(lints.len(), 1);
+        assert!(lints[0].starts_with("duplicate alternative"));
+        assert!(lint("a|b").is_empty());
+
+        // 3つ目の選択肢が1つ目と同一
+        assert_eq!(
+            lint("ab|c|ab"),
+            vec!["duplicate alternative: alternative 3 is the same as alternative 1"]
+        );
+
+        // 包含される選択肢
+        assert!(lint("x([a-z]|b)")[0].starts_with("unreachable alternative"));
+        assert_eq!(lint("[a-z]|[c-d]").len(), 1);
+        assert_eq!(lint("(?i)a|(?-i)A").len(), 1);
+        assert!(lint("b|[a-z]").is_empty());
+        assert!(lint("a|ab").is_empty());
+
+        // グループの中の選択肢
+        assert_eq!(lint("(x|x)+(y|y)").len(), 2);
+    }
+
+    #[test]
+    fn test_replacen() {
+        let re = Regex::new("a+").unwrap();
+        assert_eq!(
+            re.replacen("aa b aaa c a", "<$0>", 2).unwrap(),
+            "<aa> b <aaa> c a"
+        );
+        assert_eq!(re.replacen("aa b aaa c a", "-", 1).unwrap(), "- b aaa c a");
+
+        // limitが0の場合は全て置換する
+        assert_eq!(
+            re.replacen("aa b aaa c a", "-", 0).unwrap(),
+            re.replace_all("aa b aaa c a", "-").unwrap()
+        );
+        assert_eq!(re.replacen("xyz", "-", 3).unwrap(), "xyz");
+
+        // 空文字列へのマッチも1回として数える
+        let re = Regex::new("b*").unwrap();
+        assert_eq!(re.replacen("abc", "-", 2).unwrap(), "-a-c");
+    }
+
+    #[test]
+    fn test_split_n() {
+        let re = Regex::new("ab|c|d|ef").unwrap();
+        let split_n = re
+            .program_cfg()
+            .filter(|(_, inst, _)| matches!(inst, Instruction::SplitN(_)))
+            .collect::<Vec<_>>();
+        assert_eq!(split_n.len(), 1);
+        assert_eq!(split_n[0].2, vec![1, 4, 6, 8]);
+        assert!(!re
+            .program_cfg()
+            .any(|(_, inst, _)| matches!(inst, Instruction::Split(..))));
+        assert!(re
+            .disassemble()
+            .starts_with("0000: split_n 0001, 0004, 0006, 0008\n"));
+
+        // 各選択肢にマッチする
+        for (line, expected) in [
+            ("xab", (1, 3)),
+            ("c", (0, 1)),
+            ("-d", (1, 2)),
+            ("ef", (0, 2)),
+        ] {
+            assert_eq!(re.find(line).unwrap(), Some(expected.into()));
+            assert!(re.is_match_iter(line.chars()).unwrap());
+        }
+        assert!(!re.is_match("axe").unwrap());
+        assert!(do_matching("ab|c|d|ef", "d", true).unwrap());
+        assert!(do_matching("ab|c|d|ef", "efx", false).unwrap());
+
+        // 先頭の選択肢が優先される
+        let re = Regex::new("(a|ab|abc)c").unwrap();
+        assert_eq!(
+            re.captures("abc").unwrap().unwrap()[1],
+            Some(Match::new(0, 2))
+        );
+
+        // 2つの選択肢の場合は従来どおりsplit
+        let re = Regex::new("a|b").unwrap();
+        assert!(matches!(
+            re.program_cfg().next().unwrap().1,
+            Instruction::Split(1, 3)
+        ));
+    }
+
+    #[test]
+    fn test_compile_byte_dfa() {
+        let exprs = [
+            "a[0-9]+b",
+            "^ab|cd$",
+            "(?i)x(y|z)*",
+            "a|b|c",
+            "^$",
+            "[^a]b",
+            "a{2,3}",
+        ];
+        let lines = [
+            "", "a1b", "ab", "xxcd", "abab", "XYZ", "xyq", "c", "bb", "aaa", "a",
+        ];
+        for expr in exprs {
+            let re = Regex::new(expr).unwrap();
+            let dfa = re.compile_byte_dfa().unwrap();
+            assert_eq!(dfa.memory_usage(), 256 * 4 * dfa.state_count());
+            for line in lines {
+                assert_eq!(
+                    dfa.is_match(line.as_bytes()),
+                    re.is_match(line).unwrap(),
+                    "{expr} on {line:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_null_escape() {
+        let re = Regex::new("a\\0b").unwrap();
+        assert!(re.is_match("a\0b").unwrap());
+        assert!(!re.is_match("a0b").unwrap());
+        assert!(Regex::new("[\\0]").unwrap().is_match("x\0").unwrap());
+        assert!(Regex::new("a\\1").is_err());
+    }
+
+    #[test]
+    fn test_estimate_code_size() {
+        let exprs = [
+            "(ab)+",
+            "a|b",
+            "a|bc|d",
+            "(a|b)|c",
+            "a{2,4}",
+            "(ab){3,}",
+            "x?y*",
+            "(?i)[a-c]^$",
+            "(a|b|c){2}",
+        ];
+        for expr in exprs {
+            let ast = parse(expr).unwrap();
+            let code = get_code(&ast).unwrap();
+            assert_eq!(estimate_code_size(&ast), code.len(), "{expr}");
+        }
+        assert_eq!(estimate_code_size(&parse("(ab)+").unwrap()), 6);
+    }
+
+    #[test]
+    fn test_is_anchored() {
+        let anchored = |expr| {
+            let re = Regex::new(expr).unwrap();
+            (re.is_anchored_start(), re.is_anchored_end())
+        };
+        assert_eq!(anchored("^abc"), (true, false));
+        assert_eq!(anchored("abc$"), (false, true));
+        assert_eq!(anchored("abc"), (false, false));
+        assert_eq!(anchored("^(a|b)+$"), (true, true));
+        assert_eq!(anchored("^a|^b"), (true, false));
+        assert_eq!(anchored("^a|b$"), (false, false));
+        assert_eq!(anchored("(^a)?b"), (false, false));
+    }
+
+    #[test]
+    fn test_backtrack_limit() {
+        let re = RegexBuilder::new("(a|b)*c")
+            .backtrack_limit(16)
+            .build()
+            .unwrap();
+        assert!(re.is_match("ababc").unwrap());
+        let line = "ab".repeat(20) + "c";
+        assert!(matches!(
+            re.is_match(&line),
+            Err(EvalError::BacktrackLimitExceeded)
+        ));
+        assert!(matches!(
+            re.find(&line),
+            Err(EvalError::BacktrackLimitExceeded)
+        ));
+
+        // 上限を指定しない場合は従来通り
+        assert!(!Regex::new("(a|b)*c")
+            .unwrap()
+            .is_match("ab".repeat(20))
+            .unwrap());
+
+        let code = get_code(&parse("(a|b)*c").unwrap()).unwrap();
+        let line = line.chars().collect::<Vec<char>>();
+        for is_depth in [true, false] {
+            assert!(matches!(
+                evaluator::eval_limited(&code, &line, is_depth, Some(16)),
+                Err(EvalError::BacktrackLimitExceeded)
+            ));
+            assert!(evaluator::eval_limited(&code, &line, is_depth, None)
+                .unwrap()
+                .is_some());
+        }
+    }
+
+    #[test]
+    fn test_matches_struct() {
+        struct Scanner<'r, 't> {
+            matches: Matches<'r, 't>,
+            count: usize,
+        }
+
+        impl Scanner<'_, '_> {
+            fn next_span(&mut self) -> Option<(usize, usize)> {
+                let span = self.matches.next()?;
+                self.count += 1;
+                Some(span.into())
+            }
+        }
+
+        let re = Regex::new("a*").unwrap();
+        let mut scanner = Scanner {
+            matches: re.find_iter("bab"),
+            count: 0,
+        };
+        assert_eq!(scanner.next_span(), Some((0, 0)));
+        assert_eq!(scanner.next_span(), Some((1, 2)));
+        assert_eq!(scanner.next_span(), Some((3, 3)));
+        assert_eq!(scanner.next_span(), None);
+        assert_eq!(scanner.count, 3);
+    }
+
+    #[test]
+    fn test_quote() {
+        let re = Regex::new("a\\Q+*?\\Eb").unwrap();
+        assert!(re.is_match("a+*?b").unwrap());
+        assert!(!re.is_match("aab").unwrap());
+
+        // \E のない \Q は末尾まで
+        let re = Regex::new("^\\Q(a|b)$").unwrap();
+        assert!(re.is_match("(a|b)$").unwrap());
+        assert!(!re.is_match("x(a|b)$").unwrap());
+
+        // 区間の後は通常通り解釈される
+        assert_eq!(
+            Regex::new("\\Q.\\E+").unwrap().find("x...").unwrap(),
+            Some(Match::new(1, 4))
+        );
+    }
+
+    #[test]
+    fn test_anchored_start_scan() {
+        let re = Regex::new("^ab").unwrap();
+        assert_eq!(re.find("xxxxab").unwrap(), None);
+        assert_eq!(re.stats().eval_calls, 1);
+        assert!(!re.is_match("xxxxab").unwrap());
+        assert_eq!(re.stats().eval_calls, 2);
+        assert_eq!(re.find_iter("ababab").collect::<Vec<_>>(), vec![(0, 2)]);
+
+        let re = Regex::new("^a*").unwrap();
+        assert_eq!(re.find_iter("aab").collect::<Vec<_>>(), vec![(0, 2)]);
+        assert_eq!(re.replace_all("aab", "x").unwrap(), "xb");
+    }
+
+    #[test]
+    fn test_find_from() {
+        let re = Regex::new("ab+").unwrap();
+        let line = "xabbyabz".chars().collect::<Vec<char>>();
+        assert_eq!(re.find_from(&line, 0).unwrap(), Some(Match::new(1, 4)));
+        assert_eq!(re.find_from(&line, 2).unwrap(), Some(Match::new(5, 7)));
+        assert_eq!(re.find_from(&line, 6).unwrap(), None);
+        assert_eq!(re.find_from(&line, 100).unwrap(), None);
+
+        // ^ は文字列の先頭を表すため，途中から再開した場合はマッチしない
+        let re = Regex::new("^ab").unwrap();
+        assert_eq!(re.find_from(&line, 1).unwrap(), None);
+    }
+
+    #[test]
+    fn test_thompson_epsilon_cycle() {
+        let cases = [
+            ("(a*)*", "", true),
+            ("(a*)*", "bbb", true),
+            ("(a*)*b", "aaab", true),
+            ("(a*)*b", "aaa", false),
+            ("((a*)*|b?)*c", "abac", true),
+            ("((a*)*|b?)*c", "abab", false),
+            ("(a?){3,}$", "aaaaa", true),
+            ("^(a*|b*)*$", "abba", true),
+            ("^(a*|b*)*$", "abca", false),
+        ];
+        for (expr, line, expected) in cases {
+            let re = Regex::new(expr).unwrap();
+            assert_eq!(
+                re.is_match_iter(line.chars()).unwrap(),
+                expected,
+                "{expr} on {line:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_captures_str() {
+        let re = Regex::new("(い+)(う|え)?(お)").unwrap();
+        let caps = re.captures_str("あいいおか").unwrap().unwrap();
+        assert_eq!(caps, vec![Some("いいお"), Some("いい"), None, Some("お")]);
+        assert_eq!(re.captures_str("あいう").unwrap(), None);
+    }
+
+    #[test]
+    fn test_match_reader() {
+        use crate::{format_line, match_reader, Options};
+
+        let re = Regex::new("b+").unwrap();
+        let input = "abc\r\nxyz\nbb\n\nbab";
+        let opts = Options {
+            line_number: true,
+            ..Options::default()
+        };
+
+        let mut out = Vec::new();
+        match_reader(&re, input.as_bytes(), &mut out, &opts).unwrap();
+
+        // lines() で1行ずつ処理した場合と同じ結果になる
+        let mut expected = String::new();
+        for (i, line) in input.lines().enumerate() {
+            for s in format_line(&re, line, i + 1, &opts).unwrap() {
+                expected.push_str(&s);
+                expected.push('\n');
+            }
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        assert_eq!(expected, "1:abc\n3:bb\n5:bab\n");
+    }
+
+    #[test]
+    fn test_as_program() {
+        let re = Regex::new("ab").unwrap();
+        assert_eq!(
+            re.as_program(),
+            &[
+                Instruction::Char('a'),
+                Instruction::Char('b'),
+                Instruction::Match
+            ]
+        );
+        assert_eq!(
+            re.as_program().len(),
+            estimate_code_size(&parse("ab").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_new_bytes() {
+        use crate::engine::{ParseError, RegexError};
+
+        let re = Regex::new_bytes("(あ|い)+".as_bytes()).unwrap();
+        assert!(re.is_match("ういあ").unwrap());
+
+        // "あ" の途中で切れたバイト列
+        let bytes = &"aあ".as_bytes()[..3];
+        let err = Regex::new_bytes(bytes).unwrap_err();
+        assert!(matches!(err, RegexError::Parse(ParseError::InvalidUtf8(1))));
+        assert_eq!(err.to_string(), "ParseError: invalid UTF-8: pos = 1");
+
+        // サロゲートをUTF-8で符号化したもの
+        assert!(Regex::new_bytes(b"\xed\xa0\x80").is_err());
+    }
+
+    #[test]
+    fn test_no_capture_path() {
+        let re = Regex::new("ab+").unwrap();
+        assert_eq!(re.find("xabb").unwrap(), Some(Match::new(1, 4)));
+        assert_eq!(
+            re.captures("xabb").unwrap(),
+            Some(vec![Some(Match::new(1, 4))])
+        );
+        let stats = re.stats();
+        assert!(stats.eval_calls > 0);
+        assert_eq!(stats.capture_eval_calls, 0);
+
+        let re = Regex::new("a(b+)").unwrap();
+        assert_eq!(re.find("xabb").unwrap(), Some(Match::new(1, 4)));
+        let stats = re.stats();
+        assert_eq!(stats.capture_eval_calls, stats.eval_calls);
+
+        // 最長一致モードでも同じ結果になる
+        let re = RegexBuilder::new("a|ab")
+            .longest_match(true)
+            .build()
+            .unwrap();
+        assert_eq!(re.find("xab").unwrap(), Some(Match::new(1, 3)));
+        assert_eq!(re.stats().capture_eval_calls, 0);
+    }
+
+    #[test]
+    fn test_match_result_field() {
+        let re = Regex::new("ERROR\t[^\n]+").unwrap();
+        let line = "2024-01-01\tERROR\tディスク\t残り0%";
+        let m = re.find_match(line).unwrap().unwrap();
+        assert_eq!((m.start(), m.end()), (11, line.chars().count()));
+        assert_eq!(m.as_str(), "ERROR\tディスク\t残り0%");
+        assert_eq!(m.field(1, '\t'), Some("ディスク"));
+        assert_eq!(m.field(2, '\t'), Some("残り0%"));
+        assert_eq!(m.field(3, '\t'), None);
+        assert_eq!(re.find_match("INFO\tok").unwrap(), None);
+    }
+
+    #[test]
+    fn test_regex_try_from() {
+        let re = Regex::try_from("a+").unwrap();
+        assert!(re.is_match("baa").unwrap());
+        assert!(Regex::try_from("a(").is_err());
+
+        let re: Regex = "b|c".parse().unwrap();
+        assert!(re.is_match("abc").unwrap());
+        assert!("*".parse::<Regex>().is_err());
+    }
+
+    #[test]
+    fn test_eval_end() {
+        let cases = [
+            ("a+", "aaa", Some(3)),
+            ("a+b", "aab", Some(3)),
+            ("ab*", "abbc", Some(3)),
+            ("b", "ab", None),
+        ];
+        for (expr, line, expected) in cases {
+            let code = get_code(&parse(expr).unwrap()).unwrap();
+            let line = line.chars().collect::<Vec<char>>();
+            for is_depth in [true, false] {
+                assert_eq!(
+                    evaluator::eval_end(&code, &line, is_depth).unwrap(),
+                    expected,
+                    "{expr}"
+                );
+                assert_eq!(
+                    evaluator::eval(&code, &line, is_depth).unwrap(),
+                    expected.is_some()
+                );
+            }
+            assert_eq!(
+                evaluator::eval_from(&code, &line, 0, None, None).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn test_unicode_property() {
+        let re = Regex::new("^\\p{N}+$").unwrap();
+        assert!(re.is_match("１２３").unwrap());
+        assert!(re.is_match("42").unwrap());
+        assert!(!re.is_match("12a").unwrap());
+
+        let re = Regex::new("\\p{Greek}+").unwrap();
+        assert_eq!(re.find("abc αβγ").unwrap(), Some(Match::new(4, 7)));
+        assert_eq!(
+            Regex::new("\\P{L}+").unwrap().find("ab12c").unwrap(),
+            Some(Match::new(2, 4))
+        );
+        assert_eq!(
+            Regex::new("\\p{Han}").unwrap().find("かな漢字").unwrap(),
+            Some(Match::new(2, 3))
+        );
+
+        assert!(Regex::new("\\p{Klingon}").is_err());
+        assert!(Regex::new("\\p{L").is_err());
+        assert!(Regex::new("\\pL").is_err());
+    }
+
+    #[test]
+    fn test_split_terminator() {
+        let re = Regex::new(",").unwrap();
+        assert_eq!(re.split_terminator("a,b,").unwrap(), vec!["a", "b"]);
+        assert_eq!(re.split_terminator("a,b").unwrap(), vec!["a", "b"]);
+        assert_eq!(re.split_terminator("a,,").unwrap(), vec!["a", ""]);
+        assert_eq!(re.split_terminator(",").unwrap(), vec![""]);
+        assert!(re.split_terminator("").unwrap().is_empty());
+
+        let re = Regex::new(";+").unwrap();
+        assert_eq!(re.split_terminator("x;;y;;;").unwrap(), vec!["x", "y"]);
+
+        // 評価中のエラーは空のベクタではなくエラーとして返す
+        let re = RegexBuilder::new("(a|b)*,")
+            .backtrack_limit(3)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            re.split_terminator("a,ababababab,"),
+            Err(EvalError::BacktrackLimitExceeded)
+        ));
+    }
+
+    #[test]
+    fn test_size_limit() {
+        use crate::engine::{CodeGenError, RegexError};
+
+        // 1,000,000,000命令以上に展開される
+        let err = Regex::new("((a{1000}){1000}){1000}").unwrap_err();
+        assert!(matches!(
+            err,
+            RegexError::CodeGen(CodeGenError::RepeatTooLarge)
+        ));
+        assert!(Regex::new("(((((a{100}){100}){100}){100}){100}){100}").is_err());
+
+        let re = Regex::new("(a{10}){10}").unwrap();
+        assert!(re.is_match("a".repeat(100)).unwrap());
+        assert!(!re.is_match("a".repeat(99)).unwrap());
+
+        let pattern = "(ab){3}";
+        let size = estimate_code_size(&parse(pattern).unwrap());
+        assert!(RegexBuilder::new(pattern).size_limit(size).build().is_ok());
+        assert!(RegexBuilder::new(pattern)
+            .size_limit(size - 1)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_scoped_flags() {
+        let re = Regex::new("^(?i:abc)DEF$").unwrap();
+        assert!(re.is_match("ABCDEF").unwrap());
+        assert!(re.is_match("aBcDEF").unwrap());
+        assert!(!re.is_match("abcdef").unwrap());
+
+        // キャプチャしないため，グループ番号はずれない
+        let re = Regex::new("(?i:a|b)(c)").unwrap();
+        assert_eq!(re.captures_len(), 2);
+        assert_eq!(
+            re.captures("xBc").unwrap(),
+            Some(vec![Some(Match::new(1, 3)), Some(Match::new(2, 3))])
+        );
+
+        // 入れ子とフラグの解除
+        let re = RegexBuilder::new("^x(?-i:y(?i:z))$")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match("XyZ").unwrap());
+        assert!(!re.is_match("XYz").unwrap());
+
+        // フラグのない (?:...) もキャプチャしないグループ
+        let re = Regex::new("(?:ab)+").unwrap();
+        assert_eq!(re.captures_len(), 1);
+        assert_eq!(re.find("ababx").unwrap(), Some(Match::new(0, 4)));
+    }
+
+    #[test]
+    fn test_multi_line_find_iter() {
+        let text = "foo bar\nbaz qux\n  indented\nlast";
+        let re = RegexBuilder::new("^\\w+").multi_line(true).build().unwrap();
+        let words = re
+            .find_iter(text)
+            .map(|m| text.chars().skip(m.start).take(m.len()).collect::<String>())
+            .collect::<Vec<_>>();
+        assert_eq!(words, vec!["foo", "baz", "last"]);
+
+        // (?m) でも同じ
+        let re = Regex::new("(?m)\\w+$").unwrap();
+        assert_eq!(re.find_iter(text).count(), 4);
+        let re = Regex::new("(?m)^baz qux$").unwrap();
+        assert!(re.is_match_iter(text.chars()).unwrap());
+        assert!(re.is_match(text).unwrap());
+
+        // 複数行モードでない場合は文字列の先頭のみ
+        let re = Regex::new("^\\w+").unwrap();
+        assert_eq!(re.find_iter(text).collect::<Vec<_>>(), vec![(0, 3)]);
+
+        assert!(Regex::new("(?m)^b").unwrap().compile_byte_dfa().is_none());
+        assert_eq!(
+            Regex::new("\\d+\\s\\D").unwrap().find("ab12 c").unwrap(),
+            Some(Match::new(2, 6))
+        );
+    }
+
+    #[test]
+    fn test_first_char_skip() {
+        let line = "x".repeat(1000) + "abc" + &"y".repeat(1000);
+        let re = Regex::new("ab+c").unwrap();
+        assert!(re.is_match(&line).unwrap());
+        assert_eq!(re.find(&line).unwrap(), Some(Match::new(1000, 1003)));
+        assert_eq!(re.stats().eval_calls, 2);
+
+        // 先頭の文字が1つに決まらない場合は全ての位置を試す
+        let re = Regex::new("[ab]bc").unwrap();
+        assert_eq!(re.find(&line).unwrap(), Some(Match::new(1000, 1003)));
+        assert_eq!(re.stats().eval_calls, 1001);
+
+        let re = Regex::new("a+b|ac").unwrap();
+        assert_eq!(
+            re.find_iter("xxaab..ac").collect::<Vec<_>>(),
+            vec![(2, 5), (7, 9)]
+        );
+        assert_eq!(re.stats().eval_calls, 2);
+        assert_eq!(Regex::new("b").unwrap().find("aaa").unwrap(), None);
+    }
+
+    #[test]
+    fn test_concat_alternate() {
+        let a = Regex::new("a+").unwrap();
+        let b = Regex::new("b+").unwrap();
+        let re = a.concat(&b).unwrap();
+        assert!(re.is_match("aaabbb").unwrap());
+        assert_eq!(re.find("xaaabbb").unwrap(), Some(Match::new(1, 7)));
+        assert!(!re.is_match("bbbaaa").unwrap());
+
+        let re = a.alternate(&b).unwrap();
+        assert_eq!(
+            re.find_iter("aaxbb").collect::<Vec<_>>(),
+            vec![(0, 2), (3, 5)]
+        );
+
+        // 後ろの正規表現のグループ番号は付け替えられる
+        let x = Regex::new("(?P<x>a)(b)").unwrap();
+        let y = Regex::new("(?P<y>c)").unwrap();
+        let re = x.concat(&y).unwrap();
+        assert_eq!(re.captures_len(), 4);
+        assert_eq!(re.name_to_index("y"), Some(3));
+        assert_eq!(
+            re.captures("abc").unwrap().unwrap(),
+            vec![
+                Some(Match::new(0, 3)),
+                Some(Match::new(0, 1)),
+                Some(Match::new(1, 2)),
+                Some(Match::new(2, 3))
+            ]
+        );
+
+        // 文字列の連結と異なり，演算子の優先順位に影響されない
+        let re = Regex::new("a|b")
+            .unwrap()
+            .concat(&Regex::new("c").unwrap())
+            .unwrap();
+        assert!(!re.is_match("a").unwrap());
+        assert!(re.is_match("ac").unwrap());
+    }
+
+    #[test]
+    fn test_match_reader_crlf() {
+        use crate::{match_reader, Options};
+
+        // Windowsで作成したファイルでも，$ が \r の手前の論理的な行末にマッチする
+        let re = Regex::new("b+$").unwrap();
+        let input = "ab\r\nbbc\r\ncb\r\n";
+        let opts = Options {
+            only_matching: true,
+            ..Options::default()
+        };
+
+        let mut out = Vec::new();
+        match_reader(&re, input.as_bytes(), &mut out, &opts).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "b\nb\n");
+    }
+
+    #[test]
+    fn test_replace_all_empty_match() {
+        let re = Regex::new("a*").unwrap();
+        assert_eq!(re.replace_all("bab", "-").unwrap(), "-b-b-");
+        assert_eq!(re.replace_all("", "-").unwrap(), "-");
+        assert_eq!(re.replace_all("aa", "-").unwrap(), "-");
+        assert_eq!(re.replace_all("baab", "[$0]").unwrap(), "[]b[aa]b[]");
+
+        let re = Regex::new("x?").unwrap();
+        assert_eq!(re.replace_all("あい", "-").unwrap(), "-あ-い-");
+    }
+
+    #[test]
+    fn test_captures_read() {
+        let re = Regex::new("(a+)(b)?|(c)").unwrap();
+        let mut slots = vec![Some(Match::new(9, 9)); 10];
+
+        assert!(re.captures_read(&mut slots, "xaab").unwrap());
+        assert_eq!(
+            slots,
+            vec![
+                Some(Match::new(1, 4)),
+                Some(Match::new(1, 3)),
+                Some(Match::new(3, 4)),
+                None
+            ]
+        );
+
+        // 前回の結果は残らない
+        assert!(re.captures_read(&mut slots, "c").unwrap());
+        assert_eq!(
+            slots,
+            vec![Some(Match::new(0, 1)), None, None, Some(Match::new(0, 1))]
+        );
+
+        assert!(!re.captures_read(&mut slots, "xyz").unwrap());
+        assert_eq!(slots, vec![None; 4]);
+
+        // キャプチャグループを含まない場合はグループ0のみ
+        let re = Regex::new("b+").unwrap();
+        assert!(re.captures_read(&mut slots, "abb").unwrap());
+        assert_eq!(slots, vec![Some(Match::new(1, 3))]);
+    }
+
+    #[test]
+    fn test_never_matches() {
+        let empty = "[^\\0-\u{10FFFF}]";
+        for expr in [
+            empty.to_string(),
+            format!("a{empty}"),
+            format!("({empty})+"),
+            format!("{empty}|[^\\0-\u{d7ff}\u{e000}-\u{10FFFF}]"),
+        ] {
+            let re = Regex::new(&expr).unwrap();
+            for line in ["", "a", "abc", "\0", "\u{10FFFF}"] {
+                assert!(!re.is_match(line).unwrap());
+                assert_eq!(re.find(line).unwrap(), None);
+            }
+            assert_eq!(re.stats().eval_calls, 0);
+            assert!(re.lint()[0].starts_with("pattern never matches"));
+        }
+
+        // 空のクラスを通らずにマッチできる場合や，全ての文字を覆わない場合は対象外
+        for expr in [
+            format!("a{empty}*"),
+            format!("a|{empty}"),
+            "[^\\0-\u{10FFFE}]".to_string(),
+        ] {
+            let re = Regex::new(&expr).unwrap();
+            assert!(re.lint().is_empty());
+        }
+        assert!(Regex::new(&format!("a{empty}*"))
+            .unwrap()
+            .is_match("a")
+            .unwrap());
+    }
+
+    #[test]
+    fn test_dump_ast() {
+        use crate::engine::dump_ast;
+
+        let dump = dump_ast("a(b|c)").unwrap();
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "Seq",
+                "  Char 'a'",
+                "  Capture 1",
+                "    Or",
+                "      Seq",
+                "        Char 'b'",
+                "      Seq",
+                "        Char 'c'",
+            ]
+        );
+
+        let dump = dump_ast("^[a-z]{2,}x*$").unwrap();
+        assert_eq!(
+            dump,
+            "Seq\n  Start\n  Repeat {2,}\n    Class [a-z]\n  Star\n    Char 'x'\n  End\n"
+        );
+        assert!(dump_ast("a(").is_err());
+    }
+
+    #[test]
+    fn test_prev_end_anchor() {
+        // 隙間が現れた時点で列挙が止まる
+        let re = Regex::new("\\G([0-9]+|[a-z]+) ?").unwrap();
+        let line = "12 ab 3c , de 4";
+        assert_eq!(
+            re.find_iter(line).collect::<Vec<_>>(),
+            vec![(0, 3), (3, 6), (6, 7), (7, 9)]
+        );
+
+        // \G がないパターンは隙間を読み飛ばす
+        let re = Regex::new("([0-9]+|[a-z]+) ?").unwrap();
+        assert_eq!(re.find_iter(line).count(), 6);
+
+        // find_fromでは開始位置で成り立つ
+        let re = Regex::new("\\Gb").unwrap();
+        let chars = "abab".chars().collect::<Vec<char>>();
+        assert_eq!(re.find_from(&chars, 0).unwrap(), None);
+        assert_eq!(re.find_from(&chars, 1).unwrap(), Some(Match::new(1, 2)));
+        assert_eq!(re.find_from(&chars, 2).unwrap(), None);
+
+        // 直前のマッチがない場合は ^ と同じ
+        assert!(!re.is_match("ab").unwrap());
+        assert!(re.is_match("ba").unwrap());
+        assert!(do_matching("\\Gab", "ab", false).unwrap());
+        assert!(Regex::new("\\Ga")
+            .unwrap()
+            .compile_byte_dfa()
+            .unwrap()
+            .is_match(b"ab"));
+        assert!(!Regex::new("\\Ga")
+            .unwrap()
+            .compile_byte_dfa()
+            .unwrap()
+            .is_match(b"ba"));
+    }
+
+    #[test]
+    fn test_recursion_limit() {
+        // デフォルトでは上限がなく，長い入力でもエラーにならない
+        let line = "a".repeat(2000) + "b";
+        for expr in ["a+", "a*b", "[0-9a-z]+", "(a)*b"] {
+            let re = Regex::new(expr).unwrap();
+            assert!(re.is_match(&line).unwrap(), "{expr}");
+            assert_eq!(re.find(&line).unwrap().unwrap().start, 0, "{expr}");
+        }
+        assert!(do_matching("a*b", &line, true).unwrap());
+
+        // 上限を設定すると，深い再帰はスタックオーバーフローせずにエラーとなる
+        let re = RegexBuilder::new("(a)*$")
+            .recursion_limit(100)
+            .build()
+            .unwrap();
+        let line = "a".repeat(500);
+        assert!(matches!(
+            re.captures(&line),
+            Err(EvalError::StackOverflowPrevented)
+        ));
+        let re = RegexBuilder::new("a*$")
+            .recursion_limit(100)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            re.is_match(&line),
+            Err(EvalError::StackOverflowPrevented)
+        ));
+
+        // 上限以内なら通常通りマッチする
+        let line = "a".repeat(20);
+        assert_eq!(re.find(&line).unwrap(), Some(Match::new(0, line.len())));
+
+        let re = RegexBuilder::new("(a)*$")
+            .recursion_limit(10)
+            .build()
+            .unwrap();
+        assert!(re.is_match("aa").unwrap());
+        assert!(matches!(
+            re.find("aaaaaaaaaa"),
+            Err(EvalError::StackOverflowPrevented)
+        ));
+    }
+
+    #[test]
+    fn test_splitn() {
+        let re = Regex::new(",").unwrap();
+        assert_eq!(re.splitn("a,b,c,d", 2).unwrap(), vec!["a", "b,c,d"]);
+        assert_eq!(re.splitn("a,b,c,d", 1).unwrap(), vec!["a,b,c,d"]);
+        assert_eq!(re.splitn("a,b,c,d", 0).unwrap(), Vec::<&str>::new());
+        assert_eq!(
+            re.splitn("a,b,c,d", 10).unwrap(),
+            re.split("a,b,c,d").unwrap()
+        );
+        assert_eq!(re.splitn("", 3).unwrap(), vec![""]);
+
+        // 文字列の位置はバイト単位で扱われる
+        let re = Regex::new("、+").unwrap();
+        assert_eq!(
+            re.splitn("あ、い、、う、え", 3).unwrap(),
+            vec!["あ", "い", "う、え"]
+        );
+
+        // 探索した区切りでのエラーのみを返す
+        let re = RegexBuilder::new("(a|b)*,")
+            .backtrack_limit(3)
+            .build()
+            .unwrap();
+        let line = ",ababababab,b";
+        assert_eq!(re.splitn(line, 2).unwrap(), vec!["", "ababababab,b"]);
+        assert!(matches!(
+            re.splitn(line, 3),
+            Err(EvalError::BacktrackLimitExceeded)
+        ));
+    }
+
+    #[test]
+    fn test_escape() {
+        use crate::engine::escape;
+
+        assert_eq!(escape("a+b"), "a\\+b");
+        let re = Regex::new(&escape("a+b")).unwrap();
+        assert!(re.is_match("a+b").unwrap());
+        assert!(!re.is_match("aab").unwrap());
+
+        // 全ての特別な文字を含む文字列もそのままマッチする
+        let line = "\\()|+*?{}[]^$-,あ";
+        let re = Regex::new(&escape(line)).unwrap();
+        assert_eq!(
+            re.find(format!("x{line}")).unwrap(),
+            Some(Match::new(1, line.chars().count() + 1))
+        );
+        assert_eq!(escape("abc"), "abc");
+    }
+
+    #[test]
+    fn test_is_match_os() {
+        use std::ffi::OsStr;
+
+        let re = Regex::new("^test_[a-z]+[.]txt$").unwrap();
+        assert!(re.is_match_os(OsStr::new("test_abc.txt")).unwrap());
+        assert!(!re.is_match_os(OsStr::new("test_abc.txt.bak")).unwrap());
+
+        // UTF-8として不正なファイル名は U+FFFD に置き換えてマッチングする
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let name = OsStr::from_bytes(b"test_\xffabc.txt");
+            assert!(!re.is_match_os(name).unwrap());
+            let re = Regex::new("^test_\u{FFFD}abc").unwrap();
+            assert!(re.is_match_os(name).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_find_iter_line_col() {
+        let re = Regex::new("b+").unwrap();
+        let text = "abc\nxxbbb\n\nb";
+        assert_eq!(
+            re.find_iter_line_col(text).unwrap(),
+            vec![((0, 1), (0, 2)), ((1, 2), (1, 5)), ((3, 0), (3, 1))]
+        );
+
+        // CRLFの '\r' は列に数えない
+        let text = "a\r\nあいb\r\n";
+        assert_eq!(re.find_iter_line_col(text).unwrap(), vec![((1, 2), (1, 3))]);
+
+        // 改行をまたぐマッチ
+        let re = Regex::new("b[^a]*c").unwrap();
+        assert_eq!(
+            re.find_iter_line_col("ab\r\n\r\ncd").unwrap(),
+            vec![((0, 1), (2, 1))]
+        );
+
+        // 改行の直前の空マッチは行末の列となる
+        let re = Regex::new("x*").unwrap();
+        assert_eq!(
+            re.find_iter_line_col("a\r\n").unwrap(),
+            vec![
+                ((0, 0), (0, 0)),
+                ((0, 1), (0, 1)),
+                ((0, 1), (0, 1)),
+                ((1, 0), (1, 0))
+            ]
+        );
+
+        // 評価中のエラーは途中までのマッチではなくエラーとして返す
+        let re = RegexBuilder::new("(a|b)*,")
+            .backtrack_limit(3)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            re.find_iter_line_col("a,\nababababab,b"),
+            Err(EvalError::BacktrackLimitExceeded)
+        ));
+    }
+
+    #[test]
+    fn test_greediness() {
+        use crate::engine::Greediness;
+
+        let find = |expr: &str, greediness| {
+            RegexBuilder::new(expr)
+                .greediness(greediness)
+                .build()
+                .unwrap()
+                .find("xaaa")
+                .unwrap()
+        };
+        assert_eq!(find("a+", Greediness::Greedy), Some(Match::new(1, 4)));
+        assert_eq!(find("a+", Greediness::Lazy), Some(Match::new(1, 2)));
+        assert_eq!(find("a+", Greediness::Longest), Some(Match::new(1, 4)));
+
+        // ? を付けた繰り返しはデフォルトと逆になる。最長一致モードでは影響しない
+        assert_eq!(find("a+?", Greediness::Greedy), Some(Match::new(1, 2)));
+        assert_eq!(find("a+?", Greediness::Lazy), Some(Match::new(1, 4)));
+        assert_eq!(find("a+?", Greediness::Longest), Some(Match::new(1, 4)));
+
+        // 各種の繰り返し
+        assert_eq!(find("a*?a", Greediness::Greedy), Some(Match::new(1, 2)));
+        assert_eq!(find("a??a", Greediness::Greedy), Some(Match::new(1, 2)));
+        assert_eq!(find("a{1,3}?", Greediness::Greedy), Some(Match::new(1, 2)));
+        assert_eq!(find("a{2,}?", Greediness::Greedy), Some(Match::new(1, 3)));
+        assert_eq!(find("a{1,3}", Greediness::Lazy), Some(Match::new(1, 2)));
+
+        // (?U) でも切り替えられる
+        let re = Regex::new("(?U)(a+)(a*?)").unwrap();
+        assert_eq!(
+            re.captures("aaa").unwrap().unwrap(),
+            vec![
+                Some(Match::new(0, 3)),
+                Some(Match::new(0, 1)),
+                Some(Match::new(1, 3))
+            ]
+        );
+
+        let re = Regex::new("<[a-z<>]+?>").unwrap();
+        assert_eq!(re.find("<a><b>").unwrap(), Some(Match::new(0, 3)));
+        let re = Regex::new("<[a-z<>]+>").unwrap();
+        assert_eq!(re.find("<a><b>").unwrap(), Some(Match::new(0, 6)));
+    }
+
+    #[test]
+    fn test_char_not() {
+        let code = get_code(&parse("[^a]+").unwrap()).unwrap();
+        assert_eq!(code[0], Instruction::CharNot('a'));
+
+        let re = Regex::new("[^a]+").unwrap();
+        assert_eq!(re.find("bcd").unwrap(), Some(Match::new(0, 3)));
+        assert_eq!(re.find("bcad").unwrap(), Some(Match::new(0, 2)));
+        assert_eq!(re.find("aaa").unwrap(), None);
+        assert!(!re.is_match("").unwrap());
+        assert!(do_matching("x[^a]", "xb", false).unwrap());
+        assert!(!do_matching("x[^a]$", "x", true).unwrap());
+        assert!(re.compile_byte_dfa().unwrap().is_match(b"ab"));
+
+        // 複数の文字や大文字と小文字を区別しない場合は通常の文字クラスのまま
+        for expr in ["[^ab]", "[^a-c]", "(?i)[^a]", "[a]"] {
+            let code = get_code(&parse(expr).unwrap()).unwrap();
+            assert!(matches!(code[0], Instruction::Class(_)));
+        }
+        assert!(!Regex::new("(?i)[^a]").unwrap().is_match("A").unwrap());
+    }
+
+    #[test]
+    fn test_as_ref_str() {
+        let expr = String::from("a(b|c)+");
+        let line = String::from("xabcb");
+
+        // do_matchingは文字列の先頭からのマッチを判定する
+        let head = String::from("abcb");
+        assert!(do_matching(&expr, head.clone(), true).unwrap());
+        assert!(do_matching(expr.clone(), &head, false).unwrap());
+        assert!(do_matching("a+", "aa", true).unwrap());
+
+        let re = Regex::new(&expr).unwrap();
+        assert!(re.is_match(line.clone()).unwrap());
+        assert!(re.is_match(&line).unwrap());
+        assert!(re.is_match(line.as_str()).unwrap());
+        assert_eq!(re.find(line.clone()).unwrap(), Some(Match::new(1, 5)));
+        assert_eq!(
+            re.captures(line).unwrap().unwrap()[1],
+            Some(Match::new(4, 5))
+        );
+    }
+
+    #[test]
+    fn test_codegen_rust() {
+        use crate::engine::{codegen_rust, CodeGenError, RegexError};
+
+        let src = codegen_rust("ab", "is_ab").unwrap();
+        assert!(src.starts_with("fn is_ab(input: &[char]) -> bool {\n"));
+        assert!(src.contains("Some(&c) if c == 'a' =>"));
+        assert!(src.contains("Some(&c) if c == 'b' =>"));
+        assert!(src.contains("return true;"));
+        assert!(src.trim_end().ends_with('}'));
+
+        // 否定の文字クラスや大文字小文字を区別しない文字も条件式になる
+        let src = codegen_rust("[^x](?i)y", "m").unwrap();
+        assert!(src.contains("c != 'x'"));
+        assert!(src.contains("'y'.to_lowercase()"));
+
+        // 不正な関数名とパースエラー
+        assert!(matches!(
+            codegen_rust("ab", "fn"),
+            Err(RegexError::CodeGen(CodeGenError::InvalidFunctionName(_)))
+        ));
+        assert!(matches!(
+            codegen_rust("ab", "1st"),
+            Err(RegexError::CodeGen(CodeGenError::InvalidFunctionName(_)))
+        ));
+        assert!(matches!(codegen_rust("a(", "m"), Err(RegexError::Parse(_))));
+    }
+
+    #[test]
+    fn test_codegen_rust_compiled() {
+        use crate::engine::codegen_rust;
+        use std::{fs, process::Command};
+
+        // (パターン, 入力, 期待する結果)
+        let cases = [
+            ("ab", vec![("xaby", true), ("a", false), ("", false)]),
+            ("a+b", vec![("aab", true), ("b", false), ("ba", false)]),
+            ("^(a|)+$", vec![("aa", true), ("ab", false)]),
+            ("\\bfoo\\b", vec![("a foo", true), ("afoo", false)]),
+            ("[^x](?i)y", vec![("aY", true), ("xy", false)]),
+            // 空文字列にマッチする繰り返しでも停止する
+            ("(a*)*b", vec![("ac", false), ("aab", true), ("", false)]),
+            ("(?:a*)*[bc][cd]", vec![("ab", false), ("abd", true)]),
+            ("(a*)*$", vec![("bc", true)]),
+        ];
+
+        // 生成した関数を呼び出し，結果を1行ずつ表示するプログラムを作る
+        let mut program = String::new();
+        let mut main = String::from("fn main() {\n");
+        let mut expected = String::new();
+        for (i, (expr, inputs)) in cases.iter().enumerate() {
+            program.push_str(&codegen_rust(expr, &format!("f{i}")).unwrap());
+            for (input, result) in inputs {
+                main.push_str(&format!(
+                    "    println!(\"{{}}\", f{i}(&{input:?}.chars().collect::<Vec<char>>()));\n"
+                ));
+                expected.push_str(&format!("{result}\n"));
+            }
+        }
+        main.push_str("}\n");
+        program.push_str(&main);
+
+        let dir = std::env::temp_dir().join(format!("regex_engine_codegen_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("main.rs");
+        let bin = dir.join("main");
+        fs::write(&src, program).unwrap();
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        let status = Command::new(rustc)
+            .args(["--edition", "2021", "-O", "-o"])
+            .arg(&bin)
+            .arg(&src)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let output = Command::new(&bin).output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_input_cache() {
+        let re = Regex::new("b+c").unwrap().with_input_cache(2);
+        assert!(re.is_match("abbc").unwrap());
+        let calls = re.stats().eval_calls;
+        assert!(calls > 0);
+
+        // 同じ入力は評価器を起動しない
+        assert!(re.is_match("abbc").unwrap());
+        assert_eq!(re.stats().eval_calls, calls);
+        assert_eq!(re.stats().cache_hits, 1);
+
+        // 容量を超えると最も長く使われていない入力が捨てられる
+        assert!(re.is_match("zbc").unwrap());
+        assert!(re.is_match("abbc").unwrap());
+        assert!(re.is_match("bcbc").unwrap()); // zbc が捨てられる
+        assert_eq!(re.stats().cache_hits, 2);
+        let calls = re.stats().eval_calls;
+        assert!(re.is_match("zbc").unwrap());
+        assert!(re.stats().eval_calls > calls);
+        assert!(re.is_match("bcbc").unwrap());
+        assert_eq!(re.stats().cache_hits, 3);
+
+        // 容量0ではキャッシュしない
+        let re = Regex::new("b+c").unwrap().with_input_cache(0);
+        assert!(re.is_match("abbc").unwrap());
+        assert!(re.is_match("abbc").unwrap());
+        assert_eq!(re.stats().cache_hits, 0);
+    }
+
+    #[test]
+    fn test_trailing_empty_alternative() {
+        // 末尾の '|' は空文字列にマッチする選択肢となる
+        let re = Regex::new("^(abc|)$").unwrap();
+        assert!(re.is_match("abc").unwrap());
+        assert!(re.is_match("").unwrap());
+        assert!(!re.is_match("ab").unwrap());
+
+        let re = Regex::new("abc|").unwrap();
+        assert_eq!(re.find("").unwrap(), Some(Match::new(0, 0)));
+        assert_eq!(re.find("abc").unwrap(), Some(Match::new(0, 3)));
+        assert_eq!(re.find("xyz").unwrap(), Some(Match::new(0, 0)));
+        assert!(do_matching("abc|", "", true).unwrap());
+        assert!(do_matching("abc|", "abc", false).unwrap());
+
+        assert!(matches!(
+            parse("abc|").unwrap(),
+            crate::engine::AST::Or(_, _)
+        ));
+    }
+
+    #[test]
+    fn test_validate_program() {
+        use crate::engine::validate_program;
+
+        for expr in ["abc", "a(b|c)*d", "(?i)[a-z]+?$", "x{2,4}|y", "abc|"] {
+            assert!(Regex::new(expr).unwrap().validate().is_ok(), "{expr}");
+        }
+
+        // jump先が命令列の範囲外
+        let code = [
+            Instruction::Char('a'),
+            Instruction::Jump(5),
+            Instruction::Match,
+        ];
+        assert!(matches!(
+            validate_program(&code),
+            Err(EvalError::InvalidPC(5))
+        ));
+
+        // 到達できない命令の遷移先も検査する
+        let code = [Instruction::Match, Instruction::Split(0, 9)];
+        assert!(matches!(
+            validate_program(&code),
+            Err(EvalError::InvalidPC(9))
+        ));
+
+        // Matchがないまま末尾を越える
+        let code = [Instruction::Char('a')];
+        assert!(matches!(
+            validate_program(&code),
+            Err(EvalError::InvalidPC(1))
+        ));
+        assert!(matches!(
+            validate_program(&[]),
+            Err(EvalError::InvalidPC(0))
+        ));
+
+        // Matchに到達できない
+        let code = [Instruction::Jump(0), Instruction::Match];
+        assert!(matches!(
+            validate_program(&code),
+            Err(EvalError::NoReachableMatch)
+        ));
+    }
+
+    #[test]
+    fn test_class_literal_meta() {
+        let re = Regex::new("^[.*]+$").unwrap();
+        assert!(re.is_match(".*.*").unwrap());
+        assert!(!re.is_match("ab").unwrap());
+        assert!(!re.is_match(".a*").unwrap());
+
+        let re = Regex::new("[.+]").unwrap();
+        assert!(re.is_match("+").unwrap());
+        assert!(!re.is_match("abc").unwrap());
+
+        let re = Regex::new("^[()?*+|{}$]+$").unwrap();
+        assert!(re.is_match("()?*+|{}$").unwrap());
+        assert!(!re.is_match("()a").unwrap());
+    }
+
+    #[test]
+    fn test_word_regexp() {
+        use crate::{format_line, parse_args};
+
+        let args = ["-w".to_string(), "cat".to_string()];
+        let (opts, rest) = parse_args(&args).unwrap();
+        assert!(opts.word_regexp);
+        let re = RegexBuilder::new(rest[0])
+            .whole_word(opts.word_regexp)
+            .build()
+            .unwrap();
+        assert_eq!(format_line(&re, "a cat", 1, &opts).unwrap(), ["a cat"]);
+        assert!(format_line(&re, "category", 1, &opts).unwrap().is_empty());
+        assert_eq!(
+            re.find("concat cat_ cat!").unwrap(),
+            Some(Match::new(12, 15))
+        );
+
+        // パターン中の \b
+        let re = Regex::new("\\bab").unwrap();
+        assert!(re.is_match("x ab").unwrap());
+        assert!(re.is_match("ab").unwrap());
+        assert!(!re.is_match("xab").unwrap());
+
+        // 選択はグループとして囲まれる
+        let re = RegexBuilder::new("ab|cd").whole_word(true).build().unwrap();
+        assert!(re.is_match("x cd").unwrap());
+        assert!(!re.is_match("abc").unwrap());
+    }
+
+    #[test]
+    fn test_find_iter_merged() {
+        let re = Regex::new("a").unwrap();
+        assert_eq!(re.find_iter("aa").collect::<Vec<_>>(), vec![(0, 1), (1, 2)]);
+        assert_eq!(re.find_iter_merged("aa").unwrap(), vec![(0, 2)]);
+        assert_eq!(
+            re.find_iter_merged("baabaaa").unwrap(),
+            vec![(1, 3), (4, 7)]
+        );
+        assert!(re.find_iter_merged("bcd").unwrap().is_empty());
+
+        // 空文字列へのマッチは隣接しない限り結合されない
+        let re = Regex::new("a*").unwrap();
+        assert_eq!(
+            re.find_iter_merged("bab").unwrap(),
+            vec![(0, 0), (1, 2), (3, 3)]
+        );
+
+        // 評価中のエラーは途中までのマッチではなくエラーとして返す
+        let re = RegexBuilder::new("(a|b)*,")
+            .backtrack_limit(3)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            re.find_iter_merged("a,ababababab,b"),
+            Err(EvalError::BacktrackLimitExceeded)
+        ));
+    }
+
+    #[test]
+    fn test_is_match_traced() {
+        // char a → char b → match
+        let re = Regex::new("ab").unwrap();
+        let (matched, trace) = re.is_match_traced("ab").unwrap();
+        assert!(matched);
+        assert_eq!(trace, vec![(0, 0), (1, 1), (2, 2)]);
+
+        // 失敗した開始位置の評価も記録される
+        let (matched, trace) = re.is_match_traced("aab").unwrap();
+        assert!(matched);
+        assert_eq!(trace, vec![(0, 0), (1, 1), (0, 1), (1, 2), (2, 3)]);
+
+        // 繰り返しのバックトラック
+        let re = Regex::new("a*ab").unwrap();
+        let (matched, trace) = re.is_match_traced("aab").unwrap();
+        assert!(matched);
+        assert_eq!(
+            trace.iter().map(|(pc, _)| *pc).collect::<Vec<_>>(),
+            vec![0, 1, 2, 0, 1, 2, 0, 1, 3, 3, 4, 5]
+        );
+
+        let (matched, trace) = Regex::new("ab").unwrap().is_match_traced("x").unwrap();
+        assert!(!matched);
+        assert!(trace.is_empty()); // 'a' で始まる位置がないため評価しない
+
+        // 空文字列にマッチする繰り返しの入れ子でも停止し，
+        // 各開始位置で同じ (pc, sp) を繰り返し記録しない
+        let re = Regex::new("(a*)*b").unwrap();
+        for (line, expected) in [("aab", true), ("aac", false)] {
+            let (matched, trace) = re.is_match_traced(line).unwrap();
+            assert_eq!(matched, expected);
+            let states = re.as_program().len() * (line.len() + 1);
+            assert!(trace.len() <= states * (line.len() + 1));
+        }
+    }
+
+    #[test]
+    fn test_question_greediness() {
+        // 最長一致の ? は要素にマッチする方を優先する
+        let code = get_code(&parse("ab?").unwrap()).unwrap();
+        assert_eq!(code[1], Instruction::Split(2, 3));
+        let re = Regex::new("ab?").unwrap();
+        assert_eq!(re.find("abb").unwrap(), Some(Match::new(0, 2)));
+
+        // 最短一致の ?? は要素を飛ばす方を優先する
+        let code = get_code(&parse("ab??").unwrap()).unwrap();
+        assert_eq!(code[1], Instruction::Split(3, 2));
+        let re = Regex::new("ab??").unwrap();
+        assert_eq!(re.find("abb").unwrap(), Some(Match::new(0, 1)));
+
+        // 後続の要素のためには最短一致でも要素にマッチする
+        let re = Regex::new("ab??c").unwrap();
+        assert_eq!(re.find("abc").unwrap(), Some(Match::new(0, 3)));
+        let re = Regex::new("(b?)(b*)").unwrap();
+        assert_eq!(
+            re.captures("bb").unwrap().unwrap()[1],
+            Some(Match::new(0, 1))
+        );
+        let re = Regex::new("(b??)(b*)").unwrap();
+        assert_eq!(
+            re.captures("bb").unwrap().unwrap()[1],
+            Some(Match::new(0, 0))
+        );
+    }
+
+    #[test]
+    fn test_basic_syntax() {
+        use crate::engine::{ParseError, RegexError, Syntax};
+
+        let basic = |expr: &str| RegexBuilder::new(expr).syntax(Syntax::Basic).build();
+
+        // . は Basic では使えないが，Extended では通常の文字となる
+        assert!(matches!(
+            basic("a.b"),
+            Err(RegexError::Parse(ParseError::UnsupportedSyntax(1, '.')))
+        ));
+        assert!(Regex::new("a.b").unwrap().is_match("a.b").unwrap());
+
+        for (expr, pos, c) in [
+            ("[ab]", 0, '['),
+            ("ab$", 2, '$'),
+            ("a{2}", 1, '{'),
+            ("(?i)a", 1, '?'),
+            ("a+?", 2, '?'),
+            ("\\d", 1, 'd'),
+        ] {
+            match basic(expr) {
+                Err(RegexError::Parse(ParseError::UnsupportedSyntax(p, e))) => {
+                    assert_eq!((p, e), (pos, c), "{expr}")
+                }
+                r => panic!("{expr}: {r:?}"),
+            }
+        }
+
+        // 最初期の構文とそのエスケープは使える
+        let re = basic("a(b|c)*d+e?\\+\\(").unwrap();
+        assert!(re.is_match("xabcdde+(").unwrap());
+        assert_eq!(
+            ParseError::UnsupportedSyntax(1, '.').to_string(),
+            "ParseError: unsupported syntax: pos = 1, char = '.'"
+        );
+    }
+
+    #[test]
+    fn test_is_match_batch() {
+        let re = RegexBuilder::new("(a|b)*c$")
+            .backtrack_limit(8)
+            .build()
+            .unwrap();
+        let long = "ab".repeat(20) + "c";
+        let mut lines = vec!["c", "abc", "xyz", "ca", long.as_str(), ""];
+        let more = (0..200)
+            .map(|i| "ab".repeat(i % 4) + "c")
+            .collect::<Vec<_>>();
+        lines.extend(more.iter().map(|s| s.as_str()));
+
+        let batch = re.is_match_batch(&lines);
+        assert_eq!(batch.len(), lines.len());
+        for (line, result) in lines.iter().zip(batch) {
+            match (re.is_match(line), result) {
+                (Ok(expected), Ok(actual)) => assert_eq!(expected, actual, "{line}"),
+                (
+                    Err(EvalError::BacktrackLimitExceeded),
+                    Err(EvalError::BacktrackLimitExceeded),
+                ) => {}
+                (expected, actual) => panic!("{line}: {expected:?} != {actual:?}"),
+            }
+        }
+        assert!(re.is_match_batch(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_allow_empty() {
+        use crate::engine::{ParseError, RegexError};
+
+        let re = RegexBuilder::new("").allow_empty(true).build().unwrap();
+        assert_eq!(re.as_program(), &[Instruction::Match]);
+        assert!(re.is_match("").unwrap());
+        assert!(re.is_match("abc").unwrap());
+        assert_eq!(re.find("abc").unwrap(), Some(Match::new(0, 0)));
+        assert_eq!(
+            re.find_iter("ab").collect::<Vec<_>>(),
+            vec![(0, 0), (1, 1), (2, 2)]
+        );
+        assert_eq!(re.captures_len(), 1);
+
+        // フラグ指定のみのパターンも空のパターンとなる
+        let re = RegexBuilder::new("(?i)").allow_empty(true).build().unwrap();
+        assert!(re.is_match("x").unwrap());
+
+        // デフォルトでは従来どおりエラー
+        assert!(matches!(
+            Regex::new(""),
+            Err(RegexError::Parse(ParseError::Empty))
+        ));
+        assert!(matches!(parse(""), Err(ParseError::Empty)));
+    }
+
+    #[test]
+    fn test_optional_group_none() {
+        let re = Regex::new("(a)?b").unwrap();
+        assert_eq!(
+            re.captures("b").unwrap().unwrap(),
+            vec![Some(Match::new(0, 1)), None]
+        );
+        assert_eq!(
+            re.captures("ab").unwrap().unwrap(),
+            vec![Some(Match::new(0, 2)), Some(Match::new(0, 1))]
+        );
+        // 失敗した開始位置で記録した位置は残らない
+        assert_eq!(
+            re.captures("xab").unwrap().unwrap()[1],
+            Some(Match::new(1, 2))
+        );
+        assert_eq!(re.captures("axb").unwrap().unwrap()[1], None);
+
+        // 最長一致モードでも同様
+        let re = RegexBuilder::new("(a)?b")
+            .longest_match(true)
+            .build()
+            .unwrap();
+        assert_eq!(re.captures("b").unwrap().unwrap()[1], None);
+
+        // 参加しなかった後続のグループもNone
+        let re = Regex::new("(a)?(a)?b").unwrap();
+        assert_eq!(
+            re.captures("ab").unwrap().unwrap(),
+            vec![Some(Match::new(0, 2)), Some(Match::new(0, 1)), None]
+        );
+    }
+
+    #[test]
+    fn test_replace_all_chars() {
+        let re = Regex::new("a+").unwrap();
+        let line = "baaad".chars().collect::<Vec<char>>();
+        assert_eq!(
+            re.replace_all_chars(&line, &['X']).unwrap(),
+            vec!['b', 'X', 'd']
+        );
+
+        // replace_all と同じ結果になる
+        for (expr, line) in [
+            ("a+", "xaayaz"),
+            ("a*", "bab"),
+            ("(é|b)", "äébé"),
+            ("z", "abc"),
+        ] {
+            let re = Regex::new(expr).unwrap();
+            let chars = line.chars().collect::<Vec<char>>();
+            let replaced = re.replace_all_chars(&chars, &['-', '-']).unwrap();
+            assert_eq!(
+                replaced.into_iter().collect::<String>(),
+                re.replace_all(line, "--").unwrap(),
+                "{expr}"
+            );
+        }
+
+        // 置換文字列の $ はそのまま挿入される
+        let re = Regex::new("(b)").unwrap();
+        assert_eq!(
+            re.replace_all_chars(&['a', 'b'], &['$', '1']).unwrap(),
+            vec!['a', '$', '1']
+        );
+    }
+
+    #[test]
+    fn test_format_error() {
+        use crate::{format_error, helper::DynError};
+
+        let err: DynError = Regex::new("a(b").unwrap_err().into();
+        assert_eq!(
+            format_error("a(b", err.as_ref()),
+            "ParseError: no right parenthesis\n  a(b\n     ^"
+        );
+
+        let err: DynError = Regex::new("ab\\q").unwrap_err().into();
+        assert_eq!(
+            format_error("ab\\q", err.as_ref()),
+            "ParseError: invalid escape: pos = 3, char = 'q'\n  ab\\q\n     ^"
+        );
+
+        // ParseErrorを直接保持する場合
+        let err: DynError = parse("*a").unwrap_err().into();
+        assert!(format_error("*a", err.as_ref()).ends_with("\n  *a\n  ^"));
+
+        // 位置を持たないエラーやパースエラー以外はメッセージのみ
+        let err: DynError = parse("").unwrap_err().into();
+        assert_eq!(
+            format_error("", err.as_ref()),
+            "ParseError: empty expression"
+        );
+        let err: DynError = "invalid arguments".into();
+        assert_eq!(format_error("a", err.as_ref()), "invalid arguments");
+    }
+
+    #[test]
+    fn test_match_n() {
+        use crate::engine::{codegen::get_code_at, validate_program};
+
+        // "ab" と "b+" を連結し，それぞれのマッチを番号で区別する
+        let mut code = Vec::new();
+        for (id, expr) in ["ab", "b+"].iter().enumerate() {
+            let inst = get_code_at(&parse(expr).unwrap(), code.len()).unwrap();
+            code.extend(inst.into_iter().map(|i| match i {
+                Instruction::Match => Instruction::MatchN(id),
+                i => i,
+            }));
+        }
+        assert_eq!(code[2], Instruction::MatchN(0));
+        assert_eq!(code[5], Instruction::MatchN(1));
+        assert_eq!(code[5].to_string(), "match_n 1");
+        assert!(validate_program(&code).is_ok());
+
+        let starts = [0, 3];
+        let set = |line: &str| evaluator::eval_thompson_set(&code, &starts, line.chars()).unwrap();
+        assert_eq!(set("xab"), vec![true, true]);
+        assert_eq!(set("bb"), vec![false, true]);
+        assert_eq!(set("a"), vec![false, false]);
+
+        // 単一の正規表現の評価器ではMatchと同じく扱う
+        let code = vec![Instruction::Char('a'), Instruction::MatchN(3)];
+        assert!(evaluator::eval(&code, &['a'], true).unwrap());
+        assert!(evaluator::eval(&code, &['a'], false).unwrap());
+
+        let set = RegexSet::new(&["ab", "b+", "c"]).unwrap();
+        assert_eq!(set.matches("xab").unwrap(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_astral_char() {
+        // BMP外の文字も1文字として扱う (このエンジンの . は通常の文字なので，任意の1文字には [^\n] を使う)
+        let re = Regex::new("^[^\n]$").unwrap();
+        assert!(re.is_match("😀").unwrap());
+        assert!(!re.is_match("😀😀").unwrap());
+        assert!(Regex::new("^[^x]$").unwrap().is_match("😀").unwrap());
+        assert!(Regex::new("^😀{2}$").unwrap().is_match("😀😀").unwrap());
+        assert!(Regex::new("^[😀-😂]+$").unwrap().is_match("😁😂").unwrap());
+
+        // 位置も文字単位
+        let re = Regex::new("(b)[^x]").unwrap();
+        assert_eq!(re.find("a😀b😀").unwrap(), Some(Match::new(2, 4)));
+        assert_eq!(re.find_bytes("a😀b😀").unwrap(), Some(Match::new(5, 10)));
+        assert!(do_matching("[^x]", "😀", true).unwrap());
+        assert!(do_matching("[^x]", "😀", false).unwrap());
+
+        // バイト単位のDFAを構築しても，文字単位のマッチングは変わらない
+        let re = Regex::new("^[^x]$").unwrap();
+        let dfa = re.compile_byte_dfa().unwrap();
+        assert!(!dfa.is_match("😀".as_bytes()));
+        assert!(re.is_match("😀").unwrap());
+        assert!(re.is_match_iter("😀".chars()).unwrap());
+    }
+
+    #[test]
+    fn test_match_sort() {
+        let re = Regex::new("[0-9]+").unwrap();
+        let mut spans = re.find_iter("a12b3").collect::<Vec<_>>();
+        spans.extend(re.find("x9").unwrap());
+        spans.push(Match::new(1, 2));
+        spans.sort();
+        assert_eq!(
+            spans,
+            vec![
+                Match::new(1, 2),
+                Match::new(1, 2),
+                Match::new(1, 3),
+                Match::new(4, 5)
+            ]
+        );
+        assert_eq!(spans[2].len(), 2);
+        assert_eq!(spans[2].range(), 1..3);
+        assert!(!spans[0].is_empty());
+        assert!(Match::new(3, 3).is_empty());
+
+        // 開始位置が同じ場合は終了位置で比較する
+        assert!(Match::new(0, 1) < Match::new(0, 2));
+        assert!(Match::new(0, 5) < Match::new(1, 2));
+        assert_eq!(<(usize, usize)>::from(Match::new(2, 4)), (2, 4));
+    }
+
+    #[test]
+    fn test_from_ast() {
+        use crate::engine::AST;
+
+        let re = Regex::from_ast(AST::Plus(Box::new(AST::Char('a')))).unwrap();
+        assert!(re.is_match("aaa").unwrap());
+        assert_eq!(re.find("baaa").unwrap(), Some(Match::new(1, 4)));
+        assert!(!re.is_match("bbb").unwrap());
+
+        // パースしたASTを加工してからコード生成する
+        let ast = parse("(b+)c").unwrap();
+        let re = Regex::from_ast(AST::Seq(vec![AST::Char('a'), ast])).unwrap();
+        assert_eq!(re.captures_len(), 2);
+        assert_eq!(
+            re.captures("xabbc").unwrap().unwrap()[1],
+            Some(Match::new(2, 4))
+        );
+        assert_eq!(
+            re.disassemble(),
+            Regex::new("a(b+)c").unwrap().disassemble()
+        );
+
+        // 上限が下限より小さい繰り返しはパニックせずにエラーとなる
+        let ast = AST::Repeat(Box::new(AST::Char('a')), 3, Some(1));
+        assert!(matches!(
+            Regex::from_ast(ast.clone()),
+            Err(crate::engine::CodeGenError::FailRepeat)
+        ));
+        assert!(matches!(
+            get_code(&ast),
+            Err(crate::engine::CodeGenError::FailRepeat)
+        ));
+        estimate_code_size(&ast);
+    }
+
+    #[test]
+    fn test_literal_fast_path() {
+        let re = Regex::new("cat").unwrap();
+        assert!(re.is_match("concatenate").unwrap());
+        assert!(!re.is_match("dog").unwrap());
+        assert_eq!(re.find("αβcat").unwrap(), Some(Match::new(2, 5)));
+        assert_eq!(re.find("ca").unwrap(), None);
+        assert_eq!(re.stats().eval_calls, 0);
+
+        // 固定文字列以外の命令を含む場合は評価器を使う
+        let re = Regex::new("(cat)").unwrap();
+        assert_eq!(re.find("a cat").unwrap(), Some(Match::new(2, 5)));
+        assert!(re.stats().eval_calls > 0);
+        let re = Regex::new("^cat").unwrap();
+        assert!(!re.is_match("a cat").unwrap());
+        assert!(re.stats().eval_calls > 0);
+    }
+
+    #[test]
+    fn test_assert_kind() {
+        // 各種類の条件を，直前と直後の文字の組み合わせで判定する
+        assert!(AssertKind::Start.holds(None, Some('a'), None));
+        assert!(!AssertKind::Start.holds(Some('\n'), Some('a'), None));
+        assert!(AssertKind::End.holds(Some('a'), None, None));
+        assert!(!AssertKind::End.holds(Some('a'), Some('\n'), None));
+        assert!(AssertKind::EndNewline.holds(Some('a'), Some('\n'), None));
+        assert!(!AssertKind::EndNewline.holds(Some('a'), Some('\n'), Some('b')));
+        assert!(AssertKind::LineStart.holds(Some('\n'), Some('a'), None));
+        assert!(AssertKind::LineStart.holds(None, None, None));
+        assert!(!AssertKind::LineStart.holds(Some('\r'), Some('a'), None));
+        assert!(AssertKind::LineEnd.holds(Some('a'), Some('\n'), None));
+        assert!(!AssertKind::LineEnd.holds(Some('a'), Some('b'), None));
+        assert!(AssertKind::PrevEnd.holds(None, Some('a'), None));
+        assert!(!AssertKind::PrevEnd.holds(Some('a'), Some('b'), None));
+        assert!(AssertKind::WordBoundary.holds(Some(' '), Some('a'), None));
+        assert!(AssertKind::WordBoundary.holds(Some('a'), None, None));
+        assert!(!AssertKind::WordBoundary.holds(Some('a'), Some('_'), None));
+
+        // アンカーは全て Assert 命令にコード生成される
+        for (expr, kind) in [
+            ("^a", AssertKind::Start),
+            ("a$", AssertKind::EndNewline),
+            ("(?m)^a", AssertKind::LineStart),
+            ("(?m)a$", AssertKind::LineEnd),
+            ("\\Ga", AssertKind::PrevEnd),
+            ("\\ba", AssertKind::WordBoundary),
+        ] {
+            let code = get_code(&parse(expr).unwrap()).unwrap();
+            assert!(code.contains(&Instruction::Assert(kind)), "{expr}");
+        }
+        assert_eq!(
+            Regex::new("^a\\b").unwrap().disassemble(),
+            "0000: assert_start\n0001: char a\n0002: assert_word_boundary\n0003: match\n"
+        );
+
+        // \G は直前のマッチの終了位置で成り立つ
+        let re = Regex::new("\\Ga").unwrap();
+        assert_eq!(
+            re.find_iter("aab").collect::<Vec<_>>(),
+            vec![(0, 1), (1, 2)]
+        );
+    }
+
+    #[test]
+    fn test_end_before_final_newline() {
+        // デフォルトでは $ は末尾の改行の直前でも成り立つ
+        let re = Regex::new("abc$").unwrap();
+        assert!(re.is_match("abc\n").unwrap());
+        assert!(re.is_match_iter("xabc\n".chars()).unwrap());
+        assert_eq!(re.find("xabc\n").unwrap(), Some(Match::new(1, 4)));
+        assert!(re.compile_byte_dfa().unwrap().is_match(b"abc\n"));
+        assert!(do_matching("abc$", "abc\n", false).unwrap());
+
+        // 末尾以外の改行や，2つ続く改行の前では成り立たない
+        assert!(!re.is_match("abc\nx").unwrap());
+        assert!(!re.is_match("abc\n\n").unwrap());
+        assert!(!re.is_match_iter("abc\n\n".chars()).unwrap());
+        assert!(!re.compile_byte_dfa().unwrap().is_match(b"abc\n\n"));
+
+        // strict_end では文字列の末尾のみ
+        let re = RegexBuilder::new("abc$").strict_end(true).build().unwrap();
+        assert!(!re.is_match("abc\n").unwrap());
+        assert!(!re.compile_byte_dfa().unwrap().is_match(b"abc\n"));
+        assert!(re.is_match("xabc").unwrap());
+        let re = re.concat(&Regex::new("(x)").unwrap()).unwrap();
+        assert!(re.disassemble().contains("assert_end\n"));
+        assert!(Regex::new("a$")
+            .unwrap()
+            .disassemble()
+            .contains("assert_end_newline\n"));
+    }
+
+    #[test]
+    fn test_explain_nonmatch() {
+        let re = Regex::new("abc").unwrap();
+        assert_eq!(
+            re.explain_nonmatch("abx"),
+            "no match: partial match \"ab\" at 0..2, then 0002: char c failed at 2 (found 'x')"
+        );
+        // 最も多くの文字を読み進めた開始位置を報告する
+        assert_eq!(
+            re.explain_nonmatch("axab"),
+            "no match: partial match \"ab\" at 2..4, then 0002: char c failed at 4 (found end of input)"
+        );
+        assert_eq!(re.explain_nonmatch("xabc"), "match at 1..4: \"abc\"");
+
+        // 文字を消費しない条件で止まる場合
+        let re = Regex::new("^a+$").unwrap();
+        assert_eq!(
+            re.explain_nonmatch("aab"),
+            "no match: partial match \"aa\" at 0..2, then 0003: assert_end_newline failed at 2 (found 'b')"
+        );
+        assert_eq!(
+            Regex::new("a").unwrap().explain_nonmatch(""),
+            "no match: partial match \"\" at 0..0, then 0000: char a failed at 0 (found end of input)"
+        );
+
+        // 空文字列にマッチする繰り返しの入れ子でも停止する
+        let re = Regex::new("(a*)*b").unwrap();
+        assert_eq!(
+            re.explain_nonmatch("aac"),
+            "no match: partial match \"aa\" at 0..2, then 0007: char b failed at 2 (found 'c')"
+        );
+        assert_eq!(re.explain_nonmatch("aab"), "match at 0..3: \"aab\"");
+    }
+
+    #[test]
+    fn test_rfind_iter() {
+        let re = Regex::new("a+").unwrap();
+        let mut it = re.rfind_iter("a bb a").unwrap();
+        assert_eq!(it.next(), Some(Match::new(5, 6)));
+        assert_eq!(it.next(), Some(Match::new(0, 1)));
+        assert_eq!(it.next(), None);
+
+        // find_iter と同じマッチを逆順に列挙する
+        let re = Regex::new("a*").unwrap();
+        let mut forward = re.find_iter("baab").collect::<Vec<_>>();
+        forward.reverse();
+        assert_eq!(re.rfind_iter("baab").unwrap().collect::<Vec<_>>(), forward);
+        assert_eq!(
+            re.rfind_iter("baab").unwrap().next_back(),
+            Some(Match::new(0, 0))
+        );
+        assert_eq!(
+            Regex::new("x").unwrap().rfind_iter("abc").unwrap().count(),
+            0
+        );
+
+        // 評価中のエラーは途中までのマッチではなくエラーとして返す
+        let re = RegexBuilder::new("(a|b)*,")
+            .backtrack_limit(3)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            re.rfind_iter("a,ababababab,b"),
+            Err(EvalError::BacktrackLimitExceeded)
+        ));
+    }
+
+    #[test]
+    fn test_ungreedy_flag() {
+        use crate::engine::Greediness;
+
+        // (?U) では繰り返しが最短一致を優先する
+        let re = Regex::new("(?U)a+").unwrap();
+        assert_eq!(re.find("baaa").unwrap(), Some(Match::new(1, 2)));
+        let re = Regex::new("(?U)<[a-z<>]+>").unwrap();
+        assert_eq!(re.find("<a><b>").unwrap(), Some(Match::new(0, 3)));
+
+        // ? を付けると最長一致に戻る
+        let re = Regex::new("(?U)a+?").unwrap();
+        assert_eq!(re.find("baaa").unwrap(), Some(Match::new(1, 4)));
+        let re = Regex::new("(?U)a{1,3}").unwrap();
+        assert_eq!(re.find("aaa").unwrap(), Some(Match::new(0, 1)));
+
+        // (?-U) やグループ内の指定で範囲を限定できる
+        let re = Regex::new("(?U)a+(?-U)b+").unwrap();
+        assert_eq!(re.find("aabbb").unwrap(), Some(Match::new(0, 5)));
+        let re = Regex::new("(?U:a+)a*").unwrap();
+        assert_eq!(
+            re.captures("aaa").unwrap().unwrap()[0],
+            Some(Match::new(0, 3))
+        );
+        let re = Regex::new("(?U:(a+))").unwrap();
+        assert_eq!(
+            re.captures("aaa").unwrap().unwrap()[1],
+            Some(Match::new(0, 1))
+        );
+
+        // ビルダーの Greediness::Lazy と同じ命令列になる
+        let lazy = RegexBuilder::new("a+b*")
+            .greediness(Greediness::Lazy)
+            .build()
+            .unwrap();
+        assert_eq!(
+            lazy.disassemble(),
+            Regex::new("(?U)a+b*").unwrap().disassemble()
+        );
+    }
+
+    #[test]
+    fn test_as_str() {
+        use crate::engine::AST;
+
+        let re = Regex::new("a+b").unwrap();
+        assert_eq!(re.as_str(), "a+b");
+        assert_eq!(format!("{re}"), "a+b");
+        assert_eq!(
+            RegexBuilder::new("(?i)x")
+                .multi_line(true)
+                .build()
+                .unwrap()
+                .as_str(),
+            "(?i)x"
+        );
+
+        // 結合した正規表現は，それぞれを (?:...) で囲んだ文字列となる
+        let re = Regex::new("(a)")
+            .unwrap()
+            .concat(&Regex::new("b|c").unwrap())
+            .unwrap();
+        assert_eq!(re.as_str(), "(?:(a))(?:b|c)");
+        assert_eq!(
+            Regex::new(re.as_str()).unwrap().disassemble(),
+            re.disassemble()
+        );
+        let re = Regex::new("a")
+            .unwrap()
+            .alternate(&Regex::new("b").unwrap())
+            .unwrap();
+        assert_eq!(re.as_str(), "(?:a)|(?:b)");
+
+        assert_eq!(Regex::from_ast(AST::Char('a')).unwrap().as_str(), "");
+    }
+
+    #[test]
+    fn test_control_escape() {
+        use crate::engine::{parse_all, ParseError, AST};
+
+        let re = Regex::new("a\\cIb").unwrap();
+        assert!(re.is_match("a\tb").unwrap());
+        assert!(!re.is_match("aIb").unwrap());
+        assert_eq!(parse("\\ca").unwrap(), AST::Seq(vec![AST::Char('\u{1}')]));
+        assert_eq!(parse("\\cZ").unwrap(), AST::Seq(vec![AST::Char('\u{1a}')]));
+        assert!(Regex::new("[\\cA-\\cZ]+")
+            .unwrap()
+            .is_match("\r\n")
+            .unwrap());
+
+        // 英字が続かない場合はエラー
+        assert!(matches!(
+            parse("a\\c1"),
+            Err(ParseError::InvalidEscape(2, 'c'))
+        ));
+        assert!(matches!(
+            parse("\\c"),
+            Err(ParseError::InvalidEscape(1, 'c'))
+        ));
+        assert_eq!(parse_all("\\c?").unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn test_cache_key() {
+        use std::collections::HashMap;
+
+        // フラグのみが異なる場合は異なるキーとなる
+        let plain = Regex::new("abc").unwrap();
+        let ci = RegexBuilder::new("abc")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert_ne!(plain.cache_key(), ci.cache_key());
+        let ml = RegexBuilder::new("abc").multi_line(true).build().unwrap();
+        assert_ne!(ci.cache_key(), ml.cache_key());
+
+        // 同じ設定ならば同じキーとなり，ビルド前のキーとも一致する
+        let builder = RegexBuilder::new("abc").case_insensitive(true).clone();
+        assert_eq!(builder.cache_key(), ci.cache_key());
+        assert_eq!(plain.cache_key(), Regex::new("abc").unwrap().cache_key());
+
+        // 結果に影響しないsize_limitは含まない
+        let limited = RegexBuilder::new("abc").size_limit(100).build().unwrap();
+        assert_eq!(plain.cache_key(), limited.cache_key());
+
+        // 結合したものは結合元のキーから作られる
+        let a = Regex::new("a").unwrap();
+        let b = Regex::new("b").unwrap();
+        let cat = a.concat(&b).unwrap();
+        let alt = a.alternate(&b).unwrap();
+        assert_ne!(cat.cache_key(), alt.cache_key());
+        assert_eq!(cat.cache_key(), a.concat(&b).unwrap().cache_key());
+
+        // キャッシュとして使う
+        let mut cache: HashMap<String, Regex> = HashMap::new();
+        cache.insert(plain.cache_key().to_string(), plain);
+        cache.insert(ci.cache_key().to_string(), ci);
+        assert_eq!(cache.len(), 2);
+        assert!(!cache[&RegexBuilder::new("abc").cache_key()]
+            .is_match("ABC")
+            .unwrap());
+        let key = RegexBuilder::new("abc").case_insensitive(true).cache_key();
+        assert!(cache[&key].is_match("ABC").unwrap());
+    }
+
+    #[test]
+    fn test_shortest_match_at() {
+        let line = "ab1234cd".chars().collect::<Vec<char>>();
+
+        // startの位置に固定した最短のマッチの終了位置
+        let re = Regex::new("[0-9]+").unwrap();
+        assert_eq!(re.shortest_match_at(&line, 2).unwrap(), Some(3));
+        assert_eq!(re.find_from(&line, 2).unwrap(), Some(Match::new(2, 6)));
+        assert_eq!(re.shortest_match_at(&line, 0).unwrap(), None);
+        assert_eq!(re.shortest_match_at(&line, 5).unwrap(), Some(6));
+
+        // 最長一致の指定によらず最短
+        let re = RegexBuilder::new("[0-9]{2,}")
+            .longest_match(true)
+            .build()
+            .unwrap();
+        assert_eq!(re.shortest_match_at(&line, 2).unwrap(), Some(4));
+        assert_eq!(re.shortest_match_at(&line, 5).unwrap(), None);
+
+        // 選択や位置の条件を含む場合
+        let re = Regex::new("12|1").unwrap();
+        assert_eq!(re.shortest_match_at(&line, 2).unwrap(), Some(3));
+        let re = Regex::new("[0-9]+$").unwrap();
+        assert_eq!(re.shortest_match_at(&line, 2).unwrap(), None);
+        let re = Regex::new("[a-z]+$").unwrap();
+        assert_eq!(re.shortest_match_at(&line, 6).unwrap(), Some(8));
+        let re = Regex::new("x*").unwrap();
+        assert_eq!(re.shortest_match_at(&line, 2).unwrap(), Some(2));
+        assert_eq!(re.shortest_match_at(&line, 8).unwrap(), Some(8));
+        assert_eq!(re.shortest_match_at(&line, 9).unwrap(), None);
+
+        // 優先順にパターンを試す字句解析
+        let patterns = ["[0-9]+", "[a-z]+", " +"]
+            .map(|p| RegexBuilder::new(p).longest_match(true).build().unwrap());
+        let input = "x1 yz".chars().collect::<Vec<char>>();
+        let mut pos = 0;
+        let mut kinds = Vec::new();
+        while pos < input.len() {
+            let (kind, end) = patterns
+                .iter()
+                .enumerate()
+                .find_map(|(i, re)| Some((i, re.shortest_match_at(&input, pos).unwrap()?)))
+                .unwrap();
+            kinds.push(kind);
+            pos = end;
+        }
+        assert_eq!(kinds, vec![1, 0, 2, 1, 1]);
+    }
+
+    #[test]
+    fn test_alternation_of() {
+        let re = Regex::alternation_of(&["foo", "bar", "baz"]).unwrap();
+        for kw in ["foo", "bar", "baz"] {
+            assert!(re.is_match(kw).unwrap());
+        }
+        for other in ["fo", "ba", "qux", "bax", ""] {
+            assert!(!re.is_match(other).unwrap());
+        }
+        assert_eq!(re.find("a baz").unwrap(), Some(Match::new(2, 5)));
+        assert_eq!(re.as_str(), "foo|bar|baz");
+
+        // 共通の接頭辞は1つの分岐にまとめられ，次の文字で分岐する
+        assert_eq!(
+            re.as_program()
+                .iter()
+                .filter(|i| **i == Instruction::Char('b'))
+                .count(),
+            1
+        );
+
+        // 接頭辞となる文字列があっても長い方を優先する
+        let re = Regex::alternation_of(&["for", "foreach", "in"]).unwrap();
+        assert_eq!(re.find("foreach").unwrap(), Some(Match::new(0, 7)));
+        assert_eq!(re.find("format").unwrap(), Some(Match::new(0, 3)));
+
+        // 特殊文字は固定文字列として扱う
+        let re = Regex::alternation_of(&["a+", "(b)"]).unwrap();
+        assert!(re.is_match("a+").unwrap());
+        assert!(!re.is_match("aa").unwrap());
+        assert_eq!(re.captures_len(), 1);
+        assert_eq!(re.as_str(), "a\\+|\\(b\\)");
+
+        // 多数のキーワード
+        let words = (0..500).map(|n| format!("kw{n}")).collect::<Vec<_>>();
+        let refs = words.iter().map(|w| w.as_str()).collect::<Vec<_>>();
+        let re = Regex::alternation_of(&refs).unwrap();
+        assert!(re.is_match("x kw499 y").unwrap());
+        assert_eq!(re.find("kw123").unwrap(), Some(Match::new(0, 5)));
+        assert!(!re.is_match("kw").unwrap());
+
+        // 空の集合は何にもマッチしない
+        let re = Regex::alternation_of(&[]).unwrap();
+        assert!(!re.is_match("foo").unwrap());
+        assert!(!re.is_match("").unwrap());
+    }
+
+    #[test]
+    fn test_deep_backtracking() {
+        // 分岐はヒープ上のスタックに積まれるため，長い入力でもスタックオーバーフローしない
+        let re = Regex::new("(a|b)*c").unwrap();
+        let line = "ab".repeat(50_000) + "c";
+        assert!(re.is_match(&line).unwrap());
+        assert_eq!(re.find(&line).unwrap(), Some(Match::new(0, line.len())));
+        let caps = re.captures(&line).unwrap().unwrap();
+        assert_eq!(caps[1], Some(Match::new(line.len() - 2, line.len() - 1)));
+        assert!(!re.is_match(&line[..line.len() - 1]).unwrap());
+
+        // 空文字列にマッチする繰り返しの入れ子でも停止する
+        let re = Regex::new("(a*)*b").unwrap();
+        assert!(re.is_match("aab").unwrap());
+        assert_eq!(re.find("aab").unwrap(), Some(Match::new(0, 3)));
+        // 空文字列のみを消費する繰り返しは行わないため，グループは最後に文字を消費した繰り返し
+        assert_eq!(
+            re.captures("aab").unwrap().unwrap(),
+            vec![Some(Match::new(0, 3)), Some(Match::new(0, 2))]
+        );
+        assert!(!re.is_match("aac").unwrap());
+        for is_depth in [true, false] {
+            assert!(do_matching("(a*)*b", "aab", is_depth).unwrap());
+            assert!(!do_matching("(a*)*b", "aac", is_depth).unwrap());
+        }
+        let re = Regex::new("(?:a*)*[bc][cd]").unwrap();
+        assert!(!re.is_match("ab").unwrap());
+        assert!(re.is_match("aabd").unwrap());
+        let re = RegexBuilder::new("(a|)+$")
+            .longest_match(true)
+            .build()
+            .unwrap();
+        assert_eq!(re.find("aa").unwrap(), Some(Match::new(0, 2)));
+    }
+
+    #[test]
+    fn test_matches_error() {
+        let re = RegexBuilder::new("(a|b)*,")
+            .backtrack_limit(3)
+            .build()
+            .unwrap();
+
+        // エラーの手前までのマッチを列挙してから終了し，エラーを保持する
+        let mut matches = re.find_iter(",ababababab,b");
+        assert_eq!(matches.next(), Some(Match::new(0, 1)));
+        assert!(matches.error().is_none());
+        assert_eq!(matches.next(), None);
+        assert!(matches!(
+            matches.error(),
+            Some(EvalError::BacktrackLimitExceeded)
+        ));
+        assert_eq!(matches.next(), None);
+
+        // 末尾まで走査して終了した場合はエラーを持たない
+        let mut matches = re.find_iter(",b,");
+        assert_eq!(matches.by_ref().count(), 2);
+        assert!(matches.error().is_none());
+    }
 }